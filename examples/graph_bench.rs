@@ -0,0 +1,89 @@
+//! Compares the neighborhood queries answered by SQL, as `Tag::qtrd`
+//! did before the graph cache, with the cached graph.
+//!
+//! ```sh
+//! cargo run --release --example graph_bench [TAGS] [LINKS_PER_TAG]
+//! ```
+//!
+//! A synthetic database with 10000 tags and 4 links per tag is built
+//! in the temporary directory.
+
+use std::time::Instant;
+
+use frictune::{db::crud::Database, Tag};
+use futures::executor::block_on;
+
+/// A tiny linear congruential generator, enough for a synthetic graph.
+struct Lcg(u64);
+
+impl Lcg {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % n as u64) as usize
+    }
+}
+
+fn build(db: &Database, tags: usize, links_per_tag: usize) {
+    let mut rng = Lcg(42);
+    let rows: Vec<_> = (0..tags).map(|t| format!("('tag{t}', 'https://example.com/{t}')")).collect();
+    let mut links = vec![];
+    for t in 0..tags {
+        for _ in 0..links_per_tag {
+            let to = rng.below(tags);
+            if to == t { continue }
+            links.push(format!("('tag{t}', 'tag{to}', {}, true)", (rng.below(1000) + 1) as f64 / 1000.0));
+        }
+    }
+    block_on(db.execute(&format!("BEGIN;\n\
+        INSERT INTO tags (tag_name, info) VALUES {};\n\
+        INSERT OR IGNORE INTO relationship (tag1, tag2, weight, is_origin) VALUES {};\n\
+        COMMIT;", rows.join(", "), links.join(", ")))).unwrap();
+}
+
+/// The former `Tag::qtrd`: one query for the neighbors, then two per
+/// neighbor.
+fn qtrd_by_sql(db: &Database, name: &str) -> Vec<(String, Option<String>, Option<f64>)> {
+    block_on(async {
+        let neighbors = db.read("relationship", &["tag2".to_string()], &format!("tag1 = '{name}'"), "ORDER BY weight")
+            .await.unwrap().get::<String>(0);
+        let mut result = vec![];
+        for tag in neighbors {
+            let desc = db.read("tags", &["info".to_string()], &format!("tag_name = '{tag}'"), "")
+                .await.unwrap().get::<Option<String>>(0).into_iter().flatten().next();
+            let weight = db.read("relationship", &["weight".to_string()], &format!("tag1 = '{name}' AND tag2 = '{tag}'"), "")
+                .await.unwrap().get::<f64>(0).first().copied();
+            result.push((tag, desc, weight));
+        }
+        result
+    })
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let tags: usize = args.next().and_then(|a| a.parse().ok()).unwrap_or(10000);
+    let links_per_tag: usize = args.next().and_then(|a| a.parse().ok()).unwrap_or(4);
+
+    let path = std::env::temp_dir().join("frictune_graph_bench.db");
+    let _ = std::fs::remove_file(&path);
+    let db = Database::sync_new(path.to_str().unwrap()).unwrap();
+    let start = Instant::now();
+    build(&db, tags, links_per_tag);
+    println!("built {tags} tags in {:?}", start.elapsed());
+
+    let names: Vec<String> = (0..tags).map(|t| format!("tag{t}")).collect();
+
+    let start = Instant::now();
+    let by_sql: usize = names.iter().map(|n| qtrd_by_sql(&db, n).len()).sum();
+    let sql_time = start.elapsed();
+    println!("sql:   {by_sql} neighbors in {sql_time:?}");
+
+    let start = Instant::now();
+    block_on(db.graph()).unwrap();
+    let load_time = start.elapsed();
+    let cached: usize = names.iter().map(|n| Tag::new(n).qtrd(&db).len()).sum();
+    let cached_time = start.elapsed();
+    println!("graph: {cached} neighbors in {cached_time:?} (of which loading {load_time:?})");
+    println!("speedup: {:.1}x", sql_time.as_secs_f64() / cached_time.as_secs_f64());
+
+    let _ = std::fs::remove_file(&path);
+}
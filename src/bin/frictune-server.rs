@@ -0,0 +1,41 @@
+//! Serves the tag database over HTTP on localhost, see
+//! [`frictune::server`].
+
+use clap::Parser;
+
+#[path = "../conf.rs"]
+mod conf;
+
+#[derive(Parser)]
+#[command(author, version, about = "Serve the tag database as a JSON API on 127.0.0.1")]
+struct Args {
+    /// the port to listen on
+    #[arg(short, long, default_value_t = frictune::server::DEFAULT_PORT)]
+    port: u16,
+    /// the database to serve instead of the configured one
+    #[arg(long)]
+    db: Option<String>,
+    /// how many requests are answered at once
+    #[arg(long, default_value_t = 4)]
+    workers: usize,
+}
+
+fn main() {
+    let args = Args::parse();
+    let settings = match conf::read_config() {
+        Ok(config) => config,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    };
+    frictune::normalize::set_policy(settings.normalize.clone());
+    let db_uri = args.db.unwrap_or(settings.db_uri);
+    let db = match frictune::db::crud::Database::sync_new(&db_uri) {
+        Ok(db) => db,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    };
+    let server = match frictune::server::Server::bind(args.port, db) {
+        Ok(server) => server,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    };
+    frictune::logger::print(&format!("serving {} on http://127.0.0.1:{}", db_uri, server.port()));
+    server.run(args.workers);
+}
@@ -0,0 +1,146 @@
+//! Topic clusters found by weighted label propagation.
+//!
+//! Only origin links are considered, since derived links just repeat
+//! them. Every tag starts in a cluster of its own, and then repeatedly
+//! joins the cluster its neighbors are linked to with the largest total
+//! weight, until no tag moves. Tags are visited in a fixed order and
+//! ties go to the smallest label, so the result is deterministic.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::{graph::TagGraph, cluster::clusters};
+//!
+//! let graph = TagGraph::from_parts(vec![], vec![
+//!     ("rust".into(), "cargo".into(), 0.9, true),
+//!     ("git".into(), "GitHub".into(), 0.9, true),
+//! ]);
+//! let found = clusters(&graph, 3);
+//! assert_eq!(found.len(), 2);
+//! assert_eq!(found[0].members.len(), 2);
+//! ```
+
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+
+use crate::{db::crud::{Database, DatabaseError}, graph::TagGraph};
+
+const ITERATIONS: usize = 100;
+
+pub struct Cluster {
+    pub id: usize,
+    pub members: Vec<String>,
+    /// The members with the heaviest links inside the cluster.
+    pub top: Vec<String>,
+}
+
+/// The cluster of every tag, by tag id. Clusters are numbered from 0
+/// by decreasing size.
+pub fn label_propagation(graph: &TagGraph) -> Vec<usize> {
+    let adjacency = graph.undirected(true);
+    let mut labels: Vec<usize> = (0..graph.len()).collect();
+    for _ in 0..ITERATIONS {
+        let mut changed = false;
+        for (id, neighbors) in adjacency.iter().enumerate() {
+            let mut votes: HashMap<usize, f64> = HashMap::new();
+            for (to, weight) in neighbors {
+                *votes.entry(labels[*to]).or_default() += weight;
+            }
+            let best = votes.into_iter()
+                .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                .map(|(label, _)| label);
+            if let Some(label) = best.filter(|label| *label != labels[id]) {
+                labels[id] = label;
+                changed = true;
+            }
+        }
+        if !changed { break }
+    }
+
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for label in labels.iter() { *sizes.entry(*label).or_default() += 1 }
+    let mut order: Vec<_> = sizes.into_iter().collect();
+    order.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let renumber: HashMap<usize, usize> = order.into_iter()
+        .enumerate()
+        .map(|(new, (old, _))| (old, new))
+        .collect();
+    labels.into_iter().map(|label| renumber[&label]).collect()
+}
+
+/// The clusters with their members, biggest first, and up to `top`
+/// strongest tags of each.
+pub fn clusters(graph: &TagGraph, top: usize) -> Vec<Cluster> {
+    let labels = label_propagation(graph);
+    let adjacency = graph.undirected(true);
+    let count = labels.iter().max().map(|m| m + 1).unwrap_or_default();
+    let mut members: Vec<Vec<(usize, f64)>> = vec![vec![]; count];
+    for (id, label) in labels.iter().enumerate() {
+        let inner: f64 = adjacency[id].iter()
+            .filter(|(to, _)| labels[*to] == *label)
+            .map(|(_, weight)| weight)
+            .sum();
+        members[*label].push((id, inner));
+    }
+    members.into_iter().enumerate().map(|(id, mut tags)| {
+        tags.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| graph.name(a.0).cmp(graph.name(b.0))));
+        Cluster {
+            id,
+            top: tags.iter().take(top).map(|(t, _)| graph.name(*t).to_string()).collect(),
+            members: tags.iter().map(|(t, _)| graph.name(*t).to_string()).collect(),
+        }
+    }).collect()
+}
+
+/// The cluster id of every tag name, e.g. to color a drawing.
+pub fn membership(graph: &TagGraph) -> HashMap<String, usize> {
+    label_propagation(graph).into_iter()
+        .enumerate()
+        .map(|(id, label)| (graph.name(id).to_string(), label))
+        .collect()
+}
+
+/// Loads the graph and finds its clusters.
+pub async fn cluster_tags(db: &Database, top: usize) -> Result<Vec<Cluster>, DatabaseError> {
+    Ok(clusters(&*db.graph().await?, top))
+}
+
+/// The sync version of [`cluster_tags`].
+pub fn cluster_sync(db: &Database, top: usize) -> Vec<Cluster> {
+    block_on(async {
+        cluster_tags(db, top).await
+            .unwrap_or_else(|e| { crate::logger::warn(e.to_string()); vec![] })
+    })
+}
+
+/// The sync version of [`membership`], loading the graph first.
+pub fn membership_sync(db: &Database) -> HashMap<String, usize> {
+    match block_on(db.graph()) {
+        Ok(graph) => membership(&graph),
+        Err(e) => { crate::logger::warn(e.to_string()); HashMap::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_triangles() {
+        let mut links = vec![];
+        for (a, b) in [("a", "b"), ("b", "c"), ("c", "a"), ("x", "y"), ("y", "z"), ("z", "x")] {
+            links.push((a.to_string(), b.to_string(), 0.9, true));
+        }
+        // a weak bridge and a derived link that must be ignored
+        links.push(("c".into(), "x".into(), 0.1, true));
+        links.push(("a".into(), "z".into(), 1.0, false));
+        let graph = TagGraph::from_parts(vec![], links);
+        let labels = label_propagation(&graph);
+        let label = |name| labels[graph.id(name).unwrap()];
+        assert_eq!(label("a"), label("b"));
+        assert_eq!(label("a"), label("c"));
+        assert_eq!(label("x"), label("z"));
+        assert_ne!(label("a"), label("x"));
+    }
+}
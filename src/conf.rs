@@ -3,10 +3,33 @@ use serde_derive::{Serialize, Deserialize};
 #[derive(Serialize, Deserialize)]
 pub struct Conf {
     pub db_uri: String,
+    /// How tag names are normalized, see [`frictune::normalize`].
+    #[serde(default)]
+    pub normalize: frictune::normalize::Policy,
+    /// The weights of the links `frictune import bookmarks` makes, see
+    /// [`frictune::export::bookmarks`].
+    #[serde(default)]
+    pub bookmarks: frictune::export::bookmarks::Weights,
+    /// How `frictune index` links the tags of notes, see
+    /// [`frictune::notes`].
+    #[serde(default)]
+    pub index: frictune::notes::Settings,
+    /// How `frictune import obsidian` links and describes notes, see
+    /// [`frictune::export::obsidian`].
+    #[serde(default)]
+    pub obsidian: frictune::export::obsidian::Settings,
 }
 
 impl ::std::default::Default for Conf {
-    fn default() -> Self { Conf { db_uri: "./tags.db".to_string() } }
+    fn default() -> Self {
+        Conf {
+            db_uri: "./tags.db".to_string(),
+            normalize: Default::default(),
+            bookmarks: Default::default(),
+            index: Default::default(),
+            obsidian: Default::default(),
+        }
+    }
 }
 
 /// Get a configuration value from the file.
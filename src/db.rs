@@ -6,13 +6,16 @@
 //! table has 'tag1', 'tag2', 'weight' and 'is_origin' fields. The 'weight' is a 0 ~ 1
 //! float number, and 'is_origin' is used in inner operations.
 //!
+//! The schema itself is owned by [`migrate`], and both backends bring a
+//! database up to date when it is opened with `Database::new`.
+//!
 //! # Example
 //!
 //! ```
 //! use frictune::db::crud::*;
 //!
 //! fn open_db() {
-//!     let mut db = Database::sync_new("./tags.db").unwrap();
+//!     let db = Database::sync_new("./tags.db").unwrap();
 //!     assert!(matches!(db, Database));
 //! }
 //! ```
@@ -26,6 +29,39 @@
 pub mod crud;
 
 pub mod gluesql;
+pub mod migrate;
+
+/// The tables the cached tag graph is read from.
+const GRAPH_TABLES: [&str; 2] = ["tags", "relationship"];
+
+/// What a write does to the cached tag graph, which is settled under
+/// the same lock as the write.
+pub(crate) enum GraphSync<'c> {
+    Keep,
+    Drop,
+    /// Applied only if the write succeeds.
+    Apply(Box<dyn FnOnce(&mut crate::graph::TagGraph) + Send + 'c>),
+}
+
+impl GraphSync<'_> {
+    /// Drops the cached graph for a write to one of its tables.
+    fn of(table: &str) -> Self {
+        if GRAPH_TABLES.contains(&table) { GraphSync::Drop } else { GraphSync::Keep }
+    }
+}
+
+/// Quotes a raw value as an SQL string literal, which is the form the
+/// CRUD functions expect in their `data` arguments.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// The raw value of a literal made by [`quote`].
+pub fn unquote(text: &str) -> String {
+    text.strip_prefix('\'').and_then(|text| text.strip_suffix('\''))
+        .unwrap_or(text)
+        .replace("''", "'")
+}
 #[cfg(target_arch = "wasm32")]
 pub mod crud {
     pub use crate::db::gluesql::*;
@@ -1,10 +1,31 @@
 
-use core::panic;
-use futures::executor::block_on;
-use sqlx::{SqliteConnection, Connection, migrate::MigrateDatabase, Executor};
+use std::{str::FromStr, sync::{Arc, RwLock}, time::Duration};
 
+use futures::{executor::block_on, lock::{Mutex, OwnedMutexGuard}};
+use sqlx::{Executor, Sqlite, SqlitePool, sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions}};
+
+use super::{GraphSync, migrate::{self, Dialect, Migration, MigrationStatus}};
+use crate::graph::TagGraph;
+
+/// A handle on an SQLite database. Clones share the connection pool,
+/// the write lock and the cached graph, so each thread or task can own
+/// one: reads run concurrently, writes one at a time.
+#[derive(Clone)]
 pub struct Database {
-    conn: SqliteConnection,
+    pool: SqlitePool,
+    writes: Arc<Mutex<()>>,
+    graph: Arc<RwLock<Option<Arc<TagGraph>>>>,
+    /// The transaction of a handle from [`Database::begin`].
+    tx: Option<Arc<Transaction>>,
+    /// Whether the handle joined the transaction of another one, and
+    /// so leaves the commit to it.
+    joined: bool,
+}
+
+/// An open transaction, with the write lock it holds until it ends.
+struct Transaction {
+    conn: Mutex<Option<sqlx::Transaction<'static, Sqlite>>>,
+    _write: OwnedMutexGuard<()>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -42,6 +63,10 @@ impl DatabaseResult {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn get<'r, T>(&'r self, index: usize) -> Vec<T>
     where
         T: sqlx::Decode<'r, sqlx::sqlite::Sqlite> + sqlx::Type<sqlx::sqlite::Sqlite>,
@@ -75,78 +100,251 @@ impl From<sqlx::Error> for DatabaseError {
 }
 
 impl Database {
+    pub const DIALECT: Dialect = Dialect::Sqlite;
+
     pub fn sync_new(db_url: &str) -> anyhow::Result<Database> {
         block_on(async { Database::new(db_url).await } )
     }
 
+    /// Opens the database, applies all pending migrations and brings
+    /// the search index in step, see [`crate::search::sync_index`].
     pub async fn new(db_url: &str) -> anyhow::Result<Database> {
-        if !sqlx::Sqlite::database_exists(&db_url).await? {
-            sqlx::Sqlite::create_database(&db_url).await?;
+        let db = Database::open(db_url).await?;
+        db.migrate(false).await?;
+        crate::search::sync_index(&db).await?;
+        Ok(db)
+    }
+
+    pub fn sync_open(db_url: &str) -> anyhow::Result<Database> {
+        block_on(async { Database::open(db_url).await } )
+    }
+
+    /// Opens (and creates if missing) the database without touching
+    /// its schema, for callers like `frictune migrate status`.
+    ///
+    /// The journal is switched to WAL, so that readers do not wait for
+    /// the writer. An in-memory database would be a different one on
+    /// every pooled connection, and is gone with its last one, so it
+    /// gets a single connection that is never closed.
+    pub async fn open(db_url: &str) -> anyhow::Result<Database> {
+        let options = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            // SQLite ignores the `relationship` foreign keys unless asked
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(5));
+        let in_memory = db_url.contains(":memory:");
+        let pool = if in_memory {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new().max_connections(8)
         }
+            .connect_with(options)
+            .await?;
+        Ok(Database {
+            pool,
+            writes: Arc::new(Mutex::new(())),
+            graph: Arc::new(RwLock::new(None)),
+            tx: None,
+            joined: false,
+        })
+    }
 
-        let mut conn = SqliteConnection::connect(&db_url).await?;
+    /// A handle whose statements run in one transaction, until
+    /// [`Database::commit`]; dropped without it, they are rolled back.
+    /// Other writers wait meanwhile, and the cached graph is left alone
+    /// until the commit. A handle already in a transaction joins it.
+    pub async fn begin(&self) -> Result<Database, DatabaseError> {
+        if self.tx.is_some() {
+            return Ok(Database { joined: true, ..self.clone() });
+        }
+        let write = self.writes.clone().lock_owned().await;
+        let conn = self.pool.begin().await?;
+        Ok(Database {
+            tx: Some(Arc::new(Transaction { conn: Mutex::new(Some(conn)), _write: write })),
+            ..self.clone()
+        })
+    }
 
-        let query = sqlx::query("CREATE TABLE IF NOT EXISTS tags
-        (
-            tag_name    TEXT PRIMARY KEY NOT NULL,
-            info     TEXT
-        );
-        CREATE TABLE IF NOT EXISTS relationship
-        (
-            tag1 TEXT NOT NULL,
-            tag2 TEXT NOT NULL,
-            weight REAL,
-            is_origin INTEGER DEFAULT false,
-            CONSTRAINT relationship_id1_fk FOREIGN KEY (tag1) REFERENCES tags(tag_name),
-            CONSTRAINT relationship_id2_fk FOREIGN KEY (tag2) REFERENCES tags(tag_name),
-            CONSTRAINT relation_pk PRIMARY KEY (tag1, tag2)
-        );");
-
-        match conn
-            .execute(query)
-            .await {
-            Ok(_) => Ok(Database { conn }),
-            Err(e) => { print!("err, {}", e); panic!() }
+    /// Commits the transaction of a handle from [`Database::begin`] and
+    /// drops the cached graph. Other handles have nothing to commit.
+    pub async fn commit(self) -> Result<(), DatabaseError> {
+        let Some(tx) = self.tx.as_ref().filter(|_| !self.joined) else { return Ok(()) };
+        if let Some(conn) = tx.conn.lock().await.take() {
+            conn.commit().await?;
+        }
+        self.invalidate_graph();
+        Ok(())
+    }
+
+    /// Runs statements in the transaction, or else on the pool under
+    /// the write lock, which is held until the cached graph is synced.
+    async fn write(&self, query: &str, sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
+        crate::logger::print(query);
+        match &self.tx {
+            Some(tx) => {
+                let mut conn = tx.conn.lock().await;
+                let conn = conn.as_mut().ok_or_else(Self::ended)?;
+                (&mut **conn).execute(query).await
+            }
+            None => {
+                let _write = self.writes.lock().await;
+                let res = self.pool.execute(query).await;
+                self.sync_graph(sync, res.is_ok());
+                res
+            }
+        }
+            .map(DatabaseResult::from)
+            .map_err(DatabaseError::from)
+    }
+
+    fn sync_graph(&self, sync: GraphSync<'_>, written: bool) {
+        match sync {
+            GraphSync::Keep => {},
+            GraphSync::Drop => self.invalidate_graph(),
+            GraphSync::Apply(change) => if written { self.update_graph(change) },
+        }
+    }
+
+    /// The error of a statement on a committed transaction.
+    fn ended() -> sqlx::Error {
+        sqlx::Error::Protocol("the transaction has ended".into())
+    }
+
+    /// Runs a raw batch of statements, and drops the cached graph.
+    pub async fn execute(&self, query: &str) -> Result<DatabaseResult, DatabaseError> {
+        self.write(query, GraphSync::Drop).await
+    }
+
+    /// The tag graph, loaded on first use and then kept in sync by the
+    /// `Tag` functions, see [`crate::graph`]. The snapshot returned
+    /// does not change; later writes replace the cached one. In a
+    /// transaction, it is read anew and not cached.
+    pub async fn graph(&self) -> Result<Arc<TagGraph>, DatabaseError> {
+        if self.tx.is_some() {
+            return Ok(Arc::new(TagGraph::load(self).await?));
+        }
+        if let Some(graph) = self.graph.read().unwrap().as_ref() {
+            return Ok(graph.clone());
         }
+        // no write may come between the load and the store, and the
+        // graph may have been loaded while waiting for them to finish
+        let _write = self.writes.lock().await;
+        if let Some(graph) = self.graph.read().unwrap().as_ref() {
+            return Ok(graph.clone());
+        }
+        let graph = Arc::new(TagGraph::load(self).await?);
+        *self.graph.write().unwrap() = Some(graph.clone());
+        Ok(graph)
+    }
+
+    /// Applies a change to the cached graph, if it is loaded.
+    fn update_graph(&self, change: impl FnOnce(&mut TagGraph)) {
+        if let Some(graph) = self.graph.write().unwrap().as_mut() {
+            change(Arc::make_mut(graph));
+        }
+    }
+
+    /// Drops the cached graph, for instance after writes of another
+    /// process.
+    pub fn invalidate_graph(&self) {
+        *self.graph.write().unwrap() = None;
+    }
+
+    /// The highest applied migration, or 0 for a database that
+    /// predates versioning. Nothing is written.
+    pub async fn schema_version(&self) -> Result<i64, DatabaseError> {
+        let table = self.read("sqlite_master", &["name".to_string()], "type = 'table' AND name = 'schema_version'", "").await?;
+        if table.is_empty() { return Ok(0) }
+        let versions = self.read("schema_version", &["version".to_string()], "true", "").await?;
+        Ok(versions.get::<i64>(0).into_iter().max().unwrap_or_default())
+    }
+
+    pub async fn migration_status(&self) -> Result<MigrationStatus, DatabaseError> {
+        Ok(MigrationStatus::new(self.schema_version().await?))
+    }
+
+    /// Applies the pending migrations, each in its own transaction, and
+    /// returns them. With `dry_run` nothing is written.
+    pub async fn migrate(&self, dry_run: bool) -> Result<Vec<&'static Migration>, DatabaseError> {
+        let pending = self.migration_status().await?.pending;
+        if dry_run { return Ok(pending) }
+        for migration in pending.iter() {
+            let tx = self.begin().await?;
+            tx.execute(&format!("{}\n{}\n{}",
+                migrate::VERSION_TABLE,
+                migration.statements(Self::DIALECT),
+                migration.record()
+            )).await?;
+            tx.commit().await?;
+        }
+        Ok(pending)
     }
     
-    pub async fn create(&mut self, table: &str, entry: &[String], data: &[String]) -> Result<DatabaseResult, DatabaseError> {
+    /// Inserts a row. A write to the tables of the cached graph drops
+    /// it, unless it goes through [`Database::create_with`].
+    pub async fn create(&self, table: &str, entry: &[String], data: &[String]) -> Result<DatabaseResult, DatabaseError> {
+        self.insert(table, entry, data, GraphSync::of(table)).await
+    }
+
+    /// [`Database::create`], with the `change` that brings the cached
+    /// graph along, applied before any other write or load.
+    pub async fn create_with(&self, table: &str, entry: &[String], data: &[String],
+            change: impl FnOnce(&mut TagGraph) + Send) -> Result<DatabaseResult, DatabaseError> {
+        self.insert(table, entry, data, GraphSync::Apply(Box::new(change))).await
+    }
+
+    async fn insert(&self, table: &str, entry: &[String], data: &[String], sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
         let query = format!("INSERT INTO {} ({}) VALUES({});", table, entry.join(", "), data.join(", "));
-        crate::logger::print(&query);
-        sqlx::query(
-            &query
-        ).execute(&mut self.conn)
-            .await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+        self.write(&query, sync).await
     }
 
-    pub async fn delete(&mut self, table: &str, entry: &str, data: &str) -> Result<DatabaseResult, DatabaseError> {
+    pub async fn delete(&self, table: &str, entry: &str, data: &str) -> Result<DatabaseResult, DatabaseError> {
         let query = format!("DELETE FROM {}
         WHERE {} = {}", table, entry, data);
-        crate::logger::print(&query);
-        sqlx::query(
-            &query
-        ).execute(&mut self.conn)
-            .await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+        self.write(&query, GraphSync::of(table)).await
     }
 
-    pub async fn read(&mut self, table: &str, entry: &[String], cond: &str, opts: &str) -> Result<DatabaseResult, DatabaseError> {
+    pub async fn read(&self, table: &str, entry: &[String], cond: &str, opts: &str) -> Result<DatabaseResult, DatabaseError> {
         let query = format!("SELECT {} FROM {}
         WHERE {} {}", entry.join(", "), table, cond, opts);
         crate::logger::print(&query);
-        sqlx::query(
-            &query
-        ).fetch_all(&mut self.conn)
-            .await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+        match &self.tx {
+            Some(tx) => {
+                let mut conn = tx.conn.lock().await;
+                let conn = conn.as_mut().ok_or_else(Self::ended)?;
+                sqlx::query(&query).fetch_all(&mut **conn).await
+            }
+            None => sqlx::query(&query).fetch_all(&self.pool).await,
+        }
+            .map(DatabaseResult::from)
+            .map_err(DatabaseError::from)
     }
     
-    pub async fn update(&mut self, table: &str, entry: &[String], data: &[String],
+    /// Inserts a row, or updates `updated_entry` of the row it collides
+    /// with where `cond` holds. Like [`Database::create`], a write to
+    /// the tables of the cached graph drops it.
+    pub async fn update(&self, table: &str, entry: &[String], data: &[String],
             updated_entry: &[String], updated_data: &[String], cond: &str) -> Result<DatabaseResult, DatabaseError> {
+        self.upsert(table, entry, data, updated_entry, updated_data, cond, GraphSync::of(table)).await
+    }
+
+    /// [`Database::update`], with the `change` that brings the cached
+    /// graph along, applied before any other write or load.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_with(&self, table: &str, entry: &[String], data: &[String],
+            updated_entry: &[String], updated_data: &[String], cond: &str,
+            change: impl FnOnce(&mut TagGraph) + Send) -> Result<DatabaseResult, DatabaseError> {
+        self.upsert(table, entry, data, updated_entry, updated_data, cond, GraphSync::Apply(Box::new(change))).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn upsert(&self, table: &str, entry: &[String], data: &[String],
+            updated_entry: &[String], updated_data: &[String], cond: &str, sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
         let query = format!("INSERT INTO {} ({}) VALUES ({})
         ON CONFLICT DO
         UPDATE SET {}
@@ -158,12 +356,6 @@ impl Database {
                 format!("{} = {}", e, d)).collect::<Vec<_>>().join(", "),
             cond
         );
-        crate::logger::print(&query);
-        sqlx::query(
-            &query
-        ).execute(&mut self.conn)
-            .await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+        self.write(&query, sync).await
     }
 }
\ No newline at end of file
@@ -1,11 +1,18 @@
-use std::io::Read;
+use std::sync::{Arc, RwLock};
 
+use futures::{executor::block_on, lock::Mutex};
 use gluesql::{prelude::{MemoryStorage, Glue, Payload, Row, DataType, Value}, core::{executor::ValidateError, result}};
 
-use crate::logger;
+use super::{GraphSync, migrate::{self, Dialect, Migration, MigrationStatus}};
+use crate::graph::TagGraph;
 
+/// A handle on a GlueSQL database. Clones share the storage and the
+/// cached graph. `MemoryStorage` needs exclusive access even to read,
+/// so every statement holds the lock.
+#[derive(Clone)]
 pub struct Database {
-    conn: Glue<MemoryStorage>,
+    conn: Arc<Mutex<Glue<MemoryStorage>>>,
+    graph: Arc<RwLock<Option<Arc<TagGraph>>>>,
 }
 
 /// Database error type wrapper
@@ -33,6 +40,30 @@ impl GlueType for i32 {
     }
 }
 
+impl GlueType for i64 {
+    fn get_glue_type() -> DataType {
+        DataType::Int
+    }
+    fn get_content(thing: Value) -> Self {
+        if let Value::I64(content) = thing {
+            content
+        }
+        else { Self::default() }
+    }
+}
+
+impl GlueType for f64 {
+    fn get_glue_type() -> DataType {
+        DataType::Float
+    }
+    fn get_content(thing: Value) -> Self {
+        if let Value::F64(content) = thing {
+            content
+        }
+        else { Self::default() }
+    }
+}
+
 impl GlueType for f32 {
     fn get_glue_type() -> DataType {
         DataType::Float
@@ -57,6 +88,30 @@ impl GlueType for String {
     }
 }
 
+impl GlueType for Option<String> {
+    fn get_glue_type() -> DataType {
+        DataType::Text
+    }
+    fn get_content(thing: Value) -> Self {
+        if let Value::Str(content) = thing {
+            Some(content)
+        }
+        else { None }
+    }
+}
+
+impl GlueType for Option<f64> {
+    fn get_glue_type() -> DataType {
+        DataType::Float
+    }
+    fn get_content(thing: Value) -> Self {
+        if let Value::F64(content) = thing {
+            Some(content)
+        }
+        else { None }
+    }
+}
+
 impl GlueType for bool {
     fn get_glue_type() -> DataType {
         DataType::Boolean
@@ -98,6 +153,10 @@ impl DatabaseResult {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn get<T: GlueType>(&self, index: usize) -> Vec<T>
     {
         match self {
@@ -124,7 +183,7 @@ impl From<Vec<Payload>> for DatabaseResult {
             }
         );
         // TODO: improve error hint
-        if rows.len() == 0 { DatabaseResult::Success(
+        if rows.is_empty() { DatabaseResult::Success(
             "success".to_string()
         ) } else { DatabaseResult::Things(rows) }
     }
@@ -141,29 +200,13 @@ impl From<gluesql::core::result::Error> for DatabaseError {
 }
 
 impl Database {
-    fn init_command() -> &'static str {
-        "CREATE TABLE IF NOT EXISTS tags
-        (
-            tag_name    TEXT PRIMARY KEY NOT NULL,
-            info     TEXT DEFAULT ''
-        );
-        CREATE TABLE IF NOT EXISTS relationship
-        (
-            tag1 TEXT NOT NULL,
-            tag2 TEXT NOT NULL,
-            weight DECIMAL,
-            is_origin BOOLEAN DEFAULT false,
-            CONSTRAINT relationship_id1_fk FOREIGN KEY (tag1) REFERENCES tags(tag_name),
-            CONSTRAINT relationship_id2_fk FOREIGN KEY (tag2) REFERENCES tags(tag_name),
-            CONSTRAINT relation_pk PRIMARY KEY (tag1, tag2)
-        );"
-    }
+    pub const DIALECT: Dialect = Dialect::GlueSql;
 
     pub fn sync_new(db_url: &str) -> anyhow::Result<Database> {
         cfg_if::cfg_if! {
             if #[cfg(not(target_arch = "wasm32"))] {
                 fn get_memory(db_url: &str) -> anyhow::Result<MemoryStorage> {
-                    let mut f = std::fs::OpenOptions::new().read(true).write(true).create(true).open(db_url)?;
+                    let mut f = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(db_url)?;
                     let mut buf = vec![];
                     std::io::Read::read_to_end(&mut f, &mut buf)?;
                     if !buf.is_empty()
@@ -172,13 +215,11 @@ impl Database {
                 }
                 let conn = match get_memory(db_url) {
                     Ok(ms) => Glue::new(ms),
-                    Err(_) =>  {
-                        let mut conn = Glue::new(MemoryStorage::default());
-                        conn.execute(Self::init_command())?;
-                        conn
-                    }
+                    Err(_) => Glue::new(MemoryStorage::default()),
                 };
-                Ok(Database { conn })
+                let db = Database::from_glue(conn);
+                block_on(async { db.migrate(false).await })?;
+                Ok(db)
             }
             else {
                 anyhow::bail!("wasm mode")
@@ -189,8 +230,8 @@ impl Database {
     pub fn save(&self, db_url: &str) -> anyhow::Result<()> {
         cfg_if::cfg_if! {
             if #[cfg(not(target_arch = "wasm32"))] {
-                let mut f = std::fs::OpenOptions::new().write(true).create(true).open(db_url)?;
-                let storage = match self.conn.storage.clone()
+                let mut f = std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(db_url)?;
+                let storage = match block_on(self.conn.lock()).storage.clone()
                 { Some(s) => s, None => anyhow::bail!("no storage")};
                 let buf = bincode::serialize(&storage)?;
                 std::io::Write::write_all(&mut f, &buf)?;
@@ -203,44 +244,182 @@ impl Database {
     }
 
     pub fn deser_new(content: &[u8]) -> anyhow::Result<Database> {
-        let storage: MemoryStorage = bincode::deserialize(&content[..])?;
-        let mut conn = Glue::new(storage);
-        conn.execute(Self::init_command())?;
-        Ok(Database { conn })
+        let storage: MemoryStorage = bincode::deserialize(content)?;
+        let db = Database::from_glue(Glue::new(storage));
+        block_on(async { db.migrate(false).await })?;
+        Ok(db)
     }
 
-    pub async fn create(&mut self, table: &str, entry: &[String], data: &[String]) -> Result<DatabaseResult, DatabaseError> {
-        let query = &format!("INSERT INTO {} ({}) VALUES({});", table, entry.join(", "), data.join(", "));
-        crate::logger::print(&query);
-        self.conn.execute_async(
+    fn from_glue(glue: Glue<MemoryStorage>) -> Database {
+        Database { conn: Arc::new(Mutex::new(glue)), graph: Arc::new(RwLock::new(None)) }
+    }
+
+    /// The handle itself, for code shared with the native `Database`:
+    /// `MemoryStorage` has no transactions, so the statements until
+    /// [`Database::commit`] are not atomic.
+    pub async fn begin(&self) -> Result<Database, DatabaseError> {
+        Ok(self.clone())
+    }
+
+    /// Drops the cached graph, as the native `Database` does when it
+    /// commits.
+    pub async fn commit(self) -> Result<(), DatabaseError> {
+        self.invalidate_graph();
+        Ok(())
+    }
+
+    /// Runs statements, holding the storage until the cached graph is
+    /// synced.
+    async fn write(&self, query: &str, sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
+        crate::logger::print(query);
+        let mut conn = self.conn.lock().await;
+        let res = conn.execute_async(
             query
         ).await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+            .map(DatabaseResult::from)
+            .map_err(DatabaseError::from);
+        self.sync_graph(sync, res.is_ok());
+        res
+    }
+
+    fn sync_graph(&self, sync: GraphSync<'_>, written: bool) {
+        match sync {
+            GraphSync::Keep => {},
+            GraphSync::Drop => self.invalidate_graph(),
+            GraphSync::Apply(change) => if written { self.update_graph(change) },
+        }
+    }
+
+    /// Runs a raw batch of statements, and drops the cached graph.
+    pub async fn execute(&self, query: &str) -> Result<DatabaseResult, DatabaseError> {
+        self.write(query, GraphSync::Drop).await
+    }
+
+    /// The tag graph, loaded on first use and then kept in sync by the
+    /// `Tag` functions, see [`crate::graph`]. The snapshot returned
+    /// does not change; later writes replace the cached one.
+    pub async fn graph(&self) -> Result<Arc<TagGraph>, DatabaseError> {
+        if let Some(graph) = self.graph.read().unwrap().as_ref() {
+            return Ok(graph.clone());
+        }
+        // not `TagGraph::load`, which takes the native `Database`; the
+        // storage is held so that no write comes before the store
+        let mut conn = self.conn.lock().await;
+        if let Some(graph) = self.graph.read().unwrap().as_ref() {
+            return Ok(graph.clone());
+        }
+        let tags = DatabaseResult::from(conn.execute_async("SELECT tag_name, info FROM tags").await?);
+        let links = DatabaseResult::from(conn.execute_async("SELECT tag1, tag2, weight, is_origin FROM relationship").await?);
+        let graph = Arc::new(TagGraph::from_parts(
+            tags.get::<String>(0).into_iter().zip(tags.get::<Option<String>>(1)).collect(),
+            itertools::izip!(
+                links.get::<String>(0),
+                links.get::<String>(1),
+                links.get::<f64>(2),
+                links.get::<bool>(3)
+            ).collect(),
+        ));
+        *self.graph.write().unwrap() = Some(graph.clone());
+        Ok(graph)
+    }
+
+    /// Applies a change to the cached graph, if it is loaded.
+    fn update_graph(&self, change: impl FnOnce(&mut TagGraph)) {
+        if let Some(graph) = self.graph.write().unwrap().as_mut() {
+            change(Arc::make_mut(graph));
+        }
+    }
+
+    /// Drops the cached graph, for instance after writes of another
+    /// process.
+    pub fn invalidate_graph(&self) {
+        *self.graph.write().unwrap() = None;
+    }
+
+    /// The highest applied migration, or 0 for a database that
+    /// predates versioning. Nothing is written.
+    pub async fn schema_version(&self) -> Result<i64, DatabaseError> {
+        let exists = self.conn.lock().await.storage.as_ref()
+            .is_some_and(|storage| storage.items.contains_key("schema_version"));
+        if !exists { return Ok(0) }
+        let versions = self.read("schema_version", &["version".to_string()], "true", "").await?;
+        Ok(versions.get::<i64>(0).into_iter().max().unwrap_or_default())
+    }
+
+    pub async fn migration_status(&self) -> Result<MigrationStatus, DatabaseError> {
+        Ok(MigrationStatus::new(self.schema_version().await?))
+    }
+
+    /// Applies the pending migrations and returns them. `MemoryStorage`
+    /// has no transactions, so a failed migration can leave a partial
+    /// change behind in memory; it is only persisted by [`Database::save`].
+    pub async fn migrate(&self, dry_run: bool) -> Result<Vec<&'static Migration>, DatabaseError> {
+        let pending = self.migration_status().await?.pending;
+        if dry_run || pending.is_empty() { return Ok(pending) }
+        self.execute(migrate::VERSION_TABLE).await?;
+        for migration in pending.iter() {
+            let statements = migration.statements(Self::DIALECT);
+            if !statements.trim().is_empty() {
+                self.execute(statements).await?;
+            }
+            self.execute(&migration.record()).await?;
+        }
+        Ok(pending)
+    }
+
+    /// Inserts a row. A write to the tables of the cached graph drops
+    /// it, unless it goes through [`Database::create_with`].
+    pub async fn create(&self, table: &str, entry: &[String], data: &[String]) -> Result<DatabaseResult, DatabaseError> {
+        self.insert(table, entry, data, GraphSync::of(table)).await
+    }
+
+    /// [`Database::create`], with the `change` that brings the cached
+    /// graph along, applied before any other statement.
+    pub async fn create_with(&self, table: &str, entry: &[String], data: &[String],
+            change: impl FnOnce(&mut TagGraph) + Send) -> Result<DatabaseResult, DatabaseError> {
+        self.insert(table, entry, data, GraphSync::Apply(Box::new(change))).await
+    }
+
+    async fn insert(&self, table: &str, entry: &[String], data: &[String], sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
+        let query = &format!("INSERT INTO {} ({}) VALUES({});", table, entry.join(", "), data.join(", "));
+        self.write(query, sync).await
     }
 
-    pub async fn delete(&mut self, table: &str, entry: &str, data: &str) -> Result<DatabaseResult, DatabaseError> {
+    pub async fn delete(&self, table: &str, entry: &str, data: &str) -> Result<DatabaseResult, DatabaseError> {
         let query = &format!("DELETE FROM {} WHERE {} = {};", table, entry, data);
-        crate::logger::print(&query);
-        self.conn.execute_async(
-            query
-        ).await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+        self.write(query, GraphSync::of(table)).await
     }
 
-    pub async fn read(&mut self, table: &str, entry: &[String], cond: &str, opts: &str) -> Result<DatabaseResult, DatabaseError> {
+    pub async fn read(&self, table: &str, entry: &[String], cond: &str, opts: &str) -> Result<DatabaseResult, DatabaseError> {
         let query = &format!("SELECT {} FROM {} WHERE {} {}", entry.join(", "), table, cond, opts);
-        crate::logger::print(&query);
-        self.conn.execute_async(
+        crate::logger::print(query);
+        self.conn.lock().await.execute_async(
             query
         ).await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value))
+            .map(DatabaseResult::from)
+            .map_err(DatabaseError::from)
     }
     
-    pub async fn update(&mut self, table: &str, entry: &[String], data: &[String],
+    /// Inserts a row, or updates `updated_entry` of the row it collides
+    /// with where `cond` holds. Like [`Database::create`], a write to
+    /// the tables of the cached graph drops it.
+    pub async fn update(&self, table: &str, entry: &[String], data: &[String],
             updated_entry: &[String], updated_data: &[String], cond: &str) -> Result<DatabaseResult, DatabaseError> {
+        self.upsert(table, entry, data, updated_entry, updated_data, cond, GraphSync::of(table)).await
+    }
+
+    /// [`Database::update`], with the `change` that brings the cached
+    /// graph along, applied before any other statement.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_with(&self, table: &str, entry: &[String], data: &[String],
+            updated_entry: &[String], updated_data: &[String], cond: &str,
+            change: impl FnOnce(&mut TagGraph) + Send) -> Result<DatabaseResult, DatabaseError> {
+        self.upsert(table, entry, data, updated_entry, updated_data, cond, GraphSync::Apply(Box::new(change))).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn upsert(&self, table: &str, entry: &[String], data: &[String],
+            updated_entry: &[String], updated_data: &[String], cond: &str, sync: GraphSync<'_>) -> Result<DatabaseResult, DatabaseError> {
         // find the same part between entry and update_entry,
         // which is the conflict and can be used to query
         let mut keys = vec![];
@@ -254,13 +433,14 @@ impl Database {
             entry.join(", "),
             data.join(", "),
         );
-        crate::logger::print(&query);
-        let res = self.conn.execute_async(
+        crate::logger::print(query);
+        let mut conn = self.conn.lock().await;
+        let res = conn.execute_async(
             query
         ).await
-            .map(|value| DatabaseResult::from(value))
-            .map_err(|value| DatabaseError::from(value));
-        if let Err(DatabaseError::UniqueViolation) = res {
+            .map(DatabaseResult::from)
+            .map_err(DatabaseError::from);
+        let res = if let Err(DatabaseError::UniqueViolation) = res {
             let query = updated_entry.iter().zip(updated_data).map(|(entry, data)|
                 format!("UPDATE {} SET {} = {} WHERE {} AND {};",
                     table,
@@ -272,12 +452,14 @@ impl Database {
             ).collect::<Vec<_>>()
                 .join("\n");
             crate::logger::print(&query);
-            self.conn.execute_async(
+            conn.execute_async(
                 query
             ).await
-                .map(|value| DatabaseResult::from(value))
-                .map_err(|value| DatabaseError::from(value))
-        } else { res }
+                .map(DatabaseResult::from)
+                .map_err(DatabaseError::from)
+        } else { res };
+        self.sync_graph(sync, res.is_ok());
+        res
     }
 }
 
@@ -325,7 +507,7 @@ mod tests {
 
     #[test]
     fn test_crud() {
-        let mut db = Database::sync_new("aaa").unwrap();
+        let db = Database::sync_new("aaa").unwrap();
         let entry = ["tag_name".to_string(), "info".to_string()];
         // insert a record ('11', '22')
         assert!(matches!(
@@ -362,4 +544,18 @@ mod tests {
             Ok(DatabaseResult::Success(_))
         ), "delete failed");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_graph() {
+        let db = Database::from_glue(Glue::new(MemoryStorage::default()));
+        block_on(db.migrate(false)).unwrap();
+        let entry = ["tag_name".to_string()];
+        block_on(db.create_with("tags", &entry, &["'rust'".to_string()], |graph| graph.add_tag("rust", None))).unwrap();
+        assert_eq!(block_on(db.graph()).unwrap().len(), 1);
+        // followed by the change, then dropped by a plain write
+        block_on(db.create_with("tags", &entry, &["'cargo'".to_string()], |graph| graph.add_tag("cargo", None))).unwrap();
+        assert_eq!(block_on(db.graph()).unwrap().len(), 2);
+        block_on(db.delete("tags", "tag_name", "'cargo'")).unwrap();
+        assert_eq!(block_on(db.graph()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,268 @@
+//! Versioned schema migrations shared by both database backends.
+//!
+//! Every [`Migration`] carries one batch of statements per backend, since
+//! SQLite and GlueSQL do not agree on types and `ALTER TABLE` support.
+//! The applied versions are recorded in a `schema_version` table, and
+//! a database is brought up to date by running the pending migrations in
+//! ascending order.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::db::migrate;
+//!
+//! let pending: Vec<_> = migrate::pending(0).map(|m| m.version).collect();
+//! assert_eq!(pending.first(), Some(&1));
+//! assert_eq!(pending.last(), Some(&migrate::latest()));
+//! ```
+
+/// The SQL flavor a backend speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Sqlite,
+    GlueSql,
+}
+
+/// A single schema change. An empty statement batch means the change
+/// needs no work on that backend, but the version is still recorded.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sqlite: &'static str,
+    gluesql: &'static str,
+}
+
+impl Migration {
+    pub fn statements(&self, dialect: Dialect) -> &'static str {
+        match dialect {
+            Dialect::Sqlite => self.sqlite,
+            Dialect::GlueSql => self.gluesql,
+        }
+    }
+
+    /// The statement that marks this migration as applied.
+    pub fn record(&self) -> String {
+        format!("INSERT INTO schema_version (version, name) VALUES ({}, '{}');",
+            self.version, self.name)
+    }
+}
+
+/// Creates the bookkeeping table, before the pending migrations run.
+pub const VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version
+(
+    version INTEGER PRIMARY KEY NOT NULL,
+    name    TEXT NOT NULL
+);";
+
+/// All migrations, ordered by version. Never edit an entry that has
+/// been released; append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sqlite: "CREATE TABLE IF NOT EXISTS tags
+        (
+            tag_name    TEXT PRIMARY KEY NOT NULL,
+            info     TEXT
+        );
+        CREATE TABLE IF NOT EXISTS relationship
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight REAL,
+            is_origin INTEGER DEFAULT false,
+            CONSTRAINT relationship_id1_fk FOREIGN KEY (tag1) REFERENCES tags(tag_name),
+            CONSTRAINT relationship_id2_fk FOREIGN KEY (tag2) REFERENCES tags(tag_name),
+            CONSTRAINT relation_pk PRIMARY KEY (tag1, tag2)
+        );",
+        gluesql: "CREATE TABLE IF NOT EXISTS tags
+        (
+            tag_name    TEXT PRIMARY KEY NOT NULL,
+            info     TEXT DEFAULT ''
+        );
+        CREATE TABLE IF NOT EXISTS relationship
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight DECIMAL,
+            is_origin BOOLEAN DEFAULT false,
+            CONSTRAINT relationship_id1_fk FOREIGN KEY (tag1) REFERENCES tags(tag_name),
+            CONSTRAINT relationship_id2_fk FOREIGN KEY (tag2) REFERENCES tags(tag_name),
+            CONSTRAINT relation_pk PRIMARY KEY (tag1, tag2)
+        );",
+    },
+    // SQLite `REAL` is already an 8-byte float, so only GlueSQL has
+    // to rebuild the table with a `FLOAT` (f64) weight column.
+    Migration {
+        version: 2,
+        name: "weight_f64",
+        sqlite: "",
+        gluesql: "CREATE TABLE relationship_f64
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight FLOAT,
+            is_origin BOOLEAN DEFAULT false
+        );
+        INSERT INTO relationship_f64
+            SELECT tag1, tag2, CAST(weight AS FLOAT), is_origin FROM relationship;
+        DROP TABLE relationship;
+        CREATE TABLE relationship
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight FLOAT,
+            is_origin BOOLEAN DEFAULT false,
+            CONSTRAINT relationship_id1_fk FOREIGN KEY (tag1) REFERENCES tags(tag_name),
+            CONSTRAINT relationship_id2_fk FOREIGN KEY (tag2) REFERENCES tags(tag_name),
+            CONSTRAINT relation_pk PRIMARY KEY (tag1, tag2)
+        );
+        INSERT INTO relationship SELECT * FROM relationship_f64;
+        DROP TABLE relationship_f64;",
+    },
+    // The search tokens are produced in Rust by `crate::search::tokenize`,
+    // and GlueSQL searches with an in-memory index instead.
+    Migration {
+        version: 3,
+        name: "tags_fts",
+        sqlite: "CREATE VIRTUAL TABLE IF NOT EXISTS tags_fts
+            USING fts5(tag_name UNINDEXED, name_tokens, info_tokens);",
+        gluesql: "",
+    },
+    // The tags of every `{{ ... }}` block of the posts, and the link
+    // weights `crate::infer` computes from them.
+    Migration {
+        version: 4,
+        name: "cooccurrence",
+        sqlite: "CREATE TABLE IF NOT EXISTS post_blocks
+        (
+            post TEXT NOT NULL,
+            block INTEGER NOT NULL,
+            tag_name TEXT NOT NULL,
+            CONSTRAINT post_blocks_pk PRIMARY KEY (post, block, tag_name)
+        );
+        CREATE TABLE IF NOT EXISTS inferred_link
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            method TEXT NOT NULL,
+            weight REAL,
+            CONSTRAINT inferred_link_pk PRIMARY KEY (tag1, tag2, method)
+        );",
+        gluesql: "CREATE TABLE IF NOT EXISTS post_blocks
+        (
+            post TEXT NOT NULL,
+            block INTEGER NOT NULL,
+            tag_name TEXT NOT NULL,
+            CONSTRAINT post_blocks_pk PRIMARY KEY (post, block, tag_name)
+        );
+        CREATE TABLE IF NOT EXISTS inferred_link
+        (
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            method TEXT NOT NULL,
+            weight FLOAT,
+            CONSTRAINT inferred_link_pk PRIMARY KEY (tag1, tag2, method)
+        );",
+    },
+    // The Markdown notes `crate::notes` has indexed, and the links each
+    // of them backs, with whether the notes made the link.
+    Migration {
+        version: 5,
+        name: "notes",
+        sqlite: "CREATE TABLE IF NOT EXISTS notes
+        (
+            path TEXT PRIMARY KEY NOT NULL,
+            digest TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS note_links
+        (
+            path TEXT NOT NULL,
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            created BOOLEAN NOT NULL DEFAULT false,
+            CONSTRAINT note_links_pk PRIMARY KEY (path, tag1, tag2)
+        );",
+        gluesql: "CREATE TABLE IF NOT EXISTS notes
+        (
+            path TEXT PRIMARY KEY NOT NULL,
+            digest TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS note_links
+        (
+            path TEXT NOT NULL,
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            created BOOLEAN NOT NULL DEFAULT false,
+            CONSTRAINT note_links_pk PRIMARY KEY (path, tag1, tag2)
+        );",
+    },
+    // The links every post of `crate::watch` puts into the database, so
+    // that the ones gone while it was not running are found.
+    Migration {
+        version: 6,
+        name: "post_links",
+        sqlite: "CREATE TABLE IF NOT EXISTS post_links
+        (
+            post TEXT NOT NULL,
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight REAL NOT NULL,
+            CONSTRAINT post_links_pk PRIMARY KEY (post, tag1, tag2)
+        );",
+        gluesql: "CREATE TABLE IF NOT EXISTS post_links
+        (
+            post TEXT NOT NULL,
+            tag1 TEXT NOT NULL,
+            tag2 TEXT NOT NULL,
+            weight FLOAT NOT NULL,
+            CONSTRAINT post_links_pk PRIMARY KEY (post, tag1, tag2)
+        );",
+    },
+];
+
+/// The version a fully migrated database reports.
+pub fn latest() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or_default()
+}
+
+/// The migrations newer than `current`, in the order to apply them.
+pub fn pending(current: i64) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |m| m.version > current)
+}
+
+/// What `frictune migrate status` reports.
+pub struct MigrationStatus {
+    pub current: i64,
+    pub latest: i64,
+    pub pending: Vec<&'static Migration>,
+}
+
+impl MigrationStatus {
+    pub fn new(current: i64) -> Self {
+        MigrationStatus {
+            current,
+            latest: latest(),
+            pending: pending(current).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[test]
+    fn test_pending() {
+        assert_eq!(pending(0).count(), MIGRATIONS.len());
+        assert_eq!(pending(latest()).count(), 0);
+        let status = MigrationStatus::new(1);
+        assert_eq!(status.pending.first().map(|m| m.version), Some(2));
+    }
+}
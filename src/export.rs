@@ -0,0 +1,318 @@
+//! Dumps and restores of the whole tag graph.
+//!
+//! A [`Snapshot`] is the graph as plain data: the tags with their
+//! descriptions and the links, sorted by name so that two dumps of
+//! similar databases diff well. The submodules write and read it in the
+//! file formats; [`import`] brings one into a database, merging it with
+//! the tags there or replacing them. Only the `Database` calls and the
+//! `Tag` functions are used, so both backends can be dumped and
+//! restored.
+//!
+//! Derived links follow from the origin links, so a snapshot leaves
+//! them out unless asked for them.
+//!
+//! - [`json`]: one versioned document;
+//! - [`csv`]: `tags.csv` and `links.csv`, for spreadsheets;
+//! - [`graphml`] and [`gexf`]: for Gephi, yEd and other graph tools,
+//!   export only;
+//! - [`dot`]: for Graphviz, export only;
+//! - [`bookmarks`]: the bookmark file of the browsers, with an importer
+//!   of its own;
+//! - [`obsidian`]: an Obsidian vault, import only.
+
+pub mod bookmarks;
+pub mod csv;
+pub mod dot;
+pub mod gexf;
+pub mod graphml;
+pub mod json;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod obsidian;
+
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{db::{self, crud::{Database, DatabaseError}}, graph::TagGraph, search, Tag};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Graphml,
+    Gexf,
+    Dot,
+    Bookmarks,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "graphml" => Ok(Self::Graphml),
+            "gexf" => Ok(Self::Gexf),
+            "dot" => Ok(Self::Dot),
+            "bookmarks" => Ok(Self::Bookmarks),
+            other => Err(format!("unknown format {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TagEntry {
+    pub name: String,
+    #[serde(default)]
+    pub desc: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LinkEntry {
+    pub source: String,
+    pub target: String,
+    pub weight: f64,
+    /// `false` for a derived link.
+    #[serde(default = "origin")]
+    pub is_origin: bool,
+}
+
+fn origin() -> bool { true }
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub tags: Vec<TagEntry>,
+    pub links: Vec<LinkEntry>,
+}
+
+impl Snapshot {
+    /// The tags and the links of `graph`, the derived links only with
+    /// `derived`.
+    pub fn from_graph(graph: &TagGraph, derived: bool) -> Self {
+        Snapshot::from_tags(graph, &vec![true; graph.len()], derived)
+    }
+
+    /// The tags at most `depth` links away from `name` and the links
+    /// among them, or `None` if there is no such tag. Derived links
+    /// are followed only with `derived`.
+    pub fn around(graph: &TagGraph, name: &str, depth: usize, derived: bool) -> Option<Self> {
+        let mut kept = vec![false; graph.len()];
+        for id in graph.around(graph.id(&Tag::new(name).raw_name())?, depth, !derived) {
+            kept[id] = true;
+        }
+        Some(Snapshot::from_tags(graph, &kept, derived))
+    }
+
+    fn from_tags(graph: &TagGraph, kept: &[bool], derived: bool) -> Self {
+        let mut tags: Vec<TagEntry> = (0..graph.len())
+            .filter(|id| kept[*id])
+            .map(|id| TagEntry { name: graph.name(id).into(), desc: graph.desc(id).map(String::from) })
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut links: Vec<LinkEntry> = graph.links()
+            .filter(|(from, link)| kept[*from] && kept[link.to] && (derived || link.is_origin))
+            .map(|(from, link)| LinkEntry {
+                source: graph.name(from).into(),
+                target: graph.name(link.to).into(),
+                weight: link.weight,
+                is_origin: link.is_origin,
+            })
+            .collect();
+        links.sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+        Snapshot { tags, links }
+    }
+
+    pub async fn read(db: &Database, derived: bool) -> Result<Self, DatabaseError> {
+        Ok(Snapshot::from_graph(&*db.graph().await?, derived))
+    }
+
+    /// The sync version of [`Snapshot::read`].
+    pub fn read_sync(db: &Database, derived: bool) -> Result<Self, DatabaseError> {
+        block_on(async { Snapshot::read(db, derived).await })
+    }
+}
+
+/// Escapes text for XML attributes and elements, dropping the control
+/// characters XML 1.0 cannot hold.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_url(desc: &str) -> bool {
+    desc.starts_with("http://") || desc.starts_with("https://")
+}
+
+/// Why a tag name cannot be imported as it is.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let normalized = crate::normalize::normalize(name);
+    if normalized.trim().is_empty() { Err("the name is empty".into()) }
+    else { Ok(()) }
+}
+
+/// Why a weight cannot be imported as it is.
+pub fn check_weight(weight: f64) -> Result<(), String> {
+    if (0.0..=1.0).contains(&weight) { Ok(()) }
+    else { Err(format!("the weight {} is not within [0, 1]", weight)) }
+}
+
+/// A difference between the database and a snapshot to be merged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    AddTag { name: String, desc: Option<String> },
+    Describe { name: String, old: Option<String>, new: String },
+    AddLink { source: String, target: String, weight: f64 },
+    Reweight { source: String, target: String, old: f64, new: f64 },
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::AddTag { name, desc: Some(desc) } => write!(f, "add tag {} ({})", name, desc),
+            Change::AddTag { name, desc: None } => write!(f, "add tag {}", name),
+            Change::Describe { name, old, new } =>
+                write!(f, "describe {}: {} -> {}", name, old.as_deref().unwrap_or("(none)"), new),
+            Change::AddLink { source, target, weight } => write!(f, "link {} -> {} ({})", source, target, weight),
+            Change::Reweight { source, target, old, new } =>
+                write!(f, "reweight {} -> {}: {} -> {}", source, target, old, new),
+        }
+    }
+}
+
+/// What merging `snapshot` into `graph` changes. Derived links of the
+/// snapshot are left out.
+pub fn diff(graph: &TagGraph, snapshot: &Snapshot) -> Vec<Change> {
+    let mut changes = vec![];
+    let mut added: HashMap<String, Option<String>> = HashMap::new();
+    let named_by_links = snapshot.links.iter()
+        .flat_map(|link| [&link.source, &link.target])
+        .map(|name| TagEntry { name: name.clone(), desc: None });
+    for entry in snapshot.tags.iter().cloned().chain(named_by_links) {
+        let name = Tag::new(&entry.name).raw_name().to_string();
+        let old = match graph.id(&name) {
+            Some(id) => graph.desc(id).map(String::from),
+            None if !added.contains_key(&name) => {
+                changes.push(Change::AddTag { name: name.clone(), desc: entry.desc.clone() });
+                added.insert(name, entry.desc);
+                continue
+            },
+            None => added[&name].clone(),
+        };
+        if let Some(new) = entry.desc.filter(|new| old.as_ref() != Some(new)) {
+            changes.push(Change::Describe { name: name.clone(), old, new: new.clone() });
+            added.insert(name, Some(new));
+        }
+    }
+    for link in snapshot.links.iter().filter(|link| link.is_origin) {
+        let (source, target) = (Tag::new(&link.source).raw_name().to_string(), Tag::new(&link.target).raw_name().to_string());
+        let old = graph.id(&source).zip(graph.id(&target))
+            .and_then(|(from, to)| graph.link(from, to))
+            .filter(|old| old.is_origin)
+            .map(|old| old.weight);
+        match old {
+            None => changes.push(Change::AddLink { source, target, weight: link.weight }),
+            Some(old) if old != link.weight => changes.push(Change::Reweight { source, target, old, new: link.weight }),
+            Some(_) => {},
+        }
+    }
+    changes
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Keep the tags of the database, adding the new ones and taking
+    /// over the descriptions and weights of the snapshot.
+    Merge,
+    /// Drop all tags and links of the database first, along with the
+    /// indexed blocks, inferred links and notes that name them, so that
+    /// posts and notes are indexed again from scratch.
+    Replace,
+}
+
+/// What an import changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub tags_added: usize,
+    pub tags_described: usize,
+    pub links_set: usize,
+}
+
+/// Writes a snapshot into the database, in one transaction. Tags only
+/// named by a link are added without a description; derived links
+/// never replace origin links.
+pub async fn import(db: &Database, snapshot: &Snapshot, mode: ImportMode) -> Result<ImportReport, DatabaseError> {
+    let tx = db.begin().await?;
+    let report = import_into(&tx, snapshot, mode).await?;
+    tx.commit().await?;
+    Ok(report)
+}
+
+async fn import_into(db: &Database, snapshot: &Snapshot, mode: ImportMode) -> Result<ImportReport, DatabaseError> {
+    if mode == ImportMode::Replace {
+        let names = db.read("tags", &["tag_name".to_string()], "true", "").await?.get::<String>(0);
+        for name in names {
+            search::unindex_tag(db, &db::quote(&name)).await?;
+        }
+        for table in ["post_blocks", "post_links", "inferred_link", "note_links", "notes", "relationship", "tags"] {
+            db.delete(table, "1", "1").await?;
+        }
+    }
+
+    let mut report = ImportReport::default();
+    let graph = db.graph().await?;
+    let mut known: HashMap<String, Option<String>> = (0..graph.len())
+        .map(|id| (graph.name(id).to_string(), graph.desc(id).map(String::from)))
+        .collect();
+    let named_by_links = snapshot.links.iter()
+        .flat_map(|link| [&link.source, &link.target])
+        .map(|name| TagEntry { name: name.clone(), desc: None });
+    for entry in snapshot.tags.iter().cloned().chain(named_by_links) {
+        let tag = Tag::new(&entry.name);
+        match known.get(tag.raw_name().as_ref()) {
+            None => {
+                Tag::new_with_desc(&entry.name, entry.desc.as_deref().map(db::quote))
+                    .add_tag::<String>(db, &[]).await?;
+                report.tags_added += 1;
+            },
+            Some(desc) if entry.desc.is_some() && *desc != entry.desc => {
+                tag.modify_tag(db, &db::quote(entry.desc.as_deref().unwrap_or_default())).await?;
+                report.tags_described += 1;
+            },
+            Some(_) => continue,
+        }
+        known.insert(tag.raw_name().to_string(), entry.desc);
+    }
+
+    let entries = ["tag1".to_string(), "tag2".to_string(), "weight".to_string(), "is_origin".to_string()];
+    for link in snapshot.links.iter() {
+        let (source, target) = (Tag::new(&link.source), Tag::new(&link.target));
+        if link.is_origin {
+            source.link_tags(db, &target, link.weight).await?;
+        } else {
+            let data = [source.name.clone(), target.name.clone(), link.weight.to_string(), "false".to_string()];
+            db.update("relationship", &entries, &data, &entries[2..], &data[2..],
+                "excluded.weight > weight AND is_origin = false").await?;
+        }
+        report.links_set += 1;
+    }
+    Ok(report)
+}
+
+/// The sync version of [`import`].
+pub fn import_sync(db: &Database, snapshot: &Snapshot, mode: ImportMode) -> Result<ImportReport, DatabaseError> {
+    block_on(async { import(db, snapshot, mode).await })
+}
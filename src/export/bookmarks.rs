@@ -0,0 +1,209 @@
+//! The Netscape bookmark file, which every browser exports and imports:
+//!
+//! ```html
+//! <!DOCTYPE NETSCAPE-Bookmark-file-1>
+//! <DL><p>
+//!     <DT><H3>Rust</H3>
+//!     <DL><p>
+//!         <DT><A HREF="https://tokio.rs/" TAGS="async,runtime">Tokio</A>
+//!     </DL><p>
+//! </DL><p>
+//! ```
+//!
+//! [`parse`] turns every bookmark into a tag named by its title and
+//! described by its URL, every folder into a tag, and every keyword of
+//! `TAGS` into a tag. A bookmark links to its folder and a folder to
+//! the folder around it with [`Weights::folder`]; a bookmark links to
+//! its keywords with [`Weights::tag`]. The toolbar and unfiled folders
+//! of the browsers are not tags. A URL bookmarked twice is one tag, and
+//! of different URLs under one title only the first is kept.
+//!
+//! [`write`] goes the other way with the tags described by a URL, as a
+//! flat list whose `TAGS` are the origin links of each.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::export::bookmarks::{parse, Weights};
+//!
+//! let html = r#"<DL><DT><H3>Rust</H3><DL><DT><A HREF="https://tokio.rs/" TAGS="async">Tokio</A></DL></DL>"#;
+//! let snapshot = parse(html, &Weights::default());
+//! assert_eq!(snapshot.tags.len(), 3);
+//! assert_eq!(snapshot.links[0].target, "Rust");
+//! ```
+
+use std::{collections::{HashMap, HashSet}, io::Write, sync::LazyLock};
+
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{escape_xml, is_url, LinkEntry, Snapshot, TagEntry};
+use crate::graph::TagGraph;
+
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<h3([^>]*)>(.*?)</h3>|<a\s([^>]*)>(.*?)</a>|<dl\b[^>]*>|</dl>").unwrap()
+});
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?s)([A-Za-z_]+)\s*=\s*"([^"]*)""#).unwrap());
+static ENTITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-z]+);").unwrap());
+
+/// The weights of the links made by [`parse`], read from the
+/// `bookmarks` table of the settings.
+///
+/// ```toml
+/// [bookmarks]
+/// folder = 0.5
+/// tag = 0.8
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Weights {
+    /// From a bookmark to its folder, and from a folder to its parent.
+    pub folder: f64,
+    /// From a bookmark to each of its `TAGS`.
+    pub tag: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self { Weights { folder: 0.5, tag: 0.8 } }
+}
+
+/// Builds the snapshot as it comes, in the order of the file.
+#[derive(Default)]
+struct Builder {
+    snapshot: Snapshot,
+    names: HashSet<String>,
+    links: HashSet<(String, String)>,
+    /// The tag name of every bookmarked URL.
+    urls: HashMap<String, String>,
+}
+
+impl Builder {
+    fn tag(&mut self, name: &str, desc: Option<&str>) {
+        if self.names.insert(name.into()) {
+            self.snapshot.tags.push(TagEntry { name: name.into(), desc: desc.map(String::from) });
+        }
+    }
+
+    fn link(&mut self, source: &str, target: &str, weight: f64) {
+        if source != target && self.links.insert((source.into(), target.into())) {
+            self.snapshot.links.push(LinkEntry { source: source.into(), target: target.into(), weight, is_origin: true });
+        }
+    }
+
+    /// The tag of a bookmark, `None` if its title is taken by another
+    /// URL.
+    fn bookmark(&mut self, title: &str, url: &str) -> Option<String> {
+        if let Some(name) = self.urls.get(url) { return Some(name.clone()) }
+        if self.names.contains(title) { return None }
+        self.tag(title, Some(url));
+        self.urls.insert(url.into(), title.into());
+        Some(title.into())
+    }
+}
+
+pub fn parse(html: &str, weights: &Weights) -> Snapshot {
+    let mut builder = Builder::default();
+    // the folders around, `None` for those that are not tags
+    let mut folders: Vec<Option<String>> = vec![];
+    // the folder whose list comes next
+    let mut heading: Option<String> = None;
+    for captures in TOKEN.captures_iter(html) {
+        let whole = captures[0].to_ascii_lowercase();
+        let parent = folders.iter().rev().flatten().next().cloned();
+        if let (Some(attributes), Some(text)) = (captures.get(1), captures.get(2)) {
+            let attributes = attributes_of(attributes.as_str());
+            let root = ["personal_toolbar_folder", "unfiled_bookmarks_folder"].iter()
+                .any(|key| attributes.contains_key(*key));
+            heading = Some(name_of(text.as_str())).filter(|name| !root && !name.is_empty());
+            if let Some(folder) = &heading {
+                builder.tag(folder, None);
+                if let Some(parent) = &parent { builder.link(folder, parent, weights.folder) }
+            }
+        } else if let (Some(attributes), Some(text)) = (captures.get(3), captures.get(4)) {
+            let attributes = attributes_of(attributes.as_str());
+            let Some(url) = attributes.get("href") else { continue };
+            let title = Some(name_of(text.as_str())).filter(|title| !title.is_empty()).unwrap_or_else(|| name_of(url));
+            let Some(name) = builder.bookmark(&title, url) else { continue };
+            if let Some(folder) = &parent { builder.link(&name, folder, weights.folder) }
+            for keyword in attributes.get("tags").map(|tags| tags.split(',')).into_iter().flatten() {
+                let keyword = name_of(keyword);
+                if keyword.is_empty() { continue }
+                builder.tag(&keyword, None);
+                builder.link(&name, &keyword, weights.tag);
+            }
+        } else if whole.starts_with("</") {
+            folders.pop();
+        } else {
+            folders.push(heading.take());
+        }
+    }
+    builder.snapshot
+}
+
+/// The lowercased keys and unescaped values of the attributes of a tag.
+fn attributes_of(text: &str) -> HashMap<String, String> {
+    ATTRIBUTE.captures_iter(text)
+        .map(|c| (c[1].to_ascii_lowercase(), unescape(&c[2])))
+        .collect()
+}
+
+fn name_of(text: &str) -> String {
+    unescape(text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn unescape(text: &str) -> String {
+    ENTITY.replace_all(text, |c: &regex::Captures| {
+        let code = &c[1];
+        let decoded = match code {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ if code.starts_with("#x") || code.starts_with("#X") =>
+                u32::from_str_radix(&code[2..], 16).ok().and_then(char::from_u32),
+            _ if code.starts_with('#') => code[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        decoded.map_or_else(|| c[0].to_string(), String::from)
+    }).into_owned()
+}
+
+/// Drops the bookmarks whose names or URLs are already in `graph`,
+/// with their links, and returns their names.
+pub fn skip_known(graph: &TagGraph, snapshot: &mut Snapshot) -> Vec<String> {
+    let urls: HashSet<&str> = (0..graph.len()).filter_map(|id| graph.desc(id)).collect();
+    let known: HashSet<String> = snapshot.tags.iter()
+        .filter(|tag| tag.desc.as_deref()
+            .is_some_and(|url| urls.contains(url) || graph.id(&crate::Tag::new(&tag.name).raw_name()).is_some()))
+        .map(|tag| tag.name.clone())
+        .collect();
+    snapshot.tags.retain(|tag| !known.contains(&tag.name));
+    snapshot.links.retain(|link| !known.contains(&link.source));
+    let mut skipped: Vec<String> = known.into_iter().collect();
+    skipped.sort();
+    skipped
+}
+
+pub fn write(snapshot: &Snapshot, mut out: impl Write) -> anyhow::Result<()> {
+    let mut keywords: HashMap<&str, Vec<&str>> = HashMap::new();
+    for link in snapshot.links.iter().filter(|link| link.is_origin && !link.target.contains(',')) {
+        keywords.entry(&link.source).or_default().push(&link.target);
+    }
+    writeln!(out, "<!DOCTYPE NETSCAPE-Bookmark-file-1>")?;
+    writeln!(out, r#"<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">"#)?;
+    writeln!(out, "<TITLE>Bookmarks</TITLE>")?;
+    writeln!(out, "<H1>Bookmarks</H1>")?;
+    writeln!(out, "<DL><p>")?;
+    for tag in snapshot.tags.iter() {
+        let Some(url) = tag.desc.as_deref().filter(|desc| is_url(desc)) else { continue };
+        write!(out, r#"    <DT><A HREF="{}""#, escape_xml(url))?;
+        if let Some(keywords) = keywords.get(tag.name.as_str()) {
+            write!(out, r#" TAGS="{}""#, escape_xml(&keywords.join(",")))?;
+        }
+        writeln!(out, ">{}</A>", escape_xml(&tag.name))?;
+    }
+    writeln!(out, "</DL><p>")?;
+    Ok(())
+}
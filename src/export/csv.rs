@@ -0,0 +1,201 @@
+//! The CSV dump of a [`Snapshot`], as two files for spreadsheets:
+//!
+//! ```text
+//! tags.csv                            links.csv
+//! name,desc                           source,target,weight,is_origin
+//! cargo,https://doc.rust-lang.org/    cargo,rust,0.8,true
+//! rust,
+//! ```
+//!
+//! An empty `desc` is no description. Files exported elsewhere may name
+//! their columns differently, which [`Columns::map`] takes care of; the
+//! `desc` and `is_origin` columns may be missing. Reading checks every
+//! row with [`check_name`] and [`check_weight`] and reports the rows that
+//! fail as [`Problem`]s instead of stopping at the first one.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::export::{csv, LinkEntry, Snapshot, TagEntry};
+//!
+//! let snapshot = Snapshot {
+//!     tags: vec![TagEntry { name: "rust".into(), desc: None }],
+//!     links: vec![LinkEntry { source: "cargo".into(), target: "rust".into(), weight: 0.8, is_origin: true }],
+//! };
+//! let (mut tags, mut links) = (vec![], vec![]);
+//! csv::write(&snapshot, &mut tags, &mut links).unwrap();
+//!
+//! let columns = csv::Columns::default();
+//! let (read, problems) = csv::read(tags.as_slice(), links.as_slice(), &columns).unwrap();
+//! assert!(problems.is_empty());
+//! assert_eq!(read, snapshot);
+//! ```
+
+use std::io::{Read, Write};
+
+use super::{check_name, check_weight, LinkEntry, Snapshot, TagEntry};
+
+pub const TAGS_FILE: &str = "tags.csv";
+pub const LINKS_FILE: &str = "links.csv";
+
+pub fn write(snapshot: &Snapshot, tags_out: impl Write, links_out: impl Write) -> anyhow::Result<()> {
+    let mut tags = ::csv::Writer::from_writer(tags_out);
+    tags.write_record(["name", "desc"])?;
+    for tag in snapshot.tags.iter() {
+        tags.write_record([tag.name.as_str(), tag.desc.as_deref().unwrap_or_default()])?;
+    }
+    tags.flush()?;
+
+    let mut links = ::csv::Writer::from_writer(links_out);
+    links.write_record(["source", "target", "weight", "is_origin"])?;
+    for link in snapshot.links.iter() {
+        links.write_record([&link.source, &link.target, &link.weight.to_string(), &link.is_origin.to_string()])?;
+    }
+    links.flush()?;
+    Ok(())
+}
+
+/// The header of the column holding each field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Columns {
+    pub name: String,
+    pub desc: String,
+    pub source: String,
+    pub target: String,
+    pub weight: String,
+    pub is_origin: String,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            name: "name".into(),
+            desc: "desc".into(),
+            source: "source".into(),
+            target: "target".into(),
+            weight: "weight".into(),
+            is_origin: "is_origin".into(),
+        }
+    }
+}
+
+impl Columns {
+    /// Reads a field from the column `header`, given as `field=header`.
+    pub fn map(&mut self, mapping: &str) -> Result<(), String> {
+        let (field, header) = mapping.split_once('=')
+            .ok_or_else(|| format!("expected FIELD=HEADER, found {}", mapping))?;
+        let column = match field.trim() {
+            "name" => &mut self.name,
+            "desc" => &mut self.desc,
+            "source" => &mut self.source,
+            "target" => &mut self.target,
+            "weight" => &mut self.weight,
+            "is_origin" => &mut self.is_origin,
+            other => return Err(format!("unknown field {}", other)),
+        };
+        *column = header.trim().to_string();
+        Ok(())
+    }
+}
+
+/// A row that cannot be imported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub file: &'static str,
+    /// 1-based, the header being line 1.
+    pub line: u64,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+struct Table<R> {
+    reader: ::csv::Reader<R>,
+    file: &'static str,
+}
+
+impl<R: Read> Table<R> {
+    fn new(input: R, file: &'static str) -> Self {
+        let reader = ::csv::ReaderBuilder::new().trim(::csv::Trim::All).flexible(true).from_reader(input);
+        Table { reader, file }
+    }
+
+    /// The index of the column `header`, which must exist if `required`.
+    fn column(&mut self, header: &str, required: bool) -> anyhow::Result<Option<usize>> {
+        let index = self.reader.headers()?.iter().position(|h| h == header);
+        if index.is_none() && required {
+            anyhow::bail!("{} has no column {}", self.file, header);
+        }
+        Ok(index)
+    }
+
+    /// The rows with their line numbers.
+    fn rows(&mut self) -> impl Iterator<Item = anyhow::Result<(u64, ::csv::StringRecord)>> + '_ {
+        self.reader.records().map(|row| {
+            let row = row?;
+            Ok((row.position().map_or(0, |p| p.line()), row))
+        })
+    }
+}
+
+pub fn read_tags(input: impl Read, columns: &Columns) -> anyhow::Result<(Vec<TagEntry>, Vec<Problem>)> {
+    let mut table = Table::new(input, TAGS_FILE);
+    let name = table.column(&columns.name, true)?.unwrap_or_default();
+    let desc = table.column(&columns.desc, false)?;
+    let (mut tags, mut problems) = (vec![], vec![]);
+    for row in table.rows() {
+        let (line, row) = row?;
+        let tag = TagEntry {
+            name: row.get(name).unwrap_or_default().to_string(),
+            desc: desc.and_then(|i| row.get(i)).filter(|d| !d.is_empty()).map(String::from),
+        };
+        match check_name(&tag.name) {
+            Ok(()) => tags.push(tag),
+            Err(message) => problems.push(Problem { file: TAGS_FILE, line, message }),
+        }
+    }
+    Ok((tags, problems))
+}
+
+pub fn read_links(input: impl Read, columns: &Columns) -> anyhow::Result<(Vec<LinkEntry>, Vec<Problem>)> {
+    let mut table = Table::new(input, LINKS_FILE);
+    let source = table.column(&columns.source, true)?.unwrap_or_default();
+    let target = table.column(&columns.target, true)?.unwrap_or_default();
+    let weight = table.column(&columns.weight, true)?.unwrap_or_default();
+    let is_origin = table.column(&columns.is_origin, false)?;
+    let (mut links, mut problems) = (vec![], vec![]);
+    for row in table.rows() {
+        let (line, row) = row?;
+        let field = |i: usize| row.get(i).unwrap_or_default();
+        let link = (|| -> Result<LinkEntry, String> {
+            check_name(field(source))?;
+            check_name(field(target))?;
+            let weight = field(weight).parse::<f64>()
+                .map_err(|_| format!("the weight {} is not a number", field(weight)))?;
+            check_weight(weight)?;
+            let is_origin = match is_origin.map(field) {
+                None | Some("") => true,
+                Some(value) => value.parse::<bool>()
+                    .map_err(|_| format!("is_origin {} is neither true nor false", value))?,
+            };
+            Ok(LinkEntry { source: field(source).into(), target: field(target).into(), weight, is_origin })
+        })();
+        match link {
+            Ok(link) => links.push(link),
+            Err(message) => problems.push(Problem { file: LINKS_FILE, line, message }),
+        }
+    }
+    Ok((links, problems))
+}
+
+/// Reads both files into one snapshot.
+pub fn read(tags: impl Read, links: impl Read, columns: &Columns) -> anyhow::Result<(Snapshot, Vec<Problem>)> {
+    let (tags, mut problems) = read_tags(tags, columns)?;
+    let (links, link_problems) = read_links(links, columns)?;
+    problems.extend(link_problems);
+    Ok((Snapshot { tags, links }, problems))
+}
@@ -0,0 +1,63 @@
+//! The Graphviz DOT dump of a [`Snapshot`], for `dot`, `neato` and the
+//! other layouts:
+//!
+//! ```dot
+//! digraph frictune {
+//!   node [shape=box, style=rounded];
+//!   "rust" [tooltip="https://rust-lang.org", URL="https://rust-lang.org"];
+//!   "cargo";
+//!   "cargo" -> "rust" [label="0.80", penwidth=3.4];
+//!   "crates" -> "rust" [label="0.40", penwidth=2.2, style=dashed];
+//! }
+//! ```
+//!
+//! The heavier a link, the thicker its line, from 1 for weight 0 to 4
+//! for weight 1; derived links are dashed. A description becomes the
+//! tooltip of its tag, and a URL description also its `URL`, which
+//! makes the tag a hyperlink in SVG output.
+
+use std::io::Write;
+
+use super::{is_url, Snapshot};
+
+pub fn write(snapshot: &Snapshot, mut out: impl Write) -> anyhow::Result<()> {
+    writeln!(out, "digraph frictune {{")?;
+    writeln!(out, "  node [shape=box, style=rounded];")?;
+    for tag in snapshot.tags.iter() {
+        let mut attributes = vec![];
+        if let Some(desc) = &tag.desc {
+            attributes.push(format!("tooltip={}", quote(desc)));
+            if is_url(desc) { attributes.push(format!("URL={}", quote(desc))) }
+        }
+        if attributes.is_empty() {
+            writeln!(out, "  {};", quote(&tag.name))?;
+        } else {
+            writeln!(out, "  {} [{}];", quote(&tag.name), attributes.join(", "))?;
+        }
+    }
+    for link in snapshot.links.iter() {
+        write!(out, "  {} -> {} [label=\"{:.2}\", penwidth={:.1}", quote(&link.source), quote(&link.target),
+            link.weight, 1.0 + 3.0 * link.weight.clamp(0.0, 1.0))?;
+        if !link.is_origin { write!(out, ", style=dashed")? }
+        writeln!(out, "];")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// A DOT string literal.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {},
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,53 @@
+//! The GEXF 1.3 dump of a [`Snapshot`], Gephi's own format:
+//!
+//! ```xml
+//! <gexf xmlns="http://gexf.net/1.3" version="1.3">
+//!   <graph defaultedgetype="directed">
+//!     <attributes class="node"><attribute id="desc" title="description" type="string"/></attributes>
+//!     <attributes class="edge"><attribute id="is_origin" title="is_origin" type="boolean"/></attributes>
+//!     <nodes>
+//!       <node id="rust" label="rust"/>
+//!     </nodes>
+//!     <edges>
+//!       <edge id="0" source="cargo" target="rust" weight="0.8"><attvalues><attvalue for="is_origin" value="true"/></attvalues></edge>
+//!     </edges>
+//!   </graph>
+//! </gexf>
+//! ```
+//!
+//! The weight is GEXF's own edge weight; the description and the
+//! `is_origin` flag are attributes. Tags are identified by their names.
+
+use std::io::Write;
+
+use super::{escape_xml, Snapshot};
+
+pub fn write(snapshot: &Snapshot, mut out: impl Write) -> anyhow::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+    writeln!(out, "  <meta><creator>frictune</creator></meta>")?;
+    writeln!(out, r#"  <graph defaultedgetype="directed">"#)?;
+    writeln!(out, r#"    <attributes class="node"><attribute id="desc" title="description" type="string"/></attributes>"#)?;
+    writeln!(out, r#"    <attributes class="edge"><attribute id="is_origin" title="is_origin" type="boolean"/></attributes>"#)?;
+    writeln!(out, "    <nodes>")?;
+    for tag in snapshot.tags.iter() {
+        let name = escape_xml(&tag.name);
+        match &tag.desc {
+            Some(desc) => writeln!(out,
+                r#"      <node id="{}" label="{}"><attvalues><attvalue for="desc" value="{}"/></attvalues></node>"#,
+                name, name, escape_xml(desc))?,
+            None => writeln!(out, r#"      <node id="{}" label="{}"/>"#, name, name)?,
+        }
+    }
+    writeln!(out, "    </nodes>")?;
+    writeln!(out, "    <edges>")?;
+    for (id, link) in snapshot.links.iter().enumerate() {
+        writeln!(out,
+            r#"      <edge id="{}" source="{}" target="{}" weight="{}"><attvalues><attvalue for="is_origin" value="{}"/></attvalues></edge>"#,
+            id, escape_xml(&link.source), escape_xml(&link.target), link.weight, link.is_origin)?;
+    }
+    writeln!(out, "    </edges>")?;
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</gexf>")?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! The GraphML dump of a [`Snapshot`], for yEd and Gephi:
+//!
+//! ```xml
+//! <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+//!   <key id="label" for="node" attr.name="label" attr.type="string"/>
+//!   <key id="desc" for="node" attr.name="description" attr.type="string"/>
+//!   <key id="weight" for="edge" attr.name="weight" attr.type="double"/>
+//!   <key id="is_origin" for="edge" attr.name="is_origin" attr.type="boolean"/>
+//!   <graph id="frictune" edgedefault="directed">
+//!     <node id="rust"><data key="label">rust</data></node>
+//!     <edge source="cargo" target="rust"><data key="weight">0.8</data><data key="is_origin">true</data></edge>
+//!   </graph>
+//! </graphml>
+//! ```
+//!
+//! Tags are identified by their names; `desc` is left out of the tags
+//! without a description.
+
+use std::io::Write;
+
+use super::{escape_xml, Snapshot};
+
+pub fn write(snapshot: &Snapshot, mut out: impl Write) -> anyhow::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns""#)?;
+    writeln!(out, r#"    xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#)?;
+    writeln!(out, r#"    xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">"#)?;
+    writeln!(out, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="desc" for="node" attr.name="description" attr.type="string"/>"#)?;
+    writeln!(out, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+    writeln!(out, r#"  <key id="is_origin" for="edge" attr.name="is_origin" attr.type="boolean"/>"#)?;
+    writeln!(out, r#"  <graph id="frictune" edgedefault="directed">"#)?;
+    for tag in snapshot.tags.iter() {
+        let name = escape_xml(&tag.name);
+        write!(out, r#"    <node id="{}"><data key="label">{}</data>"#, name, name)?;
+        if let Some(desc) = &tag.desc {
+            write!(out, r#"<data key="desc">{}</data>"#, escape_xml(desc))?;
+        }
+        writeln!(out, "</node>")?;
+    }
+    for link in snapshot.links.iter() {
+        writeln!(out, r#"    <edge source="{}" target="{}"><data key="weight">{}</data><data key="is_origin">{}</data></edge>"#,
+            escape_xml(&link.source), escape_xml(&link.target), link.weight, link.is_origin)?;
+    }
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! The JSON dump of a [`Snapshot`]:
+//!
+//! ```json
+//! {
+//!   "frictune": 1,
+//!   "tags": [
+//!     { "name": "cargo", "desc": "https://doc.rust-lang.org/cargo" },
+//!     { "name": "rust", "desc": null }
+//!   ],
+//!   "links": [
+//!     { "source": "cargo", "target": "rust", "weight": 0.8, "is_origin": true }
+//!   ]
+//! }
+//! ```
+//!
+//! `frictune` is the version of the document, raised whenever a field
+//! changes meaning; documents of a newer version are refused. A
+//! missing `desc` is `null` and a missing `is_origin` is `true`.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::export::{json, LinkEntry, Snapshot, TagEntry};
+//!
+//! let snapshot = Snapshot {
+//!     tags: vec![TagEntry { name: "rust".into(), desc: None }],
+//!     links: vec![LinkEntry { source: "cargo".into(), target: "rust".into(), weight: 0.8, is_origin: true }],
+//! };
+//! let mut out = vec![];
+//! json::write(&snapshot, &mut out).unwrap();
+//! assert_eq!(json::read(out.as_slice()).unwrap(), snapshot);
+//! ```
+
+use std::io::{Read, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::{LinkEntry, Snapshot, TagEntry};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    frictune: u32,
+    tags: Vec<TagEntry>,
+    #[serde(default)]
+    links: Vec<LinkEntry>,
+}
+
+pub fn write(snapshot: &Snapshot, mut out: impl Write) -> anyhow::Result<()> {
+    let document = Document { frictune: VERSION, tags: snapshot.tags.clone(), links: snapshot.links.clone() };
+    serde_json::to_writer_pretty(&mut out, &document)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn read(input: impl Read) -> anyhow::Result<Snapshot> {
+    let document: Document = serde_json::from_reader(input)?;
+    if document.frictune > VERSION {
+        anyhow::bail!("the document has version {}, but at most {} is understood", document.frictune, VERSION);
+    }
+    Ok(Snapshot { tags: document.tags, links: document.links })
+}
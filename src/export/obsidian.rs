@@ -0,0 +1,249 @@
+//! An Obsidian vault, a folder of Markdown notes linking each other:
+//!
+//! ```markdown
+//! ---
+//! aliases: [Tokio runtime]
+//! tags: [async]
+//! ---
+//! # The Tokio runtime
+//! Built on [[Mio|mio]], see [[Rust#Async]] and #networking.
+//! ```
+//!
+//! [`parse`] turns every note into a tag named by its file name and
+//! described by its first heading, or by its path in the vault with
+//! [`Describe::Path`] and for notes without a heading. A note links to
+//! the notes of its `[[wikilinks]]` with [`Settings::link`], and to its
+//! `tags` and `#tags`, as read by [`crate::notes::extract`], with
+//! [`Settings::tag`]. Wikilinks are resolved as Obsidian does, ignoring
+//! case: by path, then by file name, then by the `aliases` of the front
+//! matter; an alias is not a tag of its own. A wikilink to a missing
+//! note still names a tag, but embeds of missing files, such as images,
+//! are skipped. Of two notes with one file name the second is named by
+//! its path.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::export::obsidian::{parse, Note, Settings};
+//!
+//! let notes = [
+//!     Note { path: "Tokio.md".into(), text: "# The Tokio runtime\nSee [[Rust]] and #async.".into() },
+//!     Note { path: "lang/Rust.md".into(), text: "".into() },
+//! ];
+//! let snapshot = parse(&notes, &Settings::default());
+//! assert_eq!(snapshot.tags[0].desc.as_deref(), Some("The Tokio runtime"));
+//! assert_eq!(snapshot.tags[1].desc.as_deref(), Some("lang/Rust.md"));
+//! assert_eq!(snapshot.links.len(), 2);
+//! ```
+
+use std::{collections::{HashMap, HashSet}, path::Path, sync::LazyLock};
+
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{check_name, LinkEntry, Snapshot, TagEntry};
+use crate::{notes, Tag};
+
+static LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"!?\[\[[^\[\]\n]*\]\]").unwrap());
+static ATX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#{1,6}[ \t]+(.*?)(?:[ \t]+#+)?[ \t]*$").unwrap());
+static WIKILINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(!?)\[\[([^\[\]|#^\n]*)[^\[\]\n]*\]\]").unwrap());
+
+/// What describes the tag of a note.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Describe {
+    /// The first heading, or else the path.
+    #[default]
+    Heading,
+    /// The path in the vault.
+    Path,
+}
+
+/// How notes are linked and described, read from the `obsidian` table
+/// of the settings.
+///
+/// ```toml
+/// [obsidian]
+/// link = 0.6
+/// tag = 0.8
+/// describe = "heading"
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// From a note to each note of its wikilinks.
+    pub link: f64,
+    /// From a note to each of its tags.
+    pub tag: f64,
+    pub describe: Describe,
+}
+
+impl Default for Settings {
+    fn default() -> Self { Settings { link: 0.6, tag: 0.8, describe: Describe::Heading } }
+}
+
+/// A note of a vault.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    /// The path from the vault, with `/` between folders.
+    pub path: String,
+    pub text: String,
+}
+
+/// Builds the snapshot as it comes, with the names as the `Tag`
+/// functions store them.
+#[derive(Default)]
+struct Builder {
+    snapshot: Snapshot,
+    names: HashSet<String>,
+    links: HashSet<(String, String)>,
+}
+
+impl Builder {
+    fn tag(&mut self, name: &str, desc: Option<String>) {
+        if self.names.insert(Tag::new(name).raw_name().into()) {
+            self.snapshot.tags.push(TagEntry { name: name.into(), desc });
+        }
+    }
+
+    fn link(&mut self, source: &str, target: &str, weight: f64) {
+        let key = (Tag::new(source).raw_name().to_string(), Tag::new(target).raw_name().to_string());
+        if key.0 != key.1 && self.links.insert(key) {
+            self.snapshot.links.push(LinkEntry { source: source.into(), target: target.into(), weight, is_origin: true });
+        }
+    }
+}
+
+pub fn parse(notes: &[Note], settings: &Settings) -> Snapshot {
+    // the tag of every note, `None` for the names that cannot be stored
+    let mut taken = HashSet::new();
+    let names: Vec<Option<String>> = notes.iter().map(|note| {
+        let path = without_extension(&note.path);
+        [path.rsplit('/').next().unwrap_or(path), path].into_iter()
+            .map(name_of)
+            .find(|name| check_name(name).is_ok() && taken.insert(Tag::new(name).raw_name().to_string()))
+    }).collect();
+
+    // paths first, then file names, then aliases, the first note winning
+    let mut targets: HashMap<String, usize> = HashMap::new();
+    for (index, note) in notes.iter().enumerate() {
+        targets.entry(without_extension(&note.path).to_lowercase()).or_insert(index);
+    }
+    for (index, note) in notes.iter().enumerate() {
+        let path = without_extension(&note.path);
+        targets.entry(path.rsplit('/').next().unwrap_or(path).to_lowercase()).or_insert(index);
+    }
+    for (index, note) in notes.iter().enumerate() {
+        for alias in aliases(notes::front_matter(&note.text).0.as_ref()) {
+            targets.entry(alias.to_lowercase()).or_insert(index);
+        }
+    }
+
+    let mut builder = Builder::default();
+    for (note, name) in notes.iter().zip(names.iter()) {
+        let Some(name) = name else { continue };
+        let heading = heading(notes::front_matter(&note.text).1).filter(|_| settings.describe == Describe::Heading);
+        builder.tag(name, Some(heading.unwrap_or_else(|| note.path.clone())));
+    }
+    for (note, name) in notes.iter().zip(names.iter()) {
+        let Some(name) = name else { continue };
+        for (embed, target) in wikilinks(&note.text) {
+            let target = without_extension(&target).to_string();
+            let linked = match targets.get(&target.to_lowercase()) {
+                Some(index) => names[*index].clone(),
+                None if embed => None,
+                None => Some(name_of(target.rsplit('/').next().unwrap_or(&target))).filter(|name| check_name(name).is_ok()),
+            };
+            if let Some(linked) = linked {
+                builder.tag(&linked, None);
+                builder.link(name, &linked, settings.link);
+            }
+        }
+        // `[[#Heading]]` is not a tag
+        for tag in notes::extract(&LINK.replace_all(&note.text, " ")) {
+            builder.tag(&tag, None);
+            builder.link(name, &tag, settings.tag);
+        }
+    }
+    builder.snapshot
+}
+
+fn without_extension(path: &str) -> &str {
+    let path = path.trim();
+    [".md", ".markdown"].iter()
+        .find_map(|extension| path.len().checked_sub(extension.len())
+            .filter(|start| path.is_char_boundary(*start) && path[*start..].eq_ignore_ascii_case(extension))
+            .map(|start| &path[..start]))
+        .unwrap_or(path)
+}
+
+fn name_of(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The `aliases` (or `alias`) of a front matter, where a single string
+/// is one alias unless separated by commas.
+fn aliases(matter: Option<&Value>) -> Vec<String> {
+    let Some(matter) = matter else { return vec![] };
+    ["aliases", "alias"].iter()
+        .flat_map(|key| match matter.get(*key) {
+            Some(Value::String(s)) if !s.contains(',') => vec![s.trim().to_string()],
+            value => notes::strings(value),
+        })
+        .filter(|alias| !alias.is_empty())
+        .collect()
+}
+
+/// The text of the first heading outside code.
+fn heading(body: &str) -> Option<String> {
+    let mut fenced = false;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") { fenced = !fenced; continue }
+        if fenced { continue }
+        if let Some(c) = ATX.captures(line.trim_end()) {
+            let text = c[1].trim();
+            if !text.is_empty() { return Some(text.to_string()) }
+        }
+    }
+    None
+}
+
+/// The targets of the `[[wikilinks]]` of a text outside code, without
+/// their headings, blocks and labels, and whether they are `![[embeds]]`.
+fn wikilinks(text: &str) -> Vec<(bool, String)> {
+    let mut links = vec![];
+    let mut fenced = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") { fenced = !fenced; continue }
+        if fenced { continue }
+        // the odd pieces between backticks are code spans
+        for piece in line.split('`').step_by(2) {
+            links.extend(WIKILINK.captures_iter(piece)
+                .map(|c| (!c[1].is_empty(), c[2].trim().to_string()))
+                .filter(|(_, target)| !target.is_empty()));
+        }
+    }
+    links
+}
+
+/// Reads the notes of the vault at `dir`, sorted by path. Hidden files
+/// and folders, such as `.obsidian` and `.trash`, are skipped.
+pub fn read(dir: &Path) -> anyhow::Result<Vec<Note>> {
+    let mut notes = vec![];
+    let walk = walkdir::WalkDir::new(dir).into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walk {
+        let entry = entry?;
+        if !entry.file_type().is_file() || !notes::is_note(entry.path()) { continue }
+        let path = entry.path().strip_prefix(dir)?.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        notes.push(Note { path, text: std::fs::read_to_string(entry.path())? });
+    }
+    notes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(notes)
+}
@@ -0,0 +1,219 @@
+//! Integrity checks of the `tags` and `relationship` tables.
+//!
+//! Neither backend guarantees that the links stay consistent: SQLite
+//! only enforces foreign keys when asked to, GlueSQL not at all, and
+//! [`crate::Tag::link_tags`] never checks its tags. [`check`] finds
+//!
+//! - links to or from a tag that does not exist,
+//! - links from a tag to itself,
+//! - tags whose names differ only by case, blanks or quoting,
+//! - weights outside `0 ~ 1`,
+//! - missing or non-finite weights,
+//! - derived links without a path of origin links behind them,
+//!
+//! and [`fsck`] can repair all but the duplicate tags, which need a
+//! decision on which name to keep, see [`crate::normalize::normalize_db`].
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::fsck::{check, Issue};
+//!
+//! let issues = check(
+//!     &["rust".into(), "Rust".into()],
+//!     &[("rust".into(), "rust".into(), Some(0.5), true)],
+//! );
+//! assert!(matches!(issues[0], Issue::SelfLoop { .. }));
+//! assert!(matches!(issues[1], Issue::DuplicateTags { .. }));
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use futures::executor::block_on;
+
+use crate::db::{self, crud::{Database, DatabaseError}};
+
+pub enum Issue {
+    DanglingLink { tag1: String, tag2: String, missing: String },
+    SelfLoop { tag: String },
+    DuplicateTags { names: Vec<String> },
+    WeightOutOfRange { tag1: String, tag2: String, weight: f64 },
+    InvalidWeight { tag1: String, tag2: String, weight: Option<f64> },
+    UnsupportedDerived { tag1: String, tag2: String },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DanglingLink { tag1, tag2, missing } =>
+                write!(f, "link {} -> {} refers to missing tag {}", tag1, tag2, missing),
+            Self::SelfLoop { tag } =>
+                write!(f, "tag {} links to itself", tag),
+            Self::DuplicateTags { names } =>
+                write!(f, "tags {} differ only by case or quoting", names.join(", ")),
+            Self::WeightOutOfRange { tag1, tag2, weight } =>
+                write!(f, "link {} -> {} has weight {} outside 0 ~ 1", tag1, tag2, weight),
+            Self::InvalidWeight { tag1, tag2, weight: Some(weight) } =>
+                write!(f, "link {} -> {} has weight {}", tag1, tag2, weight),
+            Self::InvalidWeight { tag1, tag2, weight: None } =>
+                write!(f, "link {} -> {} has no weight", tag1, tag2),
+            Self::UnsupportedDerived { tag1, tag2 } =>
+                write!(f, "derived link {} -> {} has no path of origin links", tag1, tag2),
+        }
+    }
+}
+
+impl Issue {
+    pub fn repairable(&self) -> bool {
+        !matches!(self, Self::DuplicateTags { .. })
+    }
+}
+
+pub struct Report {
+    pub issues: Vec<Issue>,
+    pub repaired: usize,
+}
+
+/// The form in which duplicate tag names are equal.
+fn duplicate_key(name: &str) -> String {
+    name.trim().trim_matches(|c| c == '\'' || c == '"').trim().to_lowercase()
+}
+
+/// Whether `to` can be reached from `from` over at least two origin links.
+fn has_origin_path(origin: &HashMap<&str, Vec<&str>>, from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<&str> = origin.get(from).into_iter().flatten().copied().collect();
+    while let Some(tag) = queue.pop_front() {
+        if !seen.insert(tag) { continue }
+        for next in origin.get(tag).into_iter().flatten() {
+            if *next == to { return true }
+            queue.push_back(next);
+        }
+    }
+    false
+}
+
+/// Finds the issues among raw tag names and
+/// `(tag1, tag2, weight, is_origin)` links.
+pub fn check(tags: &[String], links: &[(String, String, Option<f64>, bool)]) -> Vec<Issue> {
+    let mut issues = vec![];
+    let known: HashSet<&str> = tags.iter().map(String::as_str).collect();
+    let mut origin: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (tag1, tag2, _, is_origin) in links {
+        if *is_origin && tag1 != tag2 { origin.entry(tag1).or_default().push(tag2) }
+    }
+
+    for (tag1, tag2, weight, is_origin) in links {
+        if let Some(missing) = [tag1, tag2].into_iter().find(|t| !known.contains(t.as_str())) {
+            issues.push(Issue::DanglingLink { tag1: tag1.clone(), tag2: tag2.clone(), missing: missing.clone() });
+        } else if tag1 == tag2 {
+            issues.push(Issue::SelfLoop { tag: tag1.clone() });
+        } else {
+            match weight {
+                Some(weight) if !weight.is_finite() =>
+                    issues.push(Issue::InvalidWeight { tag1: tag1.clone(), tag2: tag2.clone(), weight: Some(*weight) }),
+                Some(weight) if !(0.0..=1.0).contains(weight) =>
+                    issues.push(Issue::WeightOutOfRange { tag1: tag1.clone(), tag2: tag2.clone(), weight: *weight }),
+                Some(_) => {},
+                None => issues.push(Issue::InvalidWeight { tag1: tag1.clone(), tag2: tag2.clone(), weight: None }),
+            }
+            if !*is_origin && !has_origin_path(&origin, tag1, tag2) {
+                issues.push(Issue::UnsupportedDerived { tag1: tag1.clone(), tag2: tag2.clone() });
+            }
+        }
+    }
+
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for tag in tags {
+        groups.entry(duplicate_key(tag)).or_default().push(tag.clone());
+    }
+    let mut duplicates: Vec<_> = groups.into_values().filter(|names| names.len() > 1).collect();
+    duplicates.sort();
+    issues.extend(duplicates.into_iter().map(|mut names| { names.sort(); Issue::DuplicateTags { names } }));
+    issues
+}
+
+/// Repairs an issue, and tells whether a link was changed; an earlier
+/// repair may have deleted it already.
+async fn repair(db: &Database, issue: &Issue) -> Result<bool, DatabaseError> {
+    let (tag1, tag2) = match issue {
+        Issue::DanglingLink { tag1, tag2, .. } | Issue::UnsupportedDerived { tag1, tag2 }
+            | Issue::WeightOutOfRange { tag1, tag2, .. } | Issue::InvalidWeight { tag1, tag2, .. } => (tag1, tag2),
+        Issue::SelfLoop { tag } => (tag, tag),
+        Issue::DuplicateTags { .. } => return Ok(false),
+    };
+    let link = format!("tag1 = {} AND tag2 = {}", db::quote(tag1), db::quote(tag2));
+    if db.read("relationship", &["tag1".to_string()], &link, "").await?.is_empty() { return Ok(false) }
+    match issue {
+        Issue::WeightOutOfRange { weight, .. } => {
+            db.execute(&format!("UPDATE relationship SET weight = {} WHERE {};", weight.clamp(0.0, 1.0), link)).await?;
+        },
+        _ => { db.execute(&format!("DELETE FROM relationship WHERE {};", link)).await?; },
+    }
+    Ok(true)
+}
+
+/// Checks the database, and with `repair` deletes the broken links and
+/// clamps the weights. A link can have several issues, so repairs run
+/// in the order of the report and later ones may find nothing to do;
+/// only the links changed are counted as repaired.
+pub async fn fsck(db: &Database, repair_issues: bool) -> Result<Report, DatabaseError> {
+    let tags = db.read("tags", &["tag_name".to_string()], "true", "").await?.get::<String>(0);
+    let links = db.read("relationship",
+        &["tag1".to_string(), "tag2".to_string(), "weight".to_string(), "is_origin".to_string()],
+        "true",
+        ""
+    ).await?;
+    let links: Vec<_> = itertools::izip!(
+        links.get::<String>(0),
+        links.get::<String>(1),
+        links.get::<Option<f64>>(2),
+        links.get::<bool>(3)
+    ).collect();
+    let issues = check(&tags, &links);
+    let mut repaired = 0;
+    if repair_issues {
+        for issue in issues.iter() {
+            if repair(db, issue).await? { repaired += 1 }
+        }
+    }
+    Ok(Report { issues, repaired })
+}
+
+/// The sync version of [`fsck`].
+pub fn fsck_sync(db: &Database, repair_issues: bool) -> Result<Report, DatabaseError> {
+    block_on(async { fsck(db, repair_issues).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(tag1: &str, tag2: &str, weight: f64, is_origin: bool) -> (String, String, Option<f64>, bool) {
+        (tag1.into(), tag2.into(), Some(weight), is_origin)
+    }
+
+    #[test]
+    fn test_check() {
+        let tags: Vec<String> = ["a", "b", "c", "'C'"].iter().map(|t| t.to_string()).collect();
+        let issues = check(&tags, &[
+            link("a", "b", 0.5, true),
+            link("b", "c", 1.5, true),
+            link("a", "c", 0.5, false),
+            link("c", "a", 0.5, false),
+            link("a", "ghost", 0.5, true),
+            link("a", "'C'", f64::INFINITY, true),
+            ("'C'".into(), "b".into(), None, true),
+        ]);
+        let found: Vec<_> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(found, vec![
+            "link b -> c has weight 1.5 outside 0 ~ 1",
+            "derived link c -> a has no path of origin links",
+            "link a -> ghost refers to missing tag ghost",
+            "link a -> 'C' has weight inf",
+            "link 'C' -> b has no weight",
+            "tags 'C', c differ only by case or quoting",
+        ]);
+        assert!(!issues[5].repairable());
+    }
+}
@@ -0,0 +1,337 @@
+//! The tag graph loaded into memory.
+//!
+//! Querying the database link by link is fine for a single tag but slow
+//! for anything that walks the whole graph. [`TagGraph`] reads the
+//! `tags` and `relationship` tables once and keeps them as adjacency
+//! lists indexed by tag id, for the queries, recommendations and
+//! analytics built on top of it.
+//!
+//! Each `Database` caches one graph, loaded on the first call to
+//! `Database::graph`. The `Tag` functions update it along with the
+//! tables, through `Database::create_with` and `Database::update_with`;
+//! any other write to the two tables drops it, as do raw statements
+//! through `Database::execute`. Writes of another process are only
+//! seen after `Database::invalidate_graph`.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::graph::TagGraph;
+//!
+//! let graph = TagGraph::from_parts(
+//!     vec![("rust".into(), None), ("cli".into(), None)],
+//!     vec![("rust".into(), "cli".into(), 0.8, true)],
+//! );
+//! let rust = graph.id("rust").unwrap();
+//! assert_eq!(graph.name(graph.neighbors(rust)[0].to), "cli");
+//! ```
+
+use std::collections::{BinaryHeap, HashMap};
+
+use futures::executor::block_on;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::db::crud::{Database, DatabaseError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    pub to: usize,
+    pub weight: f64,
+    pub is_origin: bool,
+}
+
+/// A tag as drawn by the web graph and served by `frictune-server`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DrawNode {
+    pub id: String,
+    pub desc: String,
+    /// see [`crate::cluster`]
+    pub cluster: usize,
+}
+
+/// A link as drawn by the web graph and served by `frictune-server`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DrawLink {
+    pub source: String,
+    pub target: String,
+    pub strength: f64,
+}
+
+#[derive(Clone, Default)]
+pub struct TagGraph {
+    names: Vec<String>,
+    descs: Vec<Option<String>>,
+    ids: HashMap<String, usize>,
+    out: Vec<Vec<Link>>,
+}
+
+impl TagGraph {
+    /// Builds a graph from `(name, description)` tags and
+    /// `(tag1, tag2, weight, is_origin)` links. Tags only mentioned by
+    /// a link are added without a description.
+    pub fn from_parts(tags: Vec<(String, Option<String>)>, links: Vec<(String, String, f64, bool)>) -> Self {
+        let mut graph = TagGraph::default();
+        for (name, desc) in tags {
+            let id = graph.insert(&name);
+            graph.descs[id] = desc.filter(|d| !d.is_empty());
+        }
+        for (tag1, tag2, weight, is_origin) in links {
+            let from = graph.insert(&tag1);
+            let to = graph.insert(&tag2);
+            graph.out[from].push(Link { to, weight, is_origin });
+        }
+        graph
+    }
+
+    /// Reads the whole graph from the database, without the links that
+    /// have no weight, which [`crate::fsck`] reports.
+    pub async fn load(db: &Database) -> Result<Self, DatabaseError> {
+        let tags = db.read("tags",
+            &["tag_name".to_string(), "info".to_string()],
+            "true",
+            ""
+        ).await?;
+        let links = db.read("relationship",
+            &["tag1".to_string(), "tag2".to_string(), "weight".to_string(), "is_origin".to_string()],
+            "true",
+            ""
+        ).await?;
+        Ok(TagGraph::from_parts(
+            tags.get::<String>(0).into_iter().zip(tags.get::<Option<String>>(1)).collect(),
+            itertools::izip!(
+                links.get::<String>(0),
+                links.get::<String>(1),
+                links.get::<Option<f64>>(2),
+                links.get::<bool>(3)
+            ).filter_map(|(tag1, tag2, weight, is_origin)| Some((tag1, tag2, weight?, is_origin))).collect(),
+        ))
+    }
+
+    /// The sync version of [`TagGraph::load`].
+    pub fn load_sync(db: &Database) -> Result<Self, DatabaseError> {
+        block_on(async { TagGraph::load(db).await })
+    }
+
+    /// Returns the id of the tag, adding it if it is new.
+    fn insert(&mut self, name: &str) -> usize {
+        if let Some(id) = self.ids.get(name) { return *id }
+        let id = self.names.len();
+        self.names.push(name.into());
+        self.descs.push(None);
+        self.out.push(vec![]);
+        self.ids.insert(name.into(), id);
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn desc(&self, id: usize) -> Option<&str> {
+        self.descs[id].as_deref()
+    }
+
+    /// The outgoing links of a tag, in the order they were read.
+    pub fn neighbors(&self, id: usize) -> &[Link] {
+        &self.out[id]
+    }
+
+    /// The neighbors of every tag, ignoring link directions. A pair
+    /// linked both ways gets the heavier weight; self-loops and
+    /// non-positive weights are dropped, and so are derived links when
+    /// `origin_only` is set.
+    pub fn undirected(&self, origin_only: bool) -> Vec<Vec<(usize, f64)>> {
+        let mut adjacency: Vec<HashMap<usize, f64>> = vec![HashMap::new(); self.len()];
+        for (from, link) in self.links() {
+            if link.weight <= 0.0 || from == link.to || (origin_only && !link.is_origin) { continue }
+            for (a, b) in [(from, link.to), (link.to, from)] {
+                let weight = adjacency[a].entry(b).or_insert(0.0);
+                *weight = link.weight.max(*weight);
+            }
+        }
+        adjacency.into_iter().map(|neighbors| {
+            let mut neighbors: Vec<_> = neighbors.into_iter().collect();
+            neighbors.sort_by_key(|(id, _)| *id);
+            neighbors
+        }).collect()
+    }
+
+    /// The link from one tag to another, if any.
+    pub fn link(&self, from: usize, to: usize) -> Option<&Link> {
+        self.out[from].iter().find(|link| link.to == to)
+    }
+
+    /// The tags at most `depth` links away from `from` in either
+    /// direction, `from` included, by id. Only origin links are
+    /// followed with `origin_only`.
+    pub fn around(&self, from: usize, depth: usize, origin_only: bool) -> Vec<usize> {
+        let adjacency = self.undirected(origin_only);
+        let mut seen = vec![false; self.len()];
+        seen[from] = true;
+        let mut frontier = vec![from];
+        for _ in 0..depth {
+            frontier = frontier.iter()
+                .flat_map(|id| adjacency[*id].iter().map(|(to, _)| *to))
+                .filter(|to| !std::mem::replace(&mut seen[*to], true))
+                .collect();
+        }
+        (0..self.len()).filter(|id| seen[*id]).collect()
+    }
+
+    /// The names of the tags `from` links to, by increasing weight.
+    pub fn neighbor_names(&self, from: &str) -> Vec<&str> {
+        let Some(id) = self.id(from) else { return vec![] };
+        let mut links: Vec<_> = self.out[id].iter().collect();
+        links.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+        links.into_iter().map(|link| self.name(link.to)).collect()
+    }
+
+    /// Adds a tag if it is new, and sets its description if one is given.
+    pub fn add_tag(&mut self, name: &str, desc: Option<String>) {
+        let id = self.insert(name);
+        if let Some(desc) = desc {
+            self.descs[id] = Some(desc).filter(|d| !d.is_empty());
+        }
+    }
+
+    /// Adds or replaces a link, like [`crate::Tag::link_tags`] does.
+    pub fn set_link(&mut self, tag1: &str, tag2: &str, weight: f64, is_origin: bool) {
+        let from = self.insert(tag1);
+        let to = self.insert(tag2);
+        match self.out[from].iter_mut().find(|link| link.to == to) {
+            Some(link) => { link.weight = weight; link.is_origin = is_origin; },
+            None => self.out[from].push(Link { to, weight, is_origin }),
+        }
+    }
+
+    /// Adds a derived link, or raises an existing derived one, like
+    /// [`crate::Tag::auto_update_links`] does. Origin links are kept.
+    pub fn raise_derived_link(&mut self, tag1: &str, tag2: &str, weight: f64) {
+        let from = self.insert(tag1);
+        let to = self.insert(tag2);
+        match self.out[from].iter_mut().find(|link| link.to == to) {
+            Some(link) => if !link.is_origin && weight > link.weight { link.weight = weight },
+            None => self.out[from].push(Link { to, weight, is_origin: false }),
+        }
+    }
+
+    /// The path of origin links from one tag to another with the
+    /// largest product of weights, which is the weight a derived link
+    /// between them would get, and that product.
+    pub fn strongest_path(&self, from: usize, to: usize) -> Option<(Vec<usize>, f64)> {
+        // Dijkstra over -ln(weight), which turns the largest product
+        // into the shortest sum
+        #[derive(PartialEq)]
+        struct Step(f64, usize);
+        impl Eq for Step {}
+        impl PartialOrd for Step {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) }
+        }
+        impl Ord for Step {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering { other.0.total_cmp(&self.0) }
+        }
+
+        let mut cost = vec![f64::INFINITY; self.len()];
+        let mut prev = vec![usize::MAX; self.len()];
+        let mut heap = BinaryHeap::from([Step(0.0, from)]);
+        cost[from] = 0.0;
+        while let Some(Step(c, id)) = heap.pop() {
+            if id == to { break }
+            if c > cost[id] { continue }
+            for link in self.out[id].iter().filter(|l| l.is_origin && l.weight > 0.0) {
+                let next = c - link.weight.min(1.0).ln();
+                if next < cost[link.to] {
+                    cost[link.to] = next;
+                    prev[link.to] = id;
+                    heap.push(Step(next, link.to));
+                }
+            }
+        }
+        if !cost[to].is_finite() { return None }
+        let mut path = vec![to];
+        while *path.last().unwrap() != from {
+            path.push(prev[*path.last().unwrap()]);
+        }
+        path.reverse();
+        Some((path, (-cost[to]).exp()))
+    }
+
+    /// All links as `(from, link)` pairs.
+    pub fn links(&self) -> impl Iterator<Item = (usize, &Link)> {
+        self.out.iter().enumerate()
+            .flat_map(|(from, links)| links.iter().map(move |link| (from, link)))
+    }
+
+    /// Every tag with its cluster, ready to be drawn.
+    pub fn draw_nodes(&self) -> Vec<DrawNode> {
+        let clusters = crate::cluster::membership(self);
+        (0..self.len()).map(|id| DrawNode {
+            id: self.name(id).into(),
+            desc: self.desc(id).unwrap_or_default().into(),
+            cluster: clusters.get(self.name(id)).copied().unwrap_or_default(),
+        }).collect()
+    }
+
+    /// Every link, ready to be drawn.
+    pub fn draw_links(&self) -> Vec<DrawLink> {
+        self.links().map(|(from, link)| DrawLink {
+            source: self.name(from).into(),
+            target: self.name(link.to).into(),
+            strength: link.weight,
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_and_path() {
+        let mut graph = TagGraph::from_parts(vec![], vec![
+            ("a".into(), "b".into(), 0.9, true),
+            ("b".into(), "c".into(), 0.9, true),
+            ("a".into(), "c".into(), 0.5, true),
+        ]);
+        let (a, c) = (graph.id("a").unwrap(), graph.id("c").unwrap());
+        let (path, weight) = graph.strongest_path(a, c).unwrap();
+        assert_eq!(path.iter().map(|id| graph.name(*id)).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert!((weight - 0.81).abs() < 1e-9);
+        assert!(graph.strongest_path(c, a).is_none());
+
+        graph.raise_derived_link("a", "c", 0.81);
+        assert_eq!(graph.link(a, c).unwrap().weight, 0.5);
+        graph.set_link("a", "c", 0.95, true);
+        graph.add_tag("d", Some("https://example.com".into()));
+        assert_eq!(graph.neighbor_names("a"), vec!["b", "c"]);
+        assert_eq!(graph.desc(graph.id("d").unwrap()), Some("https://example.com"));
+        assert_eq!(graph.strongest_path(a, c).unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn test_around() {
+        let graph = TagGraph::from_parts(vec![], vec![
+            ("a".into(), "b".into(), 0.9, true),
+            ("c".into(), "b".into(), 0.9, false),
+            ("c".into(), "d".into(), 0.9, true),
+        ]);
+        let names = |ids: Vec<usize>| ids.into_iter().map(|id| graph.name(id)).collect::<Vec<_>>();
+        let a = graph.id("a").unwrap();
+        assert_eq!(names(graph.around(a, 0, false)), vec!["a"]);
+        assert_eq!(names(graph.around(a, 2, false)), vec!["a", "b", "c"]);
+        assert_eq!(names(graph.around(a, 2, true)), vec!["a", "b"]);
+        assert_eq!(names(graph.around(graph.id("b").unwrap(), 1, false)), vec!["a", "b", "c"]);
+    }
+}
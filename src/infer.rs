@@ -0,0 +1,240 @@
+//! Link weights inferred from how often tags appear together.
+//!
+//! `tune_html` records the tags of every `{{ ... }}` block of a post in
+//! the `post_blocks` table. From these blocks, [`infer_weights`] derives
+//! a weight for every pair of tags sharing a block:
+//!
+//! - [`Method::Jaccard`]: blocks with both tags / blocks with either;
+//! - [`Method::Conditional`]: blocks with both / blocks with `tag1`,
+//!   the only measure that differs between the two directions;
+//! - [`Method::Pmi`]: the normalized pointwise mutual information,
+//!   negative values cut to 0.
+//!
+//! The results are kept apart from the hand-typed weights, in the
+//! `inferred_link` table, so that they can be compared with them
+//! ([`compare`]) or blended into them ([`blend`]).
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::infer::{infer_weights, Method};
+//!
+//! let blocks = vec![
+//!     vec!["git".to_string(), "GitHub".to_string()],
+//!     vec!["git".to_string()],
+//! ];
+//! let weights = infer_weights(&blocks, Method::Conditional);
+//! let git_github = weights.iter().find(|i| i.tag1 == "git").unwrap();
+//! assert_eq!(git_github.weight, 0.5);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use futures::executor::block_on;
+
+use crate::{db::{self, crud::{Database, DatabaseError}}, markup::Block, Tag};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Pmi,
+    Jaccard,
+    Conditional,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pmi => "pmi",
+            Self::Jaccard => "jaccard",
+            Self::Conditional => "conditional",
+        }
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pmi" => Ok(Self::Pmi),
+            "jaccard" => Ok(Self::Jaccard),
+            "conditional" => Ok(Self::Conditional),
+            other => Err(format!("unknown method {}", other)),
+        }
+    }
+}
+
+/// A directed inferred link.
+pub struct Inferred {
+    pub tag1: String,
+    pub tag2: String,
+    pub weight: f64,
+}
+
+/// An inferred link beside the origin link between the same tags.
+pub struct Comparison {
+    pub tag1: String,
+    pub tag2: String,
+    pub manual: Option<f64>,
+    pub inferred: f64,
+}
+
+/// Weights for both directions of every pair of tags sharing a block,
+/// ordered by `tag1` and `tag2`.
+pub fn infer_weights(blocks: &[Vec<String>], method: Method) -> Vec<Inferred> {
+    let total = blocks.len() as f64;
+    let mut single: BTreeMap<&str, f64> = BTreeMap::new();
+    let mut pairs: BTreeMap<(&str, &str), f64> = BTreeMap::new();
+    for block in blocks {
+        let tags: BTreeSet<&str> = block.iter().map(String::as_str).collect();
+        for a in tags.iter() {
+            *single.entry(a).or_default() += 1.0;
+            for b in tags.range::<&str, _>((std::ops::Bound::Excluded(a), std::ops::Bound::Unbounded)) {
+                *pairs.entry((a, b)).or_default() += 1.0;
+            }
+        }
+    }
+
+    let mut inferred = vec![];
+    for ((a, b), both) in pairs {
+        let (na, nb) = (single[a], single[b]);
+        let weights = match method {
+            Method::Jaccard => { let w = both / (na + nb - both); (w, w) },
+            Method::Conditional => (both / na, both / nb),
+            Method::Pmi => {
+                let p = both / total;
+                let w = if p >= 1.0 { 1.0 }
+                    else { ((p / (na / total * nb / total)).ln() / -p.ln()).max(0.0) };
+                (w, w)
+            },
+        };
+        inferred.push(Inferred { tag1: a.into(), tag2: b.into(), weight: weights.0 });
+        inferred.push(Inferred { tag1: b.into(), tag2: a.into(), weight: weights.1 });
+    }
+    inferred.sort_by(|x, y| (&x.tag1, &x.tag2).cmp(&(&y.tag1, &y.tag2)));
+    inferred
+}
+
+/// Replaces the recorded blocks of a post.
+pub async fn record_post(db: &Database, post: &str, blocks: &[Block]) -> Result<(), DatabaseError> {
+    db.delete("post_blocks", "post", &db::quote(post)).await?;
+    for (idx, block) in blocks.iter().enumerate() {
+        let names: BTreeSet<String> = block.tag_names().into_iter().collect();
+        for name in names {
+            db.create("post_blocks",
+                &["post".to_string(), "block".to_string(), "tag_name".to_string()],
+                &[db::quote(post), idx.to_string(), db::quote(&name)]
+            ).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The sync version of [`record_post`].
+pub fn record_post_sync(db: &Database, post: &str, blocks: &[Block]) {
+    if let Err(e) = block_on(async { record_post(db, post, blocks).await }) {
+        crate::logger::warn(e.to_string());
+    }
+}
+
+/// All recorded blocks as lists of tag names.
+pub async fn load_blocks(db: &Database) -> Result<Vec<Vec<String>>, DatabaseError> {
+    let rows = db.read("post_blocks",
+        &["post".to_string(), "block".to_string(), "tag_name".to_string()],
+        "true",
+        ""
+    ).await?;
+    let mut blocks: BTreeMap<(String, i64), Vec<String>> = BTreeMap::new();
+    for (post, block, tag) in itertools::izip!(rows.get::<String>(0), rows.get::<i64>(1), rows.get::<String>(2)) {
+        blocks.entry((post, block)).or_default().push(tag);
+    }
+    Ok(blocks.into_values().collect())
+}
+
+/// Computes the weights of `method` from all recorded blocks and
+/// stores them in `inferred_link`, replacing the previous ones.
+pub async fn infer(db: &Database, method: Method) -> Result<Vec<Inferred>, DatabaseError> {
+    let inferred = infer_weights(&load_blocks(db).await?, method);
+    db.delete("inferred_link", "method", &db::quote(method.as_str())).await?;
+    for link in inferred.iter() {
+        db.create("inferred_link",
+            &["tag1".to_string(), "tag2".to_string(), "method".to_string(), "weight".to_string()],
+            &[db::quote(&link.tag1), db::quote(&link.tag2), db::quote(method.as_str()), link.weight.to_string()]
+        ).await?;
+    }
+    Ok(inferred)
+}
+
+/// The stored weights of `method` beside the origin weights.
+pub async fn compare(db: &Database, method: Method) -> Result<Vec<Comparison>, DatabaseError> {
+    let inferred = db.read("inferred_link",
+        &["tag1".to_string(), "tag2".to_string(), "weight".to_string()],
+        &format!("method = {}", db::quote(method.as_str())),
+        ""
+    ).await?;
+    let manual = db.read("relationship",
+        &["tag1".to_string(), "tag2".to_string(), "weight".to_string()],
+        "is_origin = true",
+        ""
+    ).await?;
+    let manual: BTreeMap<(String, String), f64> = itertools::izip!(
+        manual.get::<String>(0), manual.get::<String>(1), manual.get::<f64>(2)
+    ).map(|(a, b, w)| ((a, b), w)).collect();
+    let mut comparisons: Vec<_> = itertools::izip!(
+        inferred.get::<String>(0), inferred.get::<String>(1), inferred.get::<f64>(2)
+    ).map(|(tag1, tag2, weight)| Comparison {
+        manual: manual.get(&(tag1.clone(), tag2.clone())).copied(),
+        tag1,
+        tag2,
+        inferred: weight,
+    }).collect();
+    comparisons.sort_by(|x, y| (&x.tag1, &x.tag2).cmp(&(&y.tag1, &y.tag2)));
+    Ok(comparisons)
+}
+
+/// Moves the origin weights towards the inferred ones, to
+/// `alpha * inferred + (1 - alpha) * manual`. Only pairs that already
+/// have an origin link are touched. Returns how many links changed.
+pub async fn blend(db: &Database, method: Method, alpha: f64) -> Result<usize, DatabaseError> {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let mut changed = 0;
+    for comparison in compare(db, method).await? {
+        if let Some(manual) = comparison.manual {
+            let weight = alpha * comparison.inferred + (1.0 - alpha) * manual;
+            Tag::new(&comparison.tag1).link_tags(db, &Tag::new(&comparison.tag2), weight).await?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks() -> Vec<Vec<String>> {
+        [vec!["a", "b"], vec!["a", "b", "b"], vec!["a", "c"], vec!["d"]].into_iter()
+            .map(|b| b.into_iter().map(String::from).collect())
+            .collect()
+    }
+
+    fn weight(inferred: &[Inferred], tag1: &str, tag2: &str) -> f64 {
+        inferred.iter().find(|i| i.tag1 == tag1 && i.tag2 == tag2).unwrap().weight
+    }
+
+    #[test]
+    fn test_methods() {
+        let jaccard = infer_weights(&blocks(), Method::Jaccard);
+        assert_eq!(jaccard.len(), 4);
+        assert_eq!(weight(&jaccard, "a", "b"), 2.0 / 3.0);
+        assert_eq!(weight(&jaccard, "b", "a"), 2.0 / 3.0);
+
+        let conditional = infer_weights(&blocks(), Method::Conditional);
+        assert_eq!(weight(&conditional, "b", "a"), 1.0);
+        assert_eq!(weight(&conditional, "a", "c"), 1.0 / 3.0);
+
+        let pmi = infer_weights(&blocks(), Method::Pmi);
+        assert!(weight(&pmi, "a", "b") > 0.0);
+        assert!(weight(&pmi, "a", "b") <= 1.0);
+    }
+}
@@ -1,8 +1,35 @@
+pub mod cluster;
 pub mod db;
+pub mod export;
+pub mod fsck;
+pub mod graph;
+pub mod infer;
 pub mod logger;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lsp;
+pub mod markup;
+pub mod normalize;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod notes;
+pub mod query;
+pub mod rank;
+pub mod recommend;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render;
+pub mod resolve;
+pub mod rpc;
+pub mod search;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod store;
+pub mod suggest;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
 
 use db::crud::{DatabaseResult, DatabaseError};
 
+use std::borrow::Cow;
+
 use futures::executor::block_on;
 use logger::watch;
 
@@ -33,7 +60,7 @@ impl MakeTag for Tag {
 
 impl MakeTag for String {
     fn get_name(&self) -> String {
-        format!("'{}'", self)
+        db::quote(&normalize::normalize(self))
     }
 
     fn get_desc(&self) -> Option<String> {
@@ -41,19 +68,34 @@ impl MakeTag for String {
     }
 
     fn get_tag(&self) -> Tag {
-        Tag { name: format!("'{}'", self), desc: None }
+        Tag::new(self)
     }
 }
 
 impl Tag {
+    /// A tag with its name normalized by the installed
+    /// [`normalize::Policy`].
     pub fn new(name: &str) -> Self {
-        Tag { name: format!("'{}'", name), desc: None }
+        Tag { name: db::quote(&normalize::normalize(name)), desc: None }
+    }
+
+    /// The name as it was given, without the quoting.
+    pub fn raw_name(&self) -> Cow<'_, str> {
+        match self.name.strip_prefix('\'').and_then(|name| name.strip_suffix('\'')) {
+            Some(name) if !name.contains('\'') => Cow::Borrowed(name),
+            _ => Cow::Owned(db::unquote(&self.name)),
+        }
+    }
+
+    /// A handle on this tag, which the `Tag` functions are built on.
+    fn on<'d>(&self, db: &'d db::crud::Database) -> store::TagRef<'d> {
+        store::TagRef::new(db, self.get_tag())
     }
 
     pub fn new_with_desc(name: &str, desc: Option<String>) -> Self {
         let desc = desc.unwrap_or_default();
         Tag {
-            name: format!("'{}'", name),
+            name: db::quote(&normalize::normalize(name)),
             desc: if !desc.is_empty() { Some(desc) }
                 else { None },
         }
@@ -69,53 +111,23 @@ impl Tag {
     /// ```
     /// use std::collections::HashMap;
     /// futures::executor::block_on(async {
-    ///     let mut conn = frictune::db::crud::Database::new("./sample.db").await.unwrap();
+    ///     let conn = frictune::db::crud::Database::new("./sample.db").await.unwrap();
     ///     let sample = frictune::Tag { name: "'sample'".to_string(), desc: None };
-    ///     sample.add_tag::<String>(&mut conn, &[]).await;
+    ///     sample.add_tag::<String>(&conn, &[]).await;
     ///     let sample2 = frictune::Tag { name: "'sample2'".to_string(), desc: None };
-    ///     sample2.add_tag(&mut conn, &[(String::from("sample"), 0.4)]).await;
-    ///     assert_eq!(frictune::Tag::query_relation(&mut conn, &sample2, &sample).await.unwrap(), 0.4);
+    ///     sample2.add_tag(&conn, &[(String::from("sample"), 0.4)]).await;
+    ///     assert_eq!(frictune::Tag::query_relation(&conn, &sample2, &sample).await.unwrap(), 0.4);
     /// });
     /// ```
-    pub async fn add_tag<T: MakeTag>(&self, db: &mut db::crud::Database, name_weight_pairs: &[(T, f32)]) -> Result<DatabaseResult, DatabaseError> {
-        match if let Some(words) = self.desc.clone() {
-                db.create("tags", &[String::from("tag_name"), String::from("info")], &[self.name.clone(), words]).await
-            } else {
-                db.create("tags", &[String::from("tag_name")], &[self.name.clone()]).await
-            }
-        {
-            Ok(_) => { }
-            Err(e) => {
-                logger::warn(e.to_string());
-                if let DatabaseError::UniqueViolation = e { }
-                else { logger::warn(e.to_string()); panic!() }
-            },
-        }
-
-        for (k, v) in name_weight_pairs {
-            match if let Some(words) = k.get_desc().clone() {
-                    db.create("tags", &[String::from("tag_name"), String::from("info")], &[k.get_name().clone(), words]).await
-                } else {
-                    db.create("tags", &[String::from("tag_name")], &[k.get_name().clone()]).await
-                }
-            {
-                Ok(_) => { }
-                Err(e) => {
-                    logger::warn(e.to_string());
-                    if let DatabaseError::UniqueViolation = e { }
-                    else { logger::warn(e.to_string()); panic!() }
-                },
-            }
-            logger::watch(self.link_tags(db, k, *v).await);
-            self.auto_update_links(db).await;
-        }
-
+    pub async fn add_tag<T: MakeTag>(&self, db: &db::crud::Database, name_weight_pairs: &[(T, f64)]) -> Result<DatabaseResult, DatabaseError> {
+        let links: Vec<_> = name_weight_pairs.iter().map(|(k, v)| (k.get_tag(), *v)).collect();
+        self.on(db).add_tags(&links).await?;
         // TODO: change the return value later
         Ok(DatabaseResult::Success("add_tag successful".into()))
     }
 
     /// The non-async version of `add_tag`
-    pub fn add_sync<T: MakeTag>(&self, db: &mut db::crud::Database, name_weight_pairs: &[(T, f32)]) {
+    pub fn add_sync<T: MakeTag>(&self, db: &db::crud::Database, name_weight_pairs: &[(T, f64)]) {
         block_on(async { watch(self.add_tag(db, name_weight_pairs).await) });
     }
 
@@ -124,55 +136,8 @@ impl Tag {
     /// 
     /// # Example (TODO)
     /// 
-    pub async fn auto_update_links(&self, db: &mut db::crud::Database) {
-        // TODO: a reverse-way propagation
-        let affected_tags: Vec<(String, f32)> = match db.read(
-            "relationship",
-            &["tag2".to_string(), "weight".to_string()],
-            &format!("tag1 = {}", self.name),
-            ""
-        ).await {
-            Ok(things) => 
-                things.get::<String>(0).into_iter().zip(
-                    things.get::<f32>(1)
-                ).collect(),
-            Err(e) => { logger::warn(e.to_string()); panic!() }
-        };
-
-        for (tag, weight) in affected_tags {
-            for (n, w) in match db.read(
-                "relationship",
-                &[String::from("*")],
-                &format!("tag1 = '{}'", tag),
-                ""
-            ).await
-            {
-                Ok(things) => { if things.len() == 0 {
-                        continue
-                    } else {
-                        things.get::<String>(0).into_iter().zip(
-                            things.get::<f32>(2)
-                        )
-                    }
-                }
-                Err(e) => { logger::warn(e.to_string()); continue }
-            } {
-                let entries = [String::from("tag1"), String::from("tag2"), String::from("weight"), String::from("is_origin")];
-                let data = [
-                    self.name.clone(),
-                    format!("'{}'", n), 
-                    (w * weight).to_string(),
-                    String::from("false")];
-                logger::watch(db.update(
-                    "relationship", 
-                    &entries,
-                    &data,
-                    &entries[2..],
-                    &data[2..],
-                    "excluded.weight > weight AND is_origin = false"
-                ).await);
-            };
-        }
+    pub async fn auto_update_links(&self, db: &db::crud::Database) {
+        self.on(db).propagate().await
     }
 
     /// The function will apply [`crate::Tag::auto_update_links`]
@@ -185,19 +150,16 @@ impl Tag {
     /// 
     /// (TODO)
     /// make it recursively update all links
-    pub async fn update_all_links(db: &mut db::crud::Database) {
-        for name in match db.read(
-            "tags",
-            &["tag_name".to_string()],
-            &format!("true"),
-            ""
-        ).await {
-            Ok(vrow) => vrow.get::<String>(0),
+    pub async fn update_all_links(db: &db::crud::Database) {
+        match store::names(db).await {
+            Ok(names) => for name in names {
+                Tag { name: db::quote(&name), desc: None }.auto_update_links(db).await;
+            },
             Err(e) => { logger::warn(e.to_string()); panic!() }
-        } { Tag { name, desc: None }.auto_update_links(db).await; }
+        }
     }
 
-    pub async fn force_update_all_links(db: &mut db::crud::Database) {
+    pub async fn force_update_all_links(db: &db::crud::Database) {
         logger::watch(db.delete("relationship", "is_origin", "false").await);
         Tag::update_all_links(db).await;
     }
@@ -207,24 +169,19 @@ impl Tag {
     /// as a hyperlink on the web.
     /// 
     /// This function can modify the description of this tag.
-    pub async fn modify_tag(&self, db: &mut db::crud::Database, desc: &str) -> Result<DatabaseResult, DatabaseError> {
-        let entry = ["tag_name".to_string(), "info".to_string()];
-        let data = [self.get_name(), desc.into()];
-        db.update("tags", &entry, &data, &entry[1..], &data[1..], "true").await
+    pub async fn modify_tag(&self, db: &db::crud::Database, desc: &str) -> Result<DatabaseResult, DatabaseError> {
+        self.on(db).set_info(desc).await
     }
 
-    pub fn mod_sync(&self, db: &mut db::crud::Database, desc: &str) {
+    pub fn mod_sync(&self, db: &db::crud::Database, desc: &str) {
         block_on(async { watch(self.modify_tag(db, desc).await) });
     }
 
-    pub async fn remove_tag(&self, db: &mut db::crud::Database) -> Result<DatabaseResult, DatabaseError> {
-        let res1 = db.delete("relationship", "tag1", &self.name).await;
-        let res2 = db.delete("relationship", "tag2", &self.name).await;
-        if res1.is_ok() && res2.is_ok() { db.delete("tags", "tag_name", &self.name).await }
-        else { res1 }
+    pub async fn remove_tag(&self, db: &db::crud::Database) -> Result<DatabaseResult, DatabaseError> {
+        self.on(db).delete().await
     }
 
-    pub fn rem_sync(&self, db: &mut db::crud::Database) {
+    pub fn rem_sync(&self, db: &db::crud::Database) {
         block_on(async { watch(self.remove_tag(db).await) });
     }
 
@@ -234,69 +191,49 @@ impl Tag {
     /// > **WARNING**
     /// 
     /// This function does not check the tags's existence. Be sure to
-    /// add them first.
-    pub async fn link_tags<T: MakeTag>(&self, db: &mut db::crud::Database, target: &T, ratio: f32) -> Result<DatabaseResult, DatabaseError> {
-        let entries = [String::from("tag1"), String::from("tag2"), String::from("weight"), String::from("is_origin")];
-        let data = [self.name.clone(), format!("{}", target.get_name()), ratio.to_string(), String::from("true")];
-        
-        match db.update(
-            "relationship", 
-            &entries,
-            &data,
-            &entries[2..],
-            &data[2..],
-            "true"
-        ).await
-        {
-            Ok(res) => { self.auto_update_links(db).await; Ok(res.into()) },
-            Err(e) => Err(e)
-        }
+    /// add them first, e.g. after checking with [`crate::Tag::exists`]
+    /// or [`crate::resolve::Resolver`].
+    pub async fn link_tags<T: MakeTag>(&self, db: &db::crud::Database, target: &T, ratio: f64) -> Result<DatabaseResult, DatabaseError> {
+        self.on(db).link_tag(&target.get_tag(), ratio).await
     }
 
-    pub fn link_sync<T: MakeTag>(&self, db: &mut db::crud::Database, target: &T, ratio: f32) {
+    pub fn link_sync<T: MakeTag>(&self, db: &db::crud::Database, target: &T, ratio: f64) {
         block_on(async { watch(self.link_tags(db, target, ratio).await) });
     }
 
-    // TODO: change the f32 to f64
-    pub async fn query_relation<T1: MakeTag, T2: MakeTag>(db: &mut db::crud::Database, tag1: &T1, tag2: &T2) -> Option<f32> {
-        match db.read("relationship",
-            &[String::from("*")],
-            &format!("tag1 = {} AND tag2 = {}", tag1.get_name(), tag2.get_name()),
-            ""
-        ).await {
-            Ok(things) => {
-                if things.len() != 1 { logger::warn(String::from("other than one queryed")); panic!() }
-                things.get::<f32>(2).get(0).copied()
-            },
+    /// Removes the link from this tag to `target`. The links derived
+    /// from this tag are recomputed without it.
+    pub async fn unlink_tags<T: MakeTag>(&self, db: &db::crud::Database, target: &T) -> Result<DatabaseResult, DatabaseError> {
+        self.on(db).unlink_tag(&target.get_tag()).await
+    }
+
+    /// The weight of the link from `tag1` to `tag2`, if any.
+    pub async fn query_relation<T1: MakeTag, T2: MakeTag>(db: &db::crud::Database, tag1: &T1, tag2: &T2) -> Option<f64> {
+        match tag1.get_tag().on(db).weight_to_tag(&tag2.get_tag()).await {
+            Ok(weight) => weight,
             Err(e) => { logger::warn(e.to_string()); panic!() }
         }
     }
 
-    pub fn query_sync<T1: MakeTag, T2: MakeTag>(db: &mut db::crud::Database, tag1: &T1, tag2: &T2) -> Option<f32> {
+    pub fn query_sync<T1: MakeTag, T2: MakeTag>(db: &db::crud::Database, tag1: &T1, tag2: &T2) -> Option<f64> {
         block_on(async { Tag::query_relation(db, tag1, tag2).await })
     }
 
-    /// This function retrieves all tags with a non-zero weight link
-    /// with this tag, ordered descendently.
-    pub async fn query_top_related(&self, db: &mut db::crud::Database) -> Vec<String> {
-        match db.read(
-            "relationship",
-            &["tag2".into()],
-            &format!("tag1 = {}", self.name),
-            "ORDER BY weight"
-        ).await {
-            Ok(things) => 
-                things.get::<String>(0),
+    /// This function retrieves all tags this tag links to, ordered by
+    /// increasing weight.
+    pub async fn query_top_related(&self, db: &db::crud::Database) -> Vec<String> {
+        match db.graph().await {
+            Ok(graph) => graph.neighbor_names(&self.raw_name()).into_iter().map(String::from).collect(),
             Err(e) => { logger::warn(e.to_string()); panic!() }
         }
     }
 
     /// The sync version of [`crate::Tag::query_top_related`].
-    pub fn qtr_sync(&self, db: &mut db::crud::Database) -> Vec<String> {
+    pub fn qtr_sync(&self, db: &db::crud::Database) -> Vec<String> {
         block_on(async { self.query_top_related(db).await })
     }
 
-    pub async fn query_desc(&self, db: &mut db::crud::Database) -> Option<String> {
+    pub async fn query_desc(&self, db: &db::crud::Database) -> Option<String> {
         match db.read(
             "tags", 
             &[String::from("info")], 
@@ -306,42 +243,44 @@ impl Tag {
             Ok(things) => {
                 if things.len() != 1 { logger::warn(String::from("other than one queryed"));
                 logger::warn(things.get::<String>(0).join("\n")); panic!() }
-                things.get::<String>(0).get(0).cloned()
+                things.get::<String>(0).first().cloned()
             }
             Err(e) => { logger::warn(e.to_string()); panic!() }
         }
     }
 
     /// The sync version of [`crate::Tag::query_desc`].
-    pub fn qd_sync(&self, db: &mut db::crud::Database) -> Option<String> {
+    pub fn qd_sync(&self, db: &db::crud::Database) -> Option<String> {
         block_on(async { self.query_desc(db).await })
     }
 
     /// A sync function that combines [`crate::Tag::qtr_sync`] and
     /// [`crate::Tag::qd_sync`]. It returns a vec of 3-tuples, of
     /// tag name, optional tag descriptions and optional weights with
-    /// this tag.
-    pub fn qtrd(&self, db: &mut db::crud::Database) -> Vec<(String, Option<String>, Option<f32>)> {
-        let tags = self.qtr_sync(db);
-        tags.iter().map(|tag|
+    /// this tag, all read from the cached graph.
+    pub fn qtrd(&self, db: &db::crud::Database) -> Vec<(String, Option<String>, Option<f64>)> {
+        let graph = match block_on(db.graph()) {
+            Ok(graph) => graph,
+            Err(e) => { logger::warn(e.to_string()); panic!() }
+        };
+        let Some(from) = graph.id(&self.raw_name()) else { return vec![] };
+        graph.neighbor_names(&self.raw_name()).into_iter().map(|tag| {
+            let to = graph.id(tag).unwrap();
             (
                 tag.into(),
-                Tag::new(tag).qd_sync(db),
-                Tag::query_sync(db, self, &Tag::new(tag))
-            )).collect()
+                graph.desc(to).map(String::from),
+                graph.link(from, to).map(|link| link.weight),
+            )
+        }).collect()
+    }
+
+    /// Whether this tag is in the database.
+    pub async fn exists(&self, db: &db::crud::Database) -> bool {
+        self.on(db).exists().await
     }
 
     /// retrieves all tag names in the database.
-    pub fn get_tags(db: &mut db::crud::Database) -> Vec<String> {
-        block_on(async {
-            db.read(
-                "tags", 
-                &[String::from("tag_name")], 
-                &format!("true"),
-                ""
-            ).await
-            .map(|thing| thing.get::<String>(0))
-            .unwrap_or_default()
-        })
+    pub fn get_tags(db: &db::crud::Database) -> Vec<String> {
+        block_on(store::names(db)).unwrap_or_default()
     }
-}
\ No newline at end of file
+}
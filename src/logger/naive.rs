@@ -1,12 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::db::crud::{DatabaseResult, DatabaseError};
 
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends the log to stderr from now on, for the modes where stdout
+/// carries a protocol, like `frictune rpc`.
+pub fn to_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+fn line(info: &str) {
+    if TO_STDERR.load(Ordering::Relaxed) { eprintln!("{}", info) }
+    else { println!("{}", info) }
+}
+
 pub fn warn(info: String) {
-    println!("{}", info);
+    line(&info);
 }
 
 /// Print out error and exit.
 pub fn rupt(info: &str) -> ! {
-    println!("{}", info);
+    line(info);
     panic!();
 }
 
@@ -18,5 +33,5 @@ pub fn watch(result: Result<DatabaseResult, DatabaseError>) {
 }
 
 pub fn print(info: &str) {
-    println!("{}", info);
+    line(info);
 }
@@ -0,0 +1,348 @@
+//! A language server for the `{{ tag ... }}` markup of
+//! [`crate::markup`], run on stdio by `frictune lsp`.
+//!
+//! - completion of the tag names of the database where a leading or a
+//!   braced tag name is typed;
+//! - hover on a tag name with its description and its heaviest links;
+//! - diagnostics for malformed blocks and weights out of `[0, 1]`;
+//! - go-to-definition listing the blocks of the other posts recorded
+//!   with the tag by `tune_html`, see [`crate::infer`]. Relative post
+//!   paths are taken from the workspace root.
+//!
+//! The documents are synced in full. Saving a document reloads the
+//! graph, so that tags added by `tune_html` meanwhile show up.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionTextEdit,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeParams, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+use serde_json::Value;
+
+use crate::{
+    db::{self, crud::Database},
+    markup::{self, Block},
+    store::blocking::TagStore,
+};
+
+/// How many completions are offered at most.
+pub const COMPLETION_LIMIT: usize = 100;
+/// How many related tags a hover shows.
+pub const HOVER_RELATED: usize = 5;
+
+/// The LSP position, counted in UTF-16 units, of a byte offset.
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(before.matches('\n').count() as u32, before[line_start..].encode_utf16().count() as u32)
+}
+
+/// The byte offset of an LSP position, clamped to its line.
+pub fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' { return line_start + i }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, span: &std::ops::Range<usize>) -> Range {
+    Range::new(position(text, span.start), position(text, span.end))
+}
+
+/// The part of a tag name typed before `offset`, and whether it is
+/// quoted, if `offset` is where a leading or a braced name goes.
+fn typed_name(text: &str, offset: usize) -> Option<(&str, bool)> {
+    let before = &text[..offset];
+    let open = before.rfind("{{")?;
+    let inner = &before[open + 2..];
+    if inner.contains("}}") { return None }
+    let name = match inner.rfind('(') {
+        Some(brace) if !inner[brace..].contains(')') => &inner[brace + 1..],
+        _ => inner,
+    }.trim_start();
+    match name.strip_prefix('"') {
+        Some(quoted) if !quoted.contains('"') => Some((quoted, true)),
+        Some(_) => None,
+        None if name.contains(char::is_whitespace) || name.contains(['(', ')']) => None,
+        None => Some((name, false)),
+    }
+}
+
+/// The raw tag name at `offset` with its byte range.
+fn name_at(blocks: &[Block], offset: usize) -> Option<(String, std::ops::Range<usize>)> {
+    blocks.iter().find_map(|block| {
+        block.name_spans.iter().zip(block.tag_names())
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(span, name)| (name, span.clone()))
+    })
+}
+
+/// The problems of a document.
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let mut found = vec![];
+    if let Some((span, message)) = markup::syntax_error(text) {
+        found.push(Diagnostic {
+            range: range(text, &span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("frictune".into()),
+            message,
+            ..Default::default()
+        });
+    }
+    for block in markup::parse_blocks(text).unwrap_or_default() {
+        for ((_, weight), span) in block.trailers.iter().zip(block.weight_spans.iter()) {
+            if let Some(span) = span.as_ref().filter(|_| !(0.0..=1.0).contains(weight)) {
+                found.push(Diagnostic {
+                    range: range(text, span),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    source: Some("frictune".into()),
+                    message: format!("weight {} is not within [0, 1]", weight),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    found
+}
+
+/// The state of a session: the open documents and the database.
+pub struct Backend {
+    store: TagStore,
+    docs: HashMap<Url, String>,
+    root: Option<PathBuf>,
+}
+
+impl Backend {
+    pub fn new(db: Database, root: Option<PathBuf>) -> Self {
+        Backend { store: TagStore::from_database(db), docs: HashMap::new(), root }
+    }
+
+    pub fn completion(&self, text: &str, offset: usize) -> Vec<CompletionItem> {
+        let Some((typed, quoted)) = typed_name(text, offset) else { return vec![] };
+        let Ok(graph) = self.store.graph() else { return vec![] };
+        let prefix = crate::normalize::normalize(typed).to_lowercase();
+        let edit_range = Range::new(position(text, offset - typed.len()), position(text, offset));
+        let mut names: Vec<usize> = (0..graph.len())
+            .filter(|id| graph.name(*id).to_lowercase().starts_with(&prefix))
+            .collect();
+        names.sort_by(|a, b| graph.name(*a).cmp(graph.name(*b)));
+        names.into_iter().take(COMPLETION_LIMIT).map(|id| {
+            let name = graph.name(id);
+            let new_text = if !quoted && name.contains(char::is_whitespace) { format!("\"{}\"", name) }
+                else { name.to_string() };
+            CompletionItem {
+                label: name.into(),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: graph.desc(id).map(String::from),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(edit_range, new_text))),
+                ..Default::default()
+            }
+        }).collect()
+    }
+
+    pub fn hover(&self, text: &str, offset: usize) -> Option<Hover> {
+        let blocks = markup::parse_blocks(text).ok()?;
+        let (name, span) = name_at(&blocks, offset)?;
+        let tag = self.store.tag(&name);
+        let value = if !tag.exists() {
+            format!("**{}** is not in the database", name)
+        } else {
+            let mut lines = vec![format!("**{}**", name)];
+            if let Some(desc) = tag.desc().ok().flatten() {
+                lines.push(desc);
+            }
+            let related: Vec<String> = tag.neighbors().unwrap_or_default().into_iter()
+                .take(HOVER_RELATED)
+                .map(|(name, weight)| format!("{} ({:.2})", name, weight))
+                .collect();
+            if !related.is_empty() {
+                lines.push(format!("related: {}", related.join(", ")));
+            }
+            lines.join("\n\n")
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(range(text, &span)),
+        })
+    }
+
+    /// The blocks of the other posts with the tag at `offset`.
+    pub fn definition(&self, uri: &Url, text: &str, offset: usize) -> Vec<Location> {
+        let Some((name, _)) = markup::parse_blocks(text).ok().and_then(|blocks| name_at(&blocks, offset)) else { return vec![] };
+        let Ok(rows) = futures::executor::block_on(self.store.database().read("post_blocks",
+            &["post".to_string(), "block".to_string()],
+            &format!("tag_name = {}", db::quote(&name)),
+            "ORDER BY post, block"
+        )) else { return vec![] };
+        let this = uri.to_file_path().ok().and_then(|path| path.canonicalize().ok());
+        rows.get::<String>(0).into_iter().zip(rows.get::<i64>(1)).filter_map(|(post, block)| {
+            let path = match (PathBuf::from(&post), &self.root) {
+                (path, Some(root)) if path.is_relative() => root.join(path),
+                (path, _) => path,
+            };
+            let path = path.canonicalize().unwrap_or(path);
+            if this.as_ref() == Some(&path) { return None }
+            let range = std::fs::read_to_string(&path).ok()
+                .and_then(|post| {
+                    let blocks = markup::parse_blocks(&post).ok()?;
+                    blocks.get(block as usize).map(|b| range(&post, &b.span))
+                })
+                .unwrap_or_default();
+            Some(Location::new(Url::from_file_path(&path).ok()?, range))
+        }).collect()
+    }
+
+    fn document(&self, params: &TextDocumentPositionParams) -> Option<(&str, usize)> {
+        let text = self.docs.get(&params.text_document.uri)?;
+        Some((text.as_str(), offset(text, params.position)))
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let result = match request.method.as_str() {
+            "textDocument/completion" => serde_json::from_value::<CompletionParams>(request.params)
+                .map(|p| {
+                    let Some((text, at)) = self.document(&p.text_document_position) else { return Value::Null };
+                    let text = text.to_string();
+                    serde_json::to_value(self.completion(&text, at)).unwrap()
+                }),
+            "textDocument/hover" => serde_json::from_value::<HoverParams>(request.params)
+                .map(|p| {
+                    let Some((text, at)) = self.document(&p.text_document_position_params) else { return Value::Null };
+                    let text = text.to_string();
+                    serde_json::to_value(self.hover(&text, at)).unwrap()
+                }),
+            "textDocument/definition" => serde_json::from_value::<GotoDefinitionParams>(request.params)
+                .map(|p| {
+                    let position = p.text_document_position_params;
+                    let Some((text, at)) = self.document(&position) else { return Value::Null };
+                    let text = text.to_string();
+                    serde_json::to_value(self.definition(&position.text_document.uri, &text, at)).unwrap()
+                }),
+            method => return Response::new_err(request.id, ErrorCode::MethodNotFound as i32, format!("unknown method {}", method)),
+        };
+        match result {
+            Ok(result) => Response::new_ok(request.id, result),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    /// Handles a notification, returning the diagnostics to publish.
+    fn notification(&mut self, notification: Notification) -> Option<PublishDiagnosticsParams> {
+        let (uri, text) = match notification.method.as_str() {
+            "textDocument/didOpen" => {
+                let p: DidOpenTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                (p.text_document.uri, p.text_document.text)
+            },
+            "textDocument/didChange" => {
+                let p: DidChangeTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                (p.text_document.uri, p.content_changes.into_iter().last()?.text)
+            },
+            "textDocument/didClose" => {
+                let p: DidCloseTextDocumentParams = serde_json::from_value(notification.params).ok()?;
+                self.docs.remove(&p.text_document.uri);
+                return Some(PublishDiagnosticsParams::new(p.text_document.uri, vec![], None));
+            },
+            "textDocument/didSave" => {
+                self.store.database().invalidate_graph();
+                return None;
+            },
+            _ => return None,
+        };
+        let found = diagnostics(&text);
+        self.docs.insert(uri.clone(), text);
+        Some(PublishDiagnosticsParams::new(uri, found, None))
+    }
+}
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["{".into(), "(".into(), "\"".into()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Initializes the session on `connection` and answers it until the
+/// client shuts it down.
+pub fn run(db: Database, connection: &Connection) -> anyhow::Result<()> {
+    let params: InitializeParams = serde_json::from_value(
+        connection.initialize(serde_json::to_value(capabilities())?)?
+    )?;
+    #[allow(deprecated)]
+    let root = params.workspace_folders.and_then(|folders| folders.into_iter().next().map(|f| f.uri))
+        .or(params.root_uri)
+        .and_then(|uri| uri.to_file_path().ok());
+    let mut backend = Backend::new(db, root);
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? { return Ok(()) }
+                connection.sender.send(Message::Response(backend.request(request)))?;
+            },
+            Message::Notification(notification) => {
+                if let Some(params) = backend.notification(notification) {
+                    connection.sender.send(Message::Notification(
+                        Notification::new("textDocument/publishDiagnostics".into(), params)
+                    ))?;
+                }
+            },
+            Message::Response(_) => {},
+        }
+    }
+    Ok(())
+}
+
+/// Runs the server on stdin and stdout.
+pub fn serve_stdio(db: Database) -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    run(db, &connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let text = "ab\n日本 {{ x }}\n";
+        let at = text.find('{').unwrap();
+        assert_eq!(position(text, at), Position::new(1, 3));
+        assert_eq!(offset(text, Position::new(1, 3)), at);
+        assert_eq!(offset(text, Position::new(0, 99)), 2);
+        assert_eq!(offset(text, Position::new(9, 0)), text.len());
+    }
+
+    #[test]
+    fn test_typed_name() {
+        fn at_end(text: &str) -> Option<(&str, bool)> { typed_name(text, text.len()) }
+        assert_eq!(at_end("see {{ ru"), Some(("ru", false)));
+        assert_eq!(at_end("{{ rust https://rust-lang.org (car"), Some(("car", false)));
+        assert_eq!(at_end("{{ \"Hacker N"), Some(("Hacker N", true)));
+        assert_eq!(at_end("{{ rust http"), None);
+        assert_eq!(at_end("{{ rust (cargo 0."), None);
+        assert_eq!(at_end("{{ rust }} ru"), None);
+    }
+}
@@ -10,12 +10,8 @@ fn main() {
                 Ok(config) => config,
                 Err(e) => frictune::logger::rupt(e.to_string().as_str()),
             };
-            let mut conn = match frictune::db::crud::Database::sync_new(&settings.db_uri)
-            {
-                Ok(conn) => conn,
-                Err(e) => frictune::logger::rupt(e.to_string().as_str()),
-            };
-            ui::cli::parse_args(&mut conn);
+            frictune::normalize::set_policy(settings.normalize.clone());
+            ui::cli::parse_args(&settings);
         }
         else {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -46,7 +42,7 @@ fn App(cx: Scope) -> Element {
             //     bincode::serialize(
             //         &gluesql::memory_storage::MemoryStorage::default()).unwrap()));
 
-            let mut glue = Database::deser_new(response).unwrap();
+            let glue = Database::deser_new(response).unwrap();
             use_shared_state_provider(cx, || glue);
 
             let tag1_name = use_state(cx, || "abc".to_string());
@@ -65,7 +61,7 @@ fn App(cx: Scope) -> Element {
                 input { value: "{tag1_desc}", oninput: |e| tag1_desc.set(e.value.clone()) },
                 input { value: "{tag2_name}", oninput: |e| tag2_name.set(e.value.clone()) },
                 input { value: "{tag2_desc}", oninput: |e| tag2_desc.set(e.value.clone()) },
-                input { value: "{link}", oninput: |e| link.set(e.value.clone().parse::<f32>().unwrap_or_default()) },
+                input { value: "{link}", oninput: |e| link.set(e.value.clone().parse::<f64>().unwrap_or_default()) },
                 div { "{words}" }
                 button {
                     onclick: move |_| {
@@ -91,6 +87,23 @@ fn App(cx: Scope) -> Element {
                     },
                     "QTR"
                 }
+                button {
+                    onclick: move |_| {
+                        let glue = use_shared_state::<Database>(cx).unwrap();
+                        let mut glue = glue.write_silent();
+                        let seeds = [tag1_name.get(), tag2_name.get()].into_iter()
+                            .filter(|name| !name.is_empty())
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        words.set(
+                            frictune::recommend::recommend_sync(&mut glue, &seeds, 10).into_iter()
+                                .map(|rec| format!("{} | {}", rec.name, rec.score))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        );
+                    },
+                    "REC"
+                }
                 button {
                     onclick: move |_| {
                         let glue = use_shared_state::<Database>(cx).unwrap();
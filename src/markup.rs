@@ -0,0 +1,185 @@
+//! The `{{ tag ... }}` markup read by `tune_html`.
+//!
+//! The grammar lives in `settings/tag_seg.pest`. A block looks like
+//! ```text
+//! {{ foo "https://example.com" (bar 0.5) ("bar baz" "https://example.org") }}
+//! ```
+//! with a leading tag, its optional description and any number of
+//! braced trailing tags, each with an optional description and weight.
+//! A missing weight is 1.0. An unquoted description never starts with
+//! `(`, so the trailing tags may follow the leading tag directly.
+//!
+//! # Example
+//!
+//! ```
+//! let blocks = frictune::markup::parse_blocks("text {{ git \"https://git-scm.com/\" (vcs 0.8) }} text").unwrap();
+//! assert_eq!(blocks[0].main.name, "'git'");
+//! assert_eq!(blocks[0].trailers[0].1, 0.8);
+//! assert_eq!(blocks[0].tag_names(), vec!["git", "vcs"]);
+//! ```
+
+use std::ops::Range;
+
+use pest::Parser;
+
+use crate::{db, Tag};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "./settings/tag_seg.pest"]
+pub struct TagParser;
+
+/// One `{{ ... }}` block. The tags are in the quoted form of
+/// [`crate::Tag`], normalized and ready to be added to the database.
+pub struct Block {
+    pub main: Tag,
+    pub trailers: Vec<(Tag, f64)>,
+    /// The byte range of the block in the parsed text.
+    pub span: Range<usize>,
+    /// The byte ranges of the tag names, in the order of
+    /// [`Block::tag_names`].
+    pub name_spans: Vec<Range<usize>>,
+    /// The byte ranges of the weights of `trailers`, if typed.
+    pub weight_spans: Vec<Option<Range<usize>>>,
+}
+
+impl Block {
+    /// The raw names of all tags in the block, leading tag first.
+    pub fn tag_names(&self) -> Vec<String> {
+        std::iter::once(&self.main)
+            .chain(self.trailers.iter().map(|(tag, _)| tag))
+            .map(|tag| tag.raw_name().into_owned())
+            .collect()
+    }
+}
+
+/// Parses all blocks of a text.
+/// this function has a strong relation with the `pest` parser.
+/// If the format is changed, both need to be changed.
+pub fn parse_blocks(content: &str) -> Result<Vec<Block>, Box<pest::error::Error<Rule>>> {
+    let pairs = TagParser::parse(Rule::final_seg, content)?;
+    Ok(pairs.flat_map(|pair| {
+        pair.into_inner()
+            .filter(|ip| matches!(ip.as_rule(), Rule::single_tag))
+            .map(|pair| {
+                let span = pair.as_span().start()..pair.as_span().end();
+                let mut main_tag = Tag { name: "''".into(), desc: None };
+                let mut trailers = vec![];
+                let mut name_spans = vec![];
+                let mut weight_spans = vec![];
+                for inner in pair.into_inner() {
+                    match inner.as_rule() {
+                        Rule::leading_word => {
+                            main_tag.name = Tag::new(inner.as_str().trim_matches('\"')).name;
+                            name_spans.insert(0, inner.as_span().start()..inner.as_span().end());
+                        },
+                        Rule::desc_leading_word => {
+                            let desc = inner.as_str().trim_matches('\"');
+                            if !desc.is_empty() {
+                                main_tag.desc = Some(db::quote(desc));
+                            }
+                        },
+                        Rule::brace => {
+                            let mut trailing_tag = Tag { name: "''".into(), desc: None };
+                            let mut num = 1.0;
+                            let mut name_span = 0..0;
+                            let mut weight_span = None;
+                            for brace_inner in inner.into_inner() {
+                                let brace_span = brace_inner.as_span().start()..brace_inner.as_span().end();
+                                match brace_inner.as_rule() {
+                                    Rule::inner_word => {
+                                        trailing_tag.name = Tag::new(brace_inner.as_str().trim_matches('\"')).name;
+                                        name_span = brace_span;
+                                    },
+                                    Rule::desc_inner_word => {
+                                        let desc = brace_inner.as_str().trim_matches('\"');
+                                        if !desc.is_empty() {
+                                            trailing_tag.desc = Some(db::quote(desc));
+                                        }
+                                    },
+                                    Rule::number => {
+                                        num = brace_inner.as_str().parse::<f64>().unwrap();
+                                        weight_span = Some(brace_span);
+                                    },
+                                    _ => { crate::logger::rupt(&format!("brace_inner is {}", brace_inner.as_str())); }
+                                }
+                            }
+                            // if not empty, push into trailers
+                            if !trailing_tag.raw_name().is_empty() {
+                                trailers.push((trailing_tag, num));
+                                name_spans.push(name_span);
+                                weight_spans.push(weight_span);
+                            }
+                        },
+                        _ => { crate::logger::rupt(&format!("inner is {}", inner.as_str())); }
+                    }
+                }
+                Block { main: main_tag, trailers, span, name_spans, weight_spans }
+            })
+            .collect::<Vec<_>>()
+    }).collect())
+}
+
+/// The first malformed block of a text, as the byte range of the
+/// offending character and the parser's message. [`parse_blocks`]
+/// silently stops before it.
+pub fn syntax_error(content: &str) -> Option<(Range<usize>, String)> {
+    use pest::error::InputLocation;
+
+    let end = TagParser::parse(Rule::final_seg, content).ok()?
+        .next().map_or(0, |pair| pair.as_span().end());
+    let rest = content[end..].trim_start();
+    if rest.is_empty() { return None }
+    let parsed = content.len() - rest.len();
+    if rest.starts_with("}}") {
+        return Some((parsed..parsed + 2, "unmatched }}".into()));
+    }
+    let (at, message) = match TagParser::parse(Rule::single_tag, rest) {
+        Err(e) => match e.location {
+            InputLocation::Pos(p) | InputLocation::Span((p, _)) => (parsed + p, e.variant.message().into_owned()),
+        },
+        Ok(_) => (parsed, "malformed block".to_string()),
+    };
+    let width = content[at..].chars().next().map_or(0, char::len_utf8);
+    Some((at..at + width, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_and_errors() {
+        let text = "a {{ git \"https://git-scm.com/\" (vcs 0.8) (\"Hacker News\") }} b";
+        let blocks = parse_blocks(text).unwrap();
+        let names: Vec<&str> = blocks[0].name_spans.iter().map(|s| &text[s.clone()]).collect();
+        assert_eq!(names, vec!["git", "vcs", "\"Hacker News\""]);
+        assert_eq!(blocks[0].weight_spans[0].clone().map(|s| &text[s]), Some("0.8"));
+        assert_eq!(blocks[0].weight_spans[1], None);
+        assert!(syntax_error(text).is_none());
+
+        let (at, _) = syntax_error("ok {{ git x (vcs 0.8 }} end").unwrap();
+        assert_eq!(at, 21..22);
+        assert_eq!(syntax_error("stray }} here").unwrap(), (6..8, "unmatched }}".to_string()));
+    }
+
+    #[test]
+    fn test_trailers_without_description() {
+        let text = "{{ cargo (rust 0.5) (crates) }}";
+        assert!(syntax_error(text).is_none());
+        let blocks = parse_blocks(text).unwrap();
+        assert_eq!(blocks[0].main.desc, None);
+        assert_eq!(blocks[0].tag_names(), vec!["cargo", "rust", "crates"]);
+        assert_eq!(blocks[0].trailers[0].1, 0.5);
+    }
+
+    #[test]
+    fn test_description_starting_with_bracket() {
+        let blocks = parse_blocks("{{ cargo \"(build tool)\" (rust 0.5) }}").unwrap();
+        assert_eq!(blocks[0].main.desc.as_deref(), Some("'(build tool)'"));
+        assert_eq!(blocks[0].tag_names(), vec!["cargo", "rust"]);
+
+        let blocks = parse_blocks("{{ cargo build-tool (rust) }}").unwrap();
+        assert_eq!(blocks[0].main.desc.as_deref(), Some("'build-tool'"));
+        assert_eq!(blocks[0].trailers[0].1, 1.0);
+    }
+}
@@ -0,0 +1,295 @@
+//! Tag name normalization.
+//!
+//! Without it, `Rust`, `rust ` and `ｒｕｓｔ` are three different tags.
+//! A [`Policy`] says which differences to erase; it is read from the
+//! `normalize` table of the settings and installed with [`set_policy`],
+//! after which [`crate::Tag::new`], the markup of `tune_html` and the
+//! queries all pass names through [`normalize`]. The default policy
+//! changes nothing, so that existing databases keep working until
+//! [`normalize_db`] has merged their names.
+//!
+//! ```toml
+//! [normalize]
+//! unicode = "nfkc"
+//! case_fold = true
+//! trim = true
+//! collapse_whitespace = true
+//! half_width = true
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::normalize::{Policy, UnicodeForm};
+//!
+//! let policy = Policy { unicode: UnicodeForm::Nfc, case_fold: true, trim: true, collapse_whitespace: true, half_width: true };
+//! assert_eq!(policy.apply("  Hacker　 News！"), "hacker news!");
+//! ```
+
+use std::{collections::BTreeMap, sync::RwLock};
+
+use futures::executor::block_on;
+use serde_derive::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{db::{self, crud::{Database, DatabaseError}}, search};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    #[default]
+    None,
+    Nfc,
+    Nfkc,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub unicode: UnicodeForm,
+    /// Lowercase the name.
+    pub case_fold: bool,
+    /// Remove leading and trailing whitespace.
+    pub trim: bool,
+    /// Turn every run of whitespace into a single space.
+    pub collapse_whitespace: bool,
+    /// Turn full-width ASCII characters and the ideographic space into
+    /// their half-width forms.
+    pub half_width: bool,
+}
+
+impl Policy {
+    pub const NONE: Policy = Policy {
+        unicode: UnicodeForm::None,
+        case_fold: false,
+        trim: false,
+        collapse_whitespace: false,
+        half_width: false,
+    };
+
+    pub fn apply(&self, name: &str) -> String {
+        let mut name: String = match self.unicode {
+            UnicodeForm::None => name.into(),
+            UnicodeForm::Nfc => name.nfc().collect(),
+            UnicodeForm::Nfkc => name.nfkc().collect(),
+        };
+        if self.half_width {
+            name = name.chars().map(|c| match c {
+                '\u{3000}' => ' ',
+                '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+                _ => c,
+            }).collect();
+        }
+        if self.case_fold {
+            name = name.to_lowercase();
+        }
+        if self.collapse_whitespace {
+            let trailing = name.ends_with(char::is_whitespace);
+            let leading = name.starts_with(char::is_whitespace);
+            let mut collapsed = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !self.trim && !collapsed.is_empty() {
+                if leading { collapsed.insert(0, ' ') }
+                if trailing { collapsed.push(' ') }
+            }
+            name = collapsed;
+        }
+        if self.trim {
+            name = name.trim().into();
+        }
+        name
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self { Policy::NONE }
+}
+
+static POLICY: RwLock<Policy> = RwLock::new(Policy::NONE);
+
+/// Installs the policy used by [`normalize`].
+pub fn set_policy(policy: Policy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+pub fn policy() -> Policy {
+    POLICY.read().unwrap().clone()
+}
+
+/// Normalizes a raw tag name with the installed policy.
+pub fn normalize(name: &str) -> String {
+    POLICY.read().unwrap().apply(name)
+}
+
+/// Tags that end up under one name.
+#[derive(Debug, PartialEq)]
+pub struct Merge {
+    pub target: String,
+    /// The current names that differ from `target`.
+    pub sources: Vec<String>,
+}
+
+/// The merges needed to bring raw tag names under `policy`.
+pub fn plan(names: &[String], policy: &Policy) -> Vec<Merge> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in names {
+        groups.entry(policy.apply(name)).or_default().push(name.clone());
+    }
+    groups.into_iter().filter_map(|(target, mut names)| {
+        names.retain(|name| *name != target);
+        names.sort();
+        if names.is_empty() { None } else { Some(Merge { target, sources: names }) }
+    }).collect()
+}
+
+async fn merge(db: &Database, merge: &Merge) -> Result<(), DatabaseError> {
+    let target = db::quote(&merge.target);
+    let found = db.read("tags", &["info".to_string()], &format!("tag_name = {}", target), "").await?;
+    if found.is_empty() {
+        let mut info = None;
+        for source in merge.sources.iter() {
+            let rows = db.read("tags", &["info".to_string()], &format!("tag_name = {}", db::quote(source)), "").await?;
+            info = rows.get::<Option<String>>(0).into_iter().flatten().find(|i| !i.is_empty());
+            if info.is_some() { break }
+        }
+        match info {
+            Some(info) => db.create("tags", &["tag_name".to_string(), "info".to_string()], &[target.clone(), db::quote(&info)]).await?,
+            None => db.create("tags", &["tag_name".to_string()], std::slice::from_ref(&target)).await?,
+        };
+    }
+
+    let entries = ["tag1".to_string(), "tag2".to_string(), "weight".to_string(), "is_origin".to_string()];
+    for source in merge.sources.iter() {
+        let quoted = db::quote(source);
+        let links = db.read("relationship",
+            &entries,
+            &format!("tag1 = {} OR tag2 = {}", quoted, quoted),
+            ""
+        ).await?;
+        for (tag1, tag2, weight, is_origin) in itertools::izip!(
+            links.get::<String>(0), links.get::<String>(1), links.get::<f64>(2), links.get::<bool>(3)
+        ) {
+            let rename = |tag: String| if tag == *source { merge.target.clone() } else { tag };
+            let (tag1, tag2) = (rename(tag1), rename(tag2));
+            if tag1 == tag2 { continue }
+            let (tag1, tag2) = (db::quote(&tag1), db::quote(&tag2));
+            let old = db.read("relationship", &entries[2..], &format!("tag1 = {} AND tag2 = {}", tag1, tag2), "").await?;
+            // of two links that become one, an origin link beats a
+            // derived one, and then the heavier one stays
+            let kept = old.get::<f64>(0).into_iter().zip(old.get::<bool>(1))
+                .any(|(old_weight, old_origin)| (old_origin, old_weight) >= (is_origin, weight));
+            if kept { continue }
+            let data = [tag1, tag2, weight.to_string(), is_origin.to_string()];
+            db.update("relationship", &entries, &data, &entries[2..], &data[2..], "true").await?;
+        }
+        db.execute(&format!("DELETE FROM relationship WHERE tag1 = {} OR tag2 = {};", quoted, quoted)).await?;
+        let blocks = db.read("post_blocks", &["post".to_string(), "block".to_string()], &format!("tag_name = {}", quoted), "").await?;
+        db.delete("post_blocks", "tag_name", &quoted).await?;
+        for (post, block) in blocks.get::<String>(0).into_iter().zip(blocks.get::<i64>(1)) {
+            match db.create("post_blocks",
+                &["post".to_string(), "block".to_string(), "tag_name".to_string()],
+                &[db::quote(&post), block.to_string(), target.clone()]
+            ).await {
+                Ok(_) | Err(DatabaseError::UniqueViolation) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        let note_entries = ["path".to_string(), "tag1".to_string(), "tag2".to_string(), "created".to_string()];
+        let backed = db.read("note_links", &note_entries, &format!("tag1 = {} OR tag2 = {}", quoted, quoted), "").await?;
+        db.delete("note_links", "tag1", &quoted).await?;
+        db.delete("note_links", "tag2", &quoted).await?;
+        for (path, tag1, tag2, created) in itertools::izip!(
+            backed.get::<String>(0), backed.get::<String>(1), backed.get::<String>(2), backed.get::<bool>(3)
+        ) {
+            let rename = |tag: String| if tag == *source { merge.target.clone() } else { tag };
+            let (tag1, tag2) = (rename(tag1), rename(tag2));
+            if tag1 == tag2 { continue }
+            // a link the notes made stays theirs when two rows become one
+            let data = [db::quote(&path), db::quote(&tag1), db::quote(&tag2), created.to_string()];
+            let written = match created {
+                true => db.update("note_links", &note_entries, &data, &note_entries[3..], &data[3..], "true").await,
+                false => db.create("note_links", &note_entries, &data).await,
+            };
+            match written {
+                Ok(_) | Err(DatabaseError::UniqueViolation) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        let post_entries = ["post".to_string(), "tag1".to_string(), "tag2".to_string(), "weight".to_string()];
+        let posted = db.read("post_links", &post_entries, &format!("tag1 = {} OR tag2 = {}", quoted, quoted), "").await?;
+        db.delete("post_links", "tag1", &quoted).await?;
+        db.delete("post_links", "tag2", &quoted).await?;
+        for (post, tag1, tag2, weight) in itertools::izip!(
+            posted.get::<String>(0), posted.get::<String>(1), posted.get::<String>(2), posted.get::<f64>(3)
+        ) {
+            let rename = |tag: String| if tag == *source { merge.target.clone() } else { tag };
+            let (tag1, tag2) = (rename(tag1), rename(tag2));
+            if tag1 == tag2 { continue }
+            match db.create("post_links", &post_entries,
+                &[db::quote(&post), db::quote(&tag1), db::quote(&tag2), weight.to_string()]
+            ).await {
+                Ok(_) | Err(DatabaseError::UniqueViolation) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        db.execute(&format!("DELETE FROM inferred_link WHERE tag1 = {} OR tag2 = {};", quoted, quoted)).await?;
+        search::unindex_tag(db, &quoted).await?;
+        db.delete("tags", "tag_name", &quoted).await?;
+    }
+    search::reindex_tag(db, &target).await?;
+    Ok(())
+}
+
+/// Renames every tag to its normalized name under `policy`, merging the
+/// tags that collide: their links are moved over, keeping of two links
+/// between the same tags the origin one, or else the heavier one, and
+/// the first non-empty description is kept when the normalized name is
+/// new. With `dry_run` the merges are only returned. Inferred links of
+/// merged tags are dropped; run `frictune infer` again to recompute
+/// them. The notes and posts backing a link back it under the new
+/// names. The merges run in one transaction, so a failed one leaves the
+/// database as it was.
+pub async fn normalize_db(db: &Database, policy: &Policy, dry_run: bool) -> Result<Vec<Merge>, DatabaseError> {
+    let names = db.read("tags", &["tag_name".to_string()], "true", "").await?.get::<String>(0);
+    let merges = plan(&names, policy);
+    if !dry_run {
+        let tx = db.begin().await?;
+        for m in merges.iter() {
+            merge(&tx, m).await?;
+        }
+        tx.commit().await?;
+    }
+    Ok(merges)
+}
+
+/// The sync version of [`normalize_db`].
+pub fn normalize_db_sync(db: &Database, policy: &Policy, dry_run: bool) -> Result<Vec<Merge>, DatabaseError> {
+    block_on(async { normalize_db(db, policy, dry_run).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let full = Policy { unicode: UnicodeForm::Nfkc, case_fold: true, trim: true, collapse_whitespace: true, half_width: false };
+        assert_eq!(full.apply(" Rust  Lang "), "rust lang");
+        assert_eq!(full.apply("ｒｕｓｔ"), "rust");
+        assert_eq!(Policy::NONE.apply(" Rust "), " Rust ");
+        let collapse = Policy { collapse_whitespace: true, ..Policy::NONE };
+        assert_eq!(collapse.apply(" a \t b "), " a b ");
+        // a composed and a decomposed é
+        let nfc = Policy { unicode: UnicodeForm::Nfc, ..Policy::NONE };
+        assert_eq!(nfc.apply("caf\u{65}\u{301}"), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_plan() {
+        let names: Vec<String> = ["rust", "Rust", "rust ", "Go "].iter().map(|n| n.to_string()).collect();
+        let policy = Policy { case_fold: true, trim: true, ..Policy::NONE };
+        assert_eq!(plan(&names, &policy), vec![
+            Merge { target: "go".into(), sources: vec!["Go ".into()] },
+            Merge { target: "rust".into(), sources: vec!["Rust".into(), "rust ".into()] },
+        ]);
+    }
+}
@@ -0,0 +1,322 @@
+//! Tags from a folder of Markdown notes.
+//!
+//! The tags of a note are the `tags` (or `tag`) of its front matter,
+//! YAML between `---` lines or TOML between `+++` lines, followed by
+//! the `#hashtags` of its text outside code:
+//!
+//! ```markdown
+//! ---
+//! tags: [rust, cli]
+//! ---
+//! Parsing arguments with #clap.
+//! ```
+//!
+//! [`index_dir`] reads every `.md` file under a folder and links the
+//! tags of each note both ways with [`Settings::weight`], unless an
+//! origin link is there already. The `note_links` table records which
+//! notes back every link and the `notes` table a digest of every note,
+//! so that the next run only reads the notes that changed. A link the
+//! notes made is removed once no note backs it anymore, unless its
+//! weight was changed by hand in the meantime; a link set by hand is
+//! never removed.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::notes::extract;
+//!
+//! assert_eq!(extract("---\ntags: [rust]\n---\nSee #clap, not `#code`."), vec!["rust", "clap"]);
+//! ```
+
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, path::Path, sync::LazyLock};
+
+use futures::executor::block_on;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{db::{self, crud::{Database, DatabaseError}}, export::check_name, Tag};
+
+static HASHTAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|[^\p{L}\p{N}_&/#])#([\p{L}\p{N}_/-]+)").unwrap());
+
+/// How notes are linked, read from the `index` table of the settings.
+///
+/// ```toml
+/// [index]
+/// weight = 0.3
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The weight of the links between the tags of a note.
+    pub weight: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self { Settings { weight: 0.3 } }
+}
+
+/// The front matter of a note, and the text after it.
+pub fn front_matter(text: &str) -> (Option<Value>, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    for fence in ["---", "+++"] {
+        let Some(rest) = text.strip_prefix(fence)
+            .and_then(|rest| rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n"))) else { continue };
+        let mut end = 0;
+        for line in rest.split_inclusive('\n') {
+            if line.trim_end() == fence {
+                let matter = &rest[..end];
+                let value = match fence {
+                    "---" => serde_yaml::from_str(matter).ok(),
+                    _ => toml::from_str(matter).ok(),
+                };
+                return (value, &rest[end + line.len()..]);
+            }
+            end += line.len();
+        }
+    }
+    (None, text)
+}
+
+/// The strings of a front matter field, either a list or a string
+/// separated by commas, or else by whitespace.
+pub fn strings(value: Option<&Value>) -> Vec<String> {
+    let strings = match value {
+        Some(Value::Array(items)) => items.iter().filter_map(|item| match item {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }).collect(),
+        Some(Value::String(s)) if s.contains(',') => s.split(',').map(String::from).collect(),
+        Some(Value::String(s)) => s.split_whitespace().map(String::from).collect(),
+        _ => vec![],
+    };
+    strings.into_iter()
+        .map(|s| s.trim().trim_start_matches('#').to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The `#hashtags` of a text, skipping code blocks, code spans and
+/// numbers like `#42`.
+pub fn hashtags(text: &str) -> Vec<String> {
+    let mut tags = vec![];
+    let mut fenced = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") { fenced = !fenced; continue }
+        if fenced { continue }
+        // the odd pieces between backticks are code spans
+        for piece in line.split('`').step_by(2) {
+            tags.extend(HASHTAG.captures_iter(piece)
+                .map(|c| c[1].trim_end_matches(['/', '-']).to_string())
+                .filter(|tag| !tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit())));
+        }
+    }
+    tags
+}
+
+/// The normalized tag names of a note, in order of appearance and
+/// without the empty ones.
+pub fn extract(text: &str) -> Vec<String> {
+    let (matter, body) = front_matter(text);
+    let mut names = matter.as_ref()
+        .map(|matter| [strings(matter.get("tags")), strings(matter.get("tag"))].concat())
+        .unwrap_or_default();
+    names.extend(hashtags(body));
+    let mut seen = BTreeSet::new();
+    names.into_iter()
+        .filter(|name| check_name(name).is_ok())
+        .map(|name| Tag::new(&name).raw_name().to_string())
+        .filter(|name| seen.insert(name.clone()))
+        .collect()
+}
+
+/// A 64-bit FNV-1a digest, to tell changed notes apart.
+pub fn digest(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// What indexing changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexReport {
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub links_added: usize,
+    pub links_removed: usize,
+}
+
+/// Indexes the text of a note, whether it changed or not.
+pub async fn index_note(db: &Database, path: &str, text: &str, settings: &Settings) -> Result<IndexReport, DatabaseError> {
+    let mut report = IndexReport { indexed: 1, ..Default::default() };
+    apply(db, path, Some((&extract(text), &digest(text))), settings, &mut report).await?;
+    Ok(report)
+}
+
+/// Drops a note that is gone, with the links only it backed. Notes
+/// never indexed are left alone.
+pub async fn forget_note(db: &Database, path: &str, settings: &Settings) -> Result<IndexReport, DatabaseError> {
+    if stored_digest(db, path).await?.is_none() { return Ok(IndexReport::default()) }
+    let mut report = IndexReport { removed: 1, ..Default::default() };
+    apply(db, path, None, settings, &mut report).await?;
+    Ok(report)
+}
+
+/// Indexes the note at `path` if it changed since it was indexed, or
+/// forgets it if it is gone.
+pub async fn sync_note(db: &Database, path: &Path, settings: &Settings) -> anyhow::Result<IndexReport> {
+    let key = path.to_string_lossy();
+    if !path.exists() { return Ok(forget_note(db, &key, settings).await?) }
+    let text = std::fs::read_to_string(path)?;
+    if stored_digest(db, &key).await?.as_deref() == Some(digest(&text).as_str()) {
+        return Ok(IndexReport { unchanged: 1, ..Default::default() })
+    }
+    Ok(index_note(db, &key, &text, settings).await?)
+}
+
+async fn stored_digest(db: &Database, path: &str) -> Result<Option<String>, DatabaseError> {
+    let rows = db.read("notes", &["digest".to_string()], &format!("path = {}", db::quote(path)), "").await?;
+    Ok(rows.get::<String>(0).into_iter().next())
+}
+
+/// Brings the links of a note up to date with its tags and digest, or
+/// forgets it when there are none.
+async fn apply(db: &Database, path: &str, note: Option<(&[String], &str)>, settings: &Settings,
+        report: &mut IndexReport) -> Result<(), DatabaseError> {
+    let key = db::quote(path);
+    let rows = db.read("note_links", &["tag1".to_string(), "tag2".to_string(), "created".to_string()],
+        &format!("path = {}", key), "").await?;
+    let old: BTreeMap<(String, String), bool> = itertools::izip!(rows.get::<String>(0), rows.get::<String>(1), rows.get::<bool>(2))
+        .map(|(a, b, created)| ((a, b), created))
+        .collect();
+    let tags = note.map(|(tags, _)| tags).unwrap_or_default();
+    let new: BTreeSet<(String, String)> = tags.iter()
+        .flat_map(|a| tags.iter().filter(move |b| *b != a).map(move |b| (a.clone(), b.clone())))
+        .collect();
+    db.delete("note_links", "path", &key).await?;
+
+    let graph = db.graph().await?;
+    let origin_weight = |a: &str, b: &str| graph.id(a).zip(graph.id(b))
+        .and_then(|(from, to)| graph.link(from, to))
+        .filter(|link| link.is_origin)
+        .map(|link| link.weight);
+    for name in tags.iter().filter(|name| graph.id(name).is_none()) {
+        Tag::new(name).add_tag::<String>(db, &[]).await?;
+    }
+    for (a, b) in new.iter() {
+        let pair = format!("tag1 = {} AND tag2 = {}", db::quote(a), db::quote(b));
+        let backers = db.read("note_links", &["created".to_string()], &pair, "").await?.get::<bool>(0);
+        let linked = origin_weight(a, b).is_none();
+        if linked {
+            Tag::new(a).link_tags(db, &Tag::new(b), settings.weight).await?;
+            report.links_added += 1;
+            // a link set by hand and removed since is now made by the
+            // notes, for all of them
+            if !backers.is_empty() {
+                db.execute(&format!("UPDATE note_links SET created = true WHERE {};", pair)).await?;
+            }
+        }
+        let created = linked || backers.contains(&true) || old.get(&(a.clone(), b.clone())) == Some(&true);
+        db.create("note_links",
+            &["path".to_string(), "tag1".to_string(), "tag2".to_string(), "created".to_string()],
+            &[key.clone(), db::quote(a), db::quote(b), created.to_string()]
+        ).await?;
+    }
+    for ((a, b), created) in old.iter().filter(|(pair, _)| !new.contains(*pair)) {
+        let backers = db.read("note_links", &["path".to_string()],
+            &format!("tag1 = {} AND tag2 = {}", db::quote(a), db::quote(b)), "").await?;
+        if *created && backers.get::<String>(0).is_empty() && origin_weight(a, b) == Some(settings.weight) {
+            Tag::new(a).unlink_tags(db, &Tag::new(b)).await?;
+            report.links_removed += 1;
+        }
+    }
+
+    match note {
+        Some((_, digest)) => {
+            let entries = ["path".to_string(), "digest".to_string()];
+            let data = [key, db::quote(digest)];
+            db.update("notes", &entries, &data, &entries[1..], &data[1..], "true").await?;
+        },
+        None => { db.delete("notes", "path", &key).await?; },
+    }
+    Ok(())
+}
+
+/// Whether a path is a Markdown note.
+pub fn is_note(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("markdown"))
+}
+
+/// Indexes the notes under `dir` that changed since the last run, and
+/// forgets the ones that are gone, in one transaction. Hidden files and
+/// folders are skipped.
+pub async fn index_dir(db: &Database, dir: &Path, settings: &Settings) -> anyhow::Result<IndexReport> {
+    let root = dir.canonicalize()?;
+    let tx = db.begin().await?;
+    let db = &tx;
+    let rows = db.read("notes", &["path".to_string(), "digest".to_string()], "true", "").await?;
+    let mut known: HashMap<String, String> = rows.get::<String>(0).into_iter().zip(rows.get::<String>(1))
+        .filter(|(path, _)| Path::new(path).starts_with(&root))
+        .collect();
+
+    let mut report = IndexReport::default();
+    let walk = walkdir::WalkDir::new(&root).into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walk {
+        let entry = entry?;
+        if !entry.file_type().is_file() || !is_note(entry.path()) { continue }
+        let path = entry.path().to_string_lossy().to_string();
+        let text = std::fs::read_to_string(entry.path())?;
+        let digest = digest(&text);
+        if known.remove(&path).as_ref() == Some(&digest) {
+            report.unchanged += 1;
+            continue
+        }
+        report.indexed += 1;
+        apply(db, &path, Some((&extract(&text), &digest)), settings, &mut report).await?;
+    }
+    for path in known.into_keys() {
+        report.removed += 1;
+        apply(db, &path, None, settings, &mut report).await?;
+    }
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// The sync version of [`index_dir`].
+pub fn index_dir_sync(db: &Database, dir: &Path, settings: &Settings) -> anyhow::Result<IndexReport> {
+    block_on(async { index_dir(db, dir, settings).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_matter() {
+        let (matter, body) = front_matter("---\ntags: rust, cli\ntitle: x\n---\nbody");
+        assert_eq!(strings(matter.as_ref().and_then(|m| m.get("tags"))), vec!["rust", "cli"]);
+        assert_eq!(body, "body");
+
+        let (matter, _) = front_matter("+++\ntags = [\"#rust\", \"cli\"]\n+++\n");
+        assert_eq!(strings(matter.as_ref().and_then(|m| m.get("tags"))), vec!["rust", "cli"]);
+
+        let (matter, body) = front_matter("--- not a fence\n---\n");
+        assert!(matter.is_none());
+        assert_eq!(body, "--- not a fence\n---\n");
+    }
+
+    #[test]
+    fn test_hashtags() {
+        let text = "# Heading\n#rust and #cli/clap, issue #42, https://a.org/#anchor\n```\n#code\n```\nx `#span` #日本語";
+        assert_eq!(hashtags(text), vec!["rust", "cli/clap", "日本語"]);
+    }
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(extract("---\ntags: [rust, it's, ' ']\n---\n#rust #cli"), vec!["rust", "it's", "cli"]);
+        assert_ne!(digest("a"), digest("b"));
+    }
+}
@@ -0,0 +1,202 @@
+//! A small boolean query language over the tag graph.
+//!
+//! A query like `rust AND (cli OR tui) NOT web` is parsed by the grammar
+//! in `settings/query.pest` and evaluated against a [`TagGraph`]:
+//!
+//! - a tag matches itself with score 1 and every tag it links to with
+//!   the link weight; `rust>0.6` only keeps links heavier than 0.6;
+//! - `AND` (or just a space) keeps the tags matched by both sides and
+//!   multiplies their scores, the way derived links multiply weights;
+//! - `OR` keeps the tags matched by either side with the higher score;
+//! - `NOT` matches every tag its operand does not, with score 1.
+//!
+//! The keywords are upper case, so `and` is still a usable tag name,
+//! and names with blanks are double quoted.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::{graph::TagGraph, query::{Expr, run}};
+//!
+//! let graph = TagGraph::from_parts(vec![], vec![
+//!     ("rust".into(), "cli".into(), 0.9, true),
+//!     ("rust".into(), "web".into(), 0.7, true),
+//! ]);
+//! let hits = run(&graph, &Expr::parse("rust>0.5 NOT web").unwrap());
+//! assert_eq!(hits.iter().map(|h| h.name.as_str()).collect::<Vec<_>>(), vec!["rust", "cli"]);
+//! ```
+
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+use pest::{Parser, iterators::Pair};
+
+use crate::{db::crud::Database, graph::TagGraph};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "./settings/query.pest"]
+struct QueryParser;
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Tag { name: String, threshold: Option<f64> },
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+pub struct QueryHit {
+    pub name: String,
+    pub score: f64,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, Box<pest::error::Error<Rule>>> {
+        let mut pairs = QueryParser::parse(Rule::query, text)?;
+        // the grammar guarantees an `expr` before `EOI`
+        Ok(Expr::from_pair(pairs.next().unwrap()))
+    }
+
+    /// this function has a strong relation with the `pest` grammar.
+    fn from_pair(pair: Pair<Rule>) -> Expr {
+        match pair.as_rule() {
+            Rule::expr | Rule::term => {
+                let is_or = pair.as_rule() == Rule::expr;
+                let mut items: Vec<_> = pair.into_inner().map(Expr::from_pair).collect();
+                if items.len() == 1 { items.pop().unwrap() }
+                else if is_or { Expr::Or(items) }
+                else { Expr::And(items) }
+            },
+            Rule::factor => {
+                let mut inner = pair.into_inner().peekable();
+                let negated = inner.next_if(|p| p.as_rule() == Rule::not_op).is_some();
+                let operand = Expr::from_pair(inner.next().unwrap());
+                if negated { Expr::Not(Box::new(operand)) } else { operand }
+            },
+            Rule::atom => {
+                let mut inner = pair.into_inner();
+                let name = crate::normalize::normalize(inner.next().unwrap().as_str().trim_matches('"'));
+                let threshold = inner.next()
+                    .and_then(|t| t.into_inner().next())
+                    .map(|n| n.as_str().parse::<f64>().unwrap_or_default());
+                Expr::Tag { name, threshold }
+            },
+            rule => unreachable!("unexpected rule {:?}", rule),
+        }
+    }
+
+    /// The tag names the query mentions.
+    pub fn tags(&self) -> Vec<&str> {
+        match self {
+            Expr::Tag { name, .. } => vec![name.as_str()],
+            Expr::Not(inner) => inner.tags(),
+            Expr::And(items) | Expr::Or(items) => items.iter().flat_map(Expr::tags).collect(),
+        }
+    }
+
+    /// Scores of the matching tags, by tag id.
+    pub fn eval(&self, graph: &TagGraph) -> HashMap<usize, f64> {
+        match self {
+            Expr::Tag { name, threshold } => {
+                let mut scores = HashMap::new();
+                if let Some(id) = graph.id(name) {
+                    for link in graph.neighbors(id) {
+                        if matches!(threshold, Some(t) if link.weight <= *t) { continue }
+                        let score = scores.entry(link.to).or_insert(0.0);
+                        *score = link.weight.max(*score);
+                    }
+                    scores.insert(id, 1.0);
+                }
+                scores
+            },
+            Expr::Not(inner) => {
+                let excluded = inner.eval(graph);
+                (0..graph.len())
+                    .filter(|id| !excluded.contains_key(id))
+                    .map(|id| (id, 1.0))
+                    .collect()
+            },
+            Expr::And(items) => {
+                let mut items = items.iter().map(|e| e.eval(graph));
+                let first = items.next().unwrap_or_default();
+                items.fold(first, |acc, scores|
+                    acc.into_iter()
+                        .filter_map(|(id, s)| scores.get(&id).map(|t| (id, s * t)))
+                        .collect()
+                )
+            },
+            Expr::Or(items) => {
+                items.iter().map(|e| e.eval(graph)).fold(HashMap::new(), |mut acc, scores| {
+                    for (id, s) in scores {
+                        let score = acc.entry(id).or_insert(0.0);
+                        *score = s.max(*score);
+                    }
+                    acc
+                })
+            },
+        }
+    }
+}
+
+/// Evaluates the query and ranks the matches, highest score first.
+pub fn run(graph: &TagGraph, expr: &Expr) -> Vec<QueryHit> {
+    let mut hits: Vec<_> = expr.eval(graph).into_iter()
+        .map(|(id, score)| QueryHit { name: graph.name(id).into(), score })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    hits
+}
+
+/// Parses and runs a query against the database.
+pub async fn query(db: &Database, text: &str) -> anyhow::Result<Vec<QueryHit>> {
+    let expr = Expr::parse(text)?;
+    Ok(run(&*db.graph().await?, &expr))
+}
+
+/// The sync version of [`query`].
+pub fn query_sync(db: &Database, text: &str) -> anyhow::Result<Vec<QueryHit>> {
+    block_on(async { query(db, text).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> Expr {
+        Expr::Tag { name: name.into(), threshold: None }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Expr::parse("rust AND (cli OR tui) NOT web").unwrap(),
+            Expr::And(vec![
+                tag("rust"),
+                Expr::Or(vec![tag("cli"), tag("tui")]),
+                Expr::Not(Box::new(tag("web"))),
+            ])
+        );
+        assert_eq!(
+            Expr::parse("\"Hacker News\" NOTES>0.6").unwrap(),
+            Expr::And(vec![
+                tag("Hacker News"),
+                Expr::Tag { name: "NOTES".into(), threshold: Some(0.6) },
+            ])
+        );
+        assert!(Expr::parse("rust AND").is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        let graph = TagGraph::from_parts(vec![], vec![
+            ("rust".into(), "cli".into(), 0.8, true),
+            ("rust".into(), "tui".into(), 0.5, false),
+            ("go".into(), "cli".into(), 0.5, true),
+        ]);
+        let hits = run(&graph, &Expr::parse("rust go").unwrap());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "cli");
+        assert!((hits[0].score - 0.4).abs() < 1e-9);
+        assert_eq!(run(&graph, &Expr::parse("rust OR go").unwrap()).len(), 4);
+    }
+}
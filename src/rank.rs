@@ -0,0 +1,207 @@
+//! Centrality measures telling which tags are hubs of the graph.
+//!
+//! All measures follow the direction of the links:
+//!
+//! - the weighted PageRank spreads the score of a tag over its outgoing
+//!   links in proportion to their weights;
+//! - the weighted in/out degrees are the sums of the link weights;
+//! - the betweenness counts the shortest paths through a tag, where a
+//!   link is shorter the heavier it is (its length is `1 / weight`).
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::{graph::TagGraph, rank::rank};
+//!
+//! let graph = TagGraph::from_parts(vec![], vec![
+//!     ("a".into(), "hub".into(), 0.9, true),
+//!     ("b".into(), "hub".into(), 0.9, true),
+//!     ("hub".into(), "c".into(), 0.9, true),
+//! ]);
+//! let ranks = rank(&graph);
+//! let hub = ranks.iter().find(|r| r.name == "hub").unwrap();
+//! assert_eq!(hub.betweenness, 2.0);
+//! ```
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use futures::executor::block_on;
+use serde_derive::Serialize;
+
+use crate::{db::crud::{Database, DatabaseError}, graph::TagGraph};
+
+const DAMPING: f64 = 0.85;
+const ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Serialize)]
+pub struct TagRank {
+    pub name: String,
+    pub pagerank: f64,
+    pub in_degree: f64,
+    pub out_degree: f64,
+    pub betweenness: f64,
+}
+
+/// Links that take part in the measures: positive weights, no self-loops.
+fn usable(graph: &TagGraph) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+    graph.links()
+        .filter(|(from, link)| link.weight > 0.0 && *from != link.to)
+        .map(|(from, link)| (from, link.to, link.weight))
+}
+
+pub fn pagerank(graph: &TagGraph) -> Vec<f64> {
+    let n = graph.len();
+    if n == 0 { return vec![] }
+    let mut totals = vec![0.0; n];
+    for (from, _, weight) in usable(graph) { totals[from] += weight }
+
+    let mut scores = vec![1.0 / n as f64; n];
+    for _ in 0..ITERATIONS {
+        // tags without outgoing links spread their score evenly
+        let stranded: f64 = (0..n).filter(|id| totals[*id] == 0.0).map(|id| scores[id]).sum();
+        let base = (1.0 - DAMPING) / n as f64 + DAMPING * stranded / n as f64;
+        let mut next = vec![base; n];
+        for (from, to, weight) in usable(graph) {
+            next[to] += DAMPING * scores[from] * weight / totals[from];
+        }
+        let delta: f64 = next.iter().zip(scores.iter()).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if delta < TOLERANCE { break }
+    }
+    scores
+}
+
+/// The weighted `(in, out)` degree of every tag.
+pub fn degrees(graph: &TagGraph) -> Vec<(f64, f64)> {
+    let mut degrees = vec![(0.0, 0.0); graph.len()];
+    for (from, to, weight) in usable(graph) {
+        degrees[to].0 += weight;
+        degrees[from].1 += weight;
+    }
+    degrees
+}
+
+#[derive(PartialEq)]
+struct Visit(f64, usize);
+
+impl Eq for Visit {}
+
+impl Ord for Visit {
+    // reversed, so that the `BinaryHeap` pops the shortest distance
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0).then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Brandes' algorithm with Dijkstra, over link lengths `1 / weight`.
+pub fn betweenness(graph: &TagGraph) -> Vec<f64> {
+    let n = graph.len();
+    let mut adjacency: Vec<Vec<(usize, f64)>> = vec![vec![]; n];
+    for (from, to, weight) in usable(graph) { adjacency[from].push((to, 1.0 / weight)) }
+
+    let mut centrality = vec![0.0; n];
+    for source in 0..n {
+        let mut order = vec![];
+        let mut predecessors: Vec<Vec<usize>> = vec![vec![]; n];
+        let mut paths = vec![0.0; n];
+        let mut distance = vec![f64::INFINITY; n];
+        paths[source] = 1.0;
+        distance[source] = 0.0;
+        let mut queue = BinaryHeap::from([Visit(0.0, source)]);
+        while let Some(Visit(dist, v)) = queue.pop() {
+            if dist > distance[v] { continue }
+            order.push(v);
+            for (w, length) in adjacency[v].iter() {
+                let candidate = dist + length;
+                // relative tolerance, so that equal paths summed in a
+                // different order still count as ties
+                if (candidate - distance[*w]).abs() <= 1e-12 * candidate {
+                    paths[*w] += paths[v];
+                    predecessors[*w].push(v);
+                } else if candidate < distance[*w] {
+                    distance[*w] = candidate;
+                    paths[*w] = paths[v];
+                    predecessors[*w] = vec![v];
+                    queue.push(Visit(candidate, *w));
+                }
+            }
+        }
+        let mut dependency = vec![0.0; n];
+        for w in order.into_iter().rev() {
+            for v in predecessors[w].iter() {
+                dependency[*v] += paths[*v] / paths[w] * (1.0 + dependency[w]);
+            }
+            if w != source { centrality[w] += dependency[w] }
+        }
+    }
+    centrality
+}
+
+/// All measures for every tag, by descending PageRank.
+pub fn rank(graph: &TagGraph) -> Vec<TagRank> {
+    let pageranks = pagerank(graph);
+    let degrees = degrees(graph);
+    let betweenness = betweenness(graph);
+    let mut ranks: Vec<_> = (0..graph.len()).map(|id| TagRank {
+        name: graph.name(id).into(),
+        pagerank: pageranks[id],
+        in_degree: degrees[id].0,
+        out_degree: degrees[id].1,
+        betweenness: betweenness[id],
+    }).collect();
+    ranks.sort_by(|a, b| b.pagerank.total_cmp(&a.pagerank).then_with(|| a.name.cmp(&b.name)));
+    ranks
+}
+
+/// Loads the graph and ranks its tags.
+pub async fn rank_tags(db: &Database) -> Result<Vec<TagRank>, DatabaseError> {
+    Ok(rank(&*db.graph().await?))
+}
+
+/// The sync version of [`rank_tags`].
+pub fn rank_sync(db: &Database) -> Vec<TagRank> {
+    block_on(async {
+        rank_tags(db).await
+            .unwrap_or_else(|e| { crate::logger::warn(e.to_string()); vec![] })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagerank() {
+        let graph = TagGraph::from_parts(vec![], vec![
+            ("a".into(), "c".into(), 1.0, true),
+            ("b".into(), "c".into(), 0.5, true),
+            ("c".into(), "a".into(), 0.2, true),
+        ]);
+        let scores = pagerank(&graph);
+        assert!((scores.iter().sum::<f64>() - 1.0).abs() < 1e-6);
+        let c = graph.id("c").unwrap();
+        assert!(scores.iter().all(|s| *s <= scores[c]));
+    }
+
+    #[test]
+    fn test_betweenness_prefers_heavy_paths() {
+        // a -> b -> d is lighter (length 2) than a -> c -> d (length 20)
+        let graph = TagGraph::from_parts(vec![], vec![
+            ("a".into(), "b".into(), 1.0, true),
+            ("b".into(), "d".into(), 1.0, true),
+            ("a".into(), "c".into(), 0.1, true),
+            ("c".into(), "d".into(), 0.1, true),
+        ]);
+        let scores = betweenness(&graph);
+        assert_eq!(scores[graph.id("b").unwrap()], 1.0);
+        assert_eq!(scores[graph.id("c").unwrap()], 0.0);
+        assert_eq!(degrees(&graph)[graph.id("d").unwrap()], (1.1, 0.0));
+    }
+}
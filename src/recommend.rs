@@ -0,0 +1,120 @@
+//! Recommendation of related tags from a set of seed tags.
+//!
+//! The friction from all seeds is aggregated with a personalized
+//! PageRank: a random walk that follows links in proportion to their
+//! weights, ignoring their direction, and jumps back to a random seed
+//! with probability `1 - DAMPING` at every step. The tags where the walk
+//! spends most of its time, seeds excluded, are the recommendations.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::{graph::TagGraph, recommend::recommend};
+//!
+//! let graph = TagGraph::from_parts(vec![], vec![
+//!     ("rust".into(), "cli".into(), 0.9, true),
+//!     ("git".into(), "cli".into(), 0.8, true),
+//!     ("git".into(), "GitHub".into(), 0.2, true),
+//! ]);
+//! let suggested = recommend(&graph, &["rust", "git"], 5);
+//! assert_eq!(suggested[0].name, "cli");
+//! ```
+
+use futures::executor::block_on;
+
+use crate::{db::crud::{Database, DatabaseError}, graph::TagGraph};
+
+/// The probability of following a link instead of restarting at a seed.
+pub const DAMPING: f64 = 0.85;
+const ITERATIONS: usize = 50;
+const TOLERANCE: f64 = 1e-9;
+
+pub struct Recommendation {
+    pub name: String,
+    pub score: f64,
+}
+
+/// The stationary scores of the walk restarting at `seeds`, by tag id.
+pub fn personalized_pagerank(graph: &TagGraph, seeds: &[usize]) -> Vec<f64> {
+    let n = graph.len();
+    if seeds.is_empty() || n == 0 { return vec![0.0; n] }
+    let adjacency = graph.undirected(false);
+    let totals: Vec<f64> = adjacency.iter().map(|a| a.iter().map(|(_, w)| w).sum()).collect();
+    let mut restart = vec![0.0; n];
+    for seed in seeds { restart[*seed] += 1.0 / seeds.len() as f64 }
+
+    let mut scores = restart.clone();
+    for _ in 0..ITERATIONS {
+        let mut next: Vec<f64> = restart.iter().map(|r| (1.0 - DAMPING) * r).collect();
+        // the walk out of a tag without links restarts at the seeds
+        let mut stranded = 0.0;
+        for (id, neighbors) in adjacency.iter().enumerate() {
+            if totals[id] == 0.0 { stranded += scores[id]; continue }
+            for (to, weight) in neighbors {
+                next[*to] += DAMPING * scores[id] * weight / totals[id];
+            }
+        }
+        for (id, r) in restart.iter().enumerate() { next[id] += DAMPING * stranded * r }
+        let delta: f64 = next.iter().zip(scores.iter()).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if delta < TOLERANCE { break }
+    }
+    scores
+}
+
+/// Ranks the tags related to all `seeds`, seeds excluded. Unknown seed
+/// names are ignored.
+pub fn recommend(graph: &TagGraph, seeds: &[&str], limit: usize) -> Vec<Recommendation> {
+    let seeds: Vec<usize> = seeds.iter().filter_map(|s| graph.id(s)).collect();
+    let mut ranked: Vec<_> = personalized_pagerank(graph, &seeds).into_iter()
+        .enumerate()
+        .filter(|(id, score)| *score > 0.0 && !seeds.contains(id))
+        .map(|(id, score)| Recommendation { name: graph.name(id).into(), score })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Loads the graph and recommends tags for `seeds`.
+pub async fn recommend_tags(db: &Database, seeds: &[String], limit: usize) -> Result<Vec<Recommendation>, DatabaseError> {
+    let graph = db.graph().await?;
+    Ok(recommend(&graph, &seeds.iter().map(String::as_str).collect::<Vec<_>>(), limit))
+}
+
+/// The sync version of [`recommend_tags`], for `tune_html` and the
+/// web UI.
+pub fn recommend_sync(db: &Database, seeds: &[String], limit: usize) -> Vec<Recommendation> {
+    block_on(async {
+        recommend_tags(db, seeds, limit).await
+            .unwrap_or_else(|e| { crate::logger::warn(e.to_string()); vec![] })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagerank_sums_to_one() {
+        let graph = TagGraph::from_parts(vec![("lonely".into(), None)], vec![
+            ("a".into(), "b".into(), 0.5, true),
+            ("b".into(), "c".into(), 1.0, true),
+        ]);
+        let scores = personalized_pagerank(&graph, &[graph.id("a").unwrap()]);
+        assert!((scores.iter().sum::<f64>() - 1.0).abs() < 1e-6);
+        assert_eq!(scores[graph.id("lonely").unwrap()], 0.0);
+    }
+
+    #[test]
+    fn test_recommend_excludes_seeds() {
+        let graph = TagGraph::from_parts(vec![], vec![
+            ("a".into(), "b".into(), 0.5, true),
+            ("c".into(), "b".into(), 0.5, true),
+            ("c".into(), "d".into(), 0.1, true),
+        ]);
+        let names: Vec<_> = recommend(&graph, &["a", "c", "unknown"], 10)
+            .into_iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["b", "d"]);
+    }
+}
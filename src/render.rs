@@ -0,0 +1,99 @@
+//! The HTML pages `tune_html` renders from posts, see that binary for
+//! the markup.
+//!
+//! [`page`] splits a post into its title and paragraphs, and [`fill`]
+//! renders the paragraphs into a Handlebars template, each `{{ ... }}`
+//! block followed by the tags most related to its leading tag. Putting
+//! the tags of the blocks into the database is left to the caller.
+
+use futures::executor::block_on;
+use handlebars::Handlebars;
+
+use crate::{db::{self, crud::Database}, markup::Block, Tag};
+
+/// As something like `{{"Hacker News"}}` will generate a
+/// `{{#each Hacker News}}` in the Handlebars file, the space is
+/// replaced with this symbol.
+pub const BLANK_ESCAPE: &str = "ß";
+
+/// The title of a post, which is its first paragraph, and the other
+/// paragraphs as HTML. Paragraphs are separated by blank lines; the
+/// ones starting with `###` are raw HTML and lose the `###`, the others
+/// are put in `<p></p>`.
+pub fn page(text: &str) -> (String, String) {
+    let text = text.replace("\r\n", "\n");
+    let mut paragraphs = text.split("\n\n");
+    let title = paragraphs.next().unwrap_or_default().to_string();
+    let content = paragraphs
+        .map(|s| match s.trim().strip_prefix("###") {
+            Some(raw) => raw.trim().to_string(),
+            None => format!("<p>{}</p>", s.trim()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (title, content)
+}
+
+/// Renders the paragraphs `content` of a post, with its `blocks` as
+/// parsed from `content`, into the template file `template`.
+pub async fn fill(db: &Database, template: &str, title: &str, content: &str, blocks: &[Block]) -> anyhow::Result<String> {
+    let mut reg = Handlebars::new();
+    reg.register_template_file("page", template)?;
+
+    // replace the self-defined tags with `handlebars` tags, from the
+    // last one so that the spans of the others stay valid
+    let mut content = content.to_string();
+    for block in blocks.iter().rev() {
+        let name = block.main.raw_name();
+        let desc = block.main.desc.as_deref().map(db::unquote).unwrap_or_default();
+        let hyperlink = if desc.contains("http")
+            { format!("<a href=\"{}\">{}</a>", desc, name) } else { name.to_string() };
+        content.replace_range(block.span.clone(), &format!("<span id=\"tag\">{}\
+        {{{{#each {}}}}}{{{{#with this}}}}\
+            {{{{#if desc}}}}
+                <span class=\"bubble\"><a href={{{{desc}}}}>{{{{name}}}}</a></span>\
+            {{{{else}}}}
+                <span class=\"bubble\">{{{{name}}}}</span>\
+            {{{{/if}}}}\
+        {{{{/with}}}}{{{{/each}}}}</span>",
+            hyperlink,
+            name.replace(' ', BLANK_ESCAPE)
+        ));
+    }
+    reg.register_template_string("content", content)?;
+
+    // construct the json from database
+    let mut env_json = serde_json::json!({
+        "date": chrono::Local::now()
+            .date_naive()
+            .format("%m/%d")
+            .to_string(),
+        "name": title,
+    });
+    for block in blocks.iter() {
+        let mut related = vec![];
+        for name in block.main.query_top_related(db).await {
+            let desc = Tag::new(&name).query_desc(db).await.unwrap_or_default();
+            related.push(serde_json::json!({ "name": name, "desc": desc }));
+        }
+        env_json[block.main.raw_name().replace(' ', BLANK_ESCAPE)] = related.into();
+    }
+    Ok(reg.render("page", &env_json)?)
+}
+
+/// The sync version of [`fill`].
+pub fn fill_sync(db: &Database, template: &str, title: &str, content: &str, blocks: &[Block]) -> anyhow::Result<String> {
+    block_on(async { fill(db, template, title, content, blocks).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        let (title, content) = page("A title\r\n\r\nsome {{ text }}\n\n### <hr>\n");
+        assert_eq!(title, "A title");
+        assert_eq!(content, "<p>some {{ text }}</p>\n<hr>");
+    }
+}
@@ -0,0 +1,128 @@
+//! Tag name resolution with did-you-mean suggestions.
+//!
+//! [`crate::Tag::link_tags`] does not check that its tags exist, so the
+//! user interfaces resolve names first. A [`Resolver`] holds the tag
+//! names of a database and proposes the closest ones for a missing name,
+//! comparing their [`normalize_key`] forms by [`edit_distance`].
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::resolve::{Resolver, Resolution};
+//!
+//! let resolver = Resolver::new(vec!["rust".into(), "Hacker News".into()]);
+//! assert!(matches!(resolver.resolve("rust"), Resolution::Found(_)));
+//! if let Resolution::Missing(suggestions) = resolver.resolve("hacker-news") {
+//!     assert_eq!(suggestions[0].name, "Hacker News");
+//! }
+//! ```
+
+use futures::executor::block_on;
+
+use crate::db::crud::Database;
+
+pub struct Suggestion {
+    pub name: String,
+    /// The edit distance between the normalized forms, 0 if they only
+    /// differ by case, quoting or separators.
+    pub distance: usize,
+}
+
+pub enum Resolution {
+    Found(String),
+    Missing(Vec<Suggestion>),
+}
+
+/// The form two names are compared in: lowercased, with quotes,
+/// whitespace, `-` and `_` removed.
+pub fn normalize_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '\'' | '"' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The Levenshtein distance, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+pub struct Resolver {
+    names: Vec<String>,
+}
+
+impl Resolver {
+    /// How many suggestions [`Resolver::resolve`] gives at most.
+    pub const SUGGESTIONS: usize = 3;
+
+    pub fn new(names: Vec<String>) -> Self {
+        Resolver { names }
+    }
+
+    /// Reads all tag names of the database.
+    pub fn load(db: &Database) -> Self {
+        Resolver::new(crate::Tag::get_tags(db))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Existing names close to `name`, closest first. A name is close
+    /// when its normalized form is within a third of the length away.
+    pub fn suggest(&self, name: &str, limit: usize) -> Vec<Suggestion> {
+        let key = normalize_key(name);
+        let threshold = (key.chars().count() / 3).max(1);
+        let mut suggestions: Vec<_> = self.names.iter()
+            .filter(|n| n.as_str() != name)
+            .map(|n| Suggestion { name: n.clone(), distance: edit_distance(&key, &normalize_key(n)) })
+            .filter(|s| s.distance <= threshold)
+            .collect();
+        suggestions.sort_by(|a, b| a.distance.cmp(&b.distance).then_with(|| a.name.cmp(&b.name)));
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    pub fn resolve(&self, name: &str) -> Resolution {
+        if self.contains(name) { Resolution::Found(name.into()) }
+        else { Resolution::Missing(self.suggest(name, Self::SUGGESTIONS)) }
+    }
+}
+
+/// The sync shortcut of [`Resolver::load`] and [`Resolver::resolve`]
+/// for a single name.
+pub fn resolve_sync(db: &Database, name: &str) -> Resolution {
+    if block_on(crate::Tag::new(name).exists(db)) { Resolution::Found(name.into()) }
+    else { Resolver::load(db).resolve(name) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("机器学习", "机器学"), 1);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let resolver = Resolver::new(vec!["rust".into(), "Rust".into(), "rest".into(), "git".into()]);
+        let names: Vec<_> = resolver.suggest("rust ", 3).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["Rust", "rust", "rest"]);
+        assert!(matches!(resolver.resolve("golang"), Resolution::Missing(v) if v.is_empty()));
+    }
+}
@@ -0,0 +1,261 @@
+//! Line-delimited JSON-RPC 2.0, spoken by `frictune rpc` on stdin and
+//! stdout for editor plugins.
+//!
+//! Each line holds one request, or a batch of them, and gets one line
+//! back; notifications, requests without an `id`, get none. A request
+//! whose `id` is `null` is answered with `"id": null`. The
+//! parameters are named:
+//!
+//! | method      | params                                | result                   |
+//! |-------------|---------------------------------------|--------------------------|
+//! | `add`       | `name`, `desc?`, `links?` as `[[name, weight]]` | `{name, desc, neighbors}` |
+//! | `link`      | `source`, `target`, `weight`          | `{source, target, weight}` |
+//! | `neighbors` | `name`                                | `[{name, weight}]`, heaviest first |
+//! | `relation`  | `source`, `target`                    | the weight, or `null`    |
+//! | `describe`  | `name`, `desc`                        | `{name, desc, neighbors}` |
+//! | `search`    | `query`, `limit?`                     | `[{name, info, score}]`  |
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "relation", "params": {"source": "rust", "target": "cargo"}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":0.8}
+//! ```
+//!
+//! Besides the codes of the specification, errors use
+//! [`TAG_NOT_FOUND`], [`INVALID_WEIGHT`] and [`DATABASE_ERROR`], with
+//! the offending names or the database message in `data`.
+
+use std::io::{BufRead, Write};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    db::crud::{Database, DatabaseError},
+    store::blocking::TagStore,
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const TAG_NOT_FOUND: i64 = -32001;
+pub const INVALID_WEIGHT: i64 = -32002;
+pub const DATABASE_ERROR: i64 = -32003;
+
+pub const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        RpcError { code, message: message.to_string(), data: None }
+    }
+
+    fn with_data(self, data: Value) -> Self {
+        RpcError { data: Some(data), ..self }
+    }
+}
+
+impl From<DatabaseError> for RpcError {
+    fn from(e: DatabaseError) -> Self {
+        RpcError::new(DATABASE_ERROR, "database error").with_data(json!(e.to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// `None` when absent, `Some(Value::Null)` when `null`.
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+struct AddParams {
+    name: String,
+    desc: Option<String>,
+    #[serde(default)]
+    links: Vec<(String, f64)>,
+}
+
+#[derive(Deserialize)]
+struct LinkParams {
+    source: String,
+    target: String,
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+struct NameParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PairParams {
+    source: String,
+    target: String,
+}
+
+#[derive(Deserialize)]
+struct DescribeParams {
+    name: String,
+    desc: String,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    limit: Option<usize>,
+}
+
+fn params<T: serde::de::DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    serde_json::from_value(params.unwrap_or(Value::Null))
+        .map_err(|e| RpcError::new(INVALID_PARAMS, "invalid params").with_data(json!(e.to_string())))
+}
+
+fn valid_weight(weight: f64) -> Result<f64, RpcError> {
+    if (0.0..=1.0).contains(&weight) { Ok(weight) }
+    else { Err(RpcError::new(INVALID_WEIGHT, "weight is not within [0, 1]").with_data(json!(weight))) }
+}
+
+/// One session, holding its connection until the input ends.
+pub struct Session {
+    store: TagStore,
+}
+
+impl Session {
+    pub fn new(db: Database) -> Self {
+        Session { store: TagStore::from_database(db) }
+    }
+
+    fn require(&self, name: &str) -> Result<String, RpcError> {
+        let tag = self.store.tag(name);
+        if tag.exists() { Ok(tag.name().into()) }
+        else { Err(RpcError::new(TAG_NOT_FOUND, "tag not found").with_data(json!(tag.name()))) }
+    }
+
+    fn view(&self, name: &str) -> Result<Value, RpcError> {
+        let tag = self.store.tag(name);
+        let (name, desc) = (tag.name().to_string(), tag.desc()?);
+        Ok(json!({ "name": name, "desc": desc, "neighbors": self.neighbors(&name)? }))
+    }
+
+    fn neighbors(&self, name: &str) -> Result<Value, RpcError> {
+        let neighbors: Vec<_> = self.store.tag(name).neighbors()?.into_iter()
+            .map(|(name, weight)| json!({ "name": name, "weight": weight }))
+            .collect();
+        Ok(json!(neighbors))
+    }
+
+    /// Runs one method.
+    pub fn call(&self, method: &str, raw: Option<Value>) -> Result<Value, RpcError> {
+        match method {
+            "add" => {
+                let p: AddParams = params(raw)?;
+                for (_, weight) in p.links.iter() { valid_weight(*weight)?; }
+                let links: Vec<(&str, f64)> = p.links.iter().map(|(n, w)| (n.as_str(), *w)).collect();
+                let tag = self.store.tag(&p.name);
+                tag.add_linked(&links)?;
+                if let Some(desc) = p.desc.filter(|d| !d.is_empty()) {
+                    tag.describe(&desc)?;
+                }
+                self.view(&p.name)
+            },
+            "link" => {
+                let p: LinkParams = params(raw)?;
+                let weight = valid_weight(p.weight)?;
+                let source = self.require(&p.source)?;
+                let target = self.require(&p.target)?;
+                self.store.tag(&source).link(&target, weight)?;
+                Ok(json!({ "source": source, "target": target, "weight": weight }))
+            },
+            "neighbors" => {
+                let p: NameParams = params(raw)?;
+                let name = self.require(&p.name)?;
+                self.neighbors(&name)
+            },
+            "relation" => {
+                let p: PairParams = params(raw)?;
+                Ok(json!(self.store.tag(&p.source).weight_to(&p.target)?))
+            },
+            "describe" => {
+                let p: DescribeParams = params(raw)?;
+                self.store.tag(&p.name).describe(&p.desc)?;
+                self.view(&p.name)
+            },
+            "search" => {
+                let p: SearchParams = params(raw)?;
+                let hits: Vec<_> = self.store.search(&p.query, p.limit.unwrap_or(DEFAULT_LIMIT))?.into_iter()
+                    .map(|hit| json!({ "name": hit.name, "info": hit.info, "score": hit.score }))
+                    .collect();
+                Ok(json!(hits))
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "method not found").with_data(json!(method))),
+        }
+    }
+
+    /// The response to one request, `None` for a notification.
+    fn respond(&self, request: Value) -> Option<Value> {
+        let request: Request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null,
+                RpcError::new(INVALID_REQUEST, "invalid request").with_data(json!(e.to_string())))),
+        };
+        if request.jsonrpc != "2.0" {
+            return Some(error_response(request.id.unwrap_or(Value::Null),
+                RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")));
+        }
+        let result = self.call(&request.method, request.params);
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_response(id, e),
+        })
+    }
+
+    /// The line answering `line`, `None` if nothing is due.
+    pub fn handle_line(&self, line: &str) -> Option<String> {
+        if line.trim().is_empty() { return None }
+        let response = match serde_json::from_str::<Value>(line) {
+            Err(e) => Some(error_response(Value::Null,
+                RpcError::new(PARSE_ERROR, "parse error").with_data(json!(e.to_string())))),
+            Ok(Value::Array(batch)) if batch.is_empty() =>
+                Some(error_response(Value::Null, RpcError::new(INVALID_REQUEST, "empty batch"))),
+            Ok(Value::Array(batch)) => {
+                let responses: Vec<Value> = batch.into_iter().filter_map(|r| self.respond(r)).collect();
+                if responses.is_empty() { None } else { Some(Value::Array(responses)) }
+            },
+            Ok(request) => self.respond(request),
+        };
+        response.map(|r| r.to_string())
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Answers the lines of `input` on `output` until the input ends.
+pub fn serve(db: Database, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    let session = Session::new(db);
+    for line in input.lines() {
+        if let Some(response) = session.handle_line(&line?) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
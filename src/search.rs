@@ -0,0 +1,272 @@
+//! Full-text search over tag names and descriptions.
+//!
+//! Both backends share [`tokenize`], which splits latin text into
+//! lowercased words and CJK text into single characters plus bigrams,
+//! so that `机器学习` can be found by `学习`. The SQLite backend keeps
+//! the tokens in an FTS5 table (`tags_fts`) ranked by `bm25`, while the
+//! GlueSQL backend builds an [`Index`] in memory for every search.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::search::tokenize;
+//!
+//! assert_eq!(tokenize("Hacker News"), vec!["hacker", "news"]);
+//! assert_eq!(tokenize("机器学习"), vec!["机", "机器", "器", "器学", "学", "学习", "习"]);
+//! ```
+
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+
+use crate::db::{self, crud::{Database, DatabaseResult, DatabaseError}};
+
+/// How much more a token in the tag name counts than one in the description.
+pub const NAME_BOOST: f64 = 10.0;
+pub const INFO_BOOST: f64 = 1.0;
+
+pub struct SearchHit {
+    pub name: String,
+    pub info: Option<String>,
+    pub score: f64,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+        | 0x3400..=0x4DBF   // CJK Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF   // Hangul Syllables
+        | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // CJK Extension B and later
+    )
+}
+
+/// Splits text into search tokens. Runs of letters and digits become
+/// one lowercased token, and every CJK character yields itself and the
+/// bigram it starts, because CJK text has no spaces between words.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let chars: Vec<char> = text.chars().collect();
+    for (idx, c) in chars.iter().enumerate() {
+        if is_cjk(*c) {
+            if !word.is_empty() { tokens.push(std::mem::take(&mut word)) }
+            tokens.push(c.to_string());
+            if let Some(next) = chars.get(idx + 1).filter(|n| is_cjk(**n)) {
+                tokens.push(format!("{}{}", c, next));
+            }
+        } else if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() { tokens.push(word) }
+    tokens
+}
+
+/// An in-memory inverted index, used where FTS5 is not available.
+#[derive(Default)]
+pub struct Index {
+    docs: Vec<(String, Option<String>)>,
+    /// token -> (document, weighted term frequency)
+    postings: HashMap<String, Vec<(usize, f64)>>,
+}
+
+impl Index {
+    pub fn insert(&mut self, name: &str, info: Option<&str>) {
+        let doc = self.docs.len();
+        self.docs.push((name.into(), info.map(String::from)));
+        let mut freq: HashMap<String, f64> = HashMap::new();
+        for token in tokenize(name) {
+            *freq.entry(token).or_default() += NAME_BOOST;
+        }
+        for token in tokenize(info.unwrap_or_default()) {
+            *freq.entry(token).or_default() += INFO_BOOST;
+        }
+        for (token, tf) in freq {
+            self.postings.entry(token).or_default().push((doc, tf));
+        }
+    }
+
+    /// Ranks documents by a tf-idf sum over the query tokens. A query
+    /// token also matches longer tokens it is a prefix of, at half weight.
+    pub fn search(&self, text: &str, limit: usize) -> Vec<SearchHit> {
+        let total = self.docs.len() as f64;
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for query in tokenize(text) {
+            for (token, postings) in self.postings.iter() {
+                let factor = if *token == query { 1.0 }
+                    else if token.starts_with(&query) { 0.5 }
+                    else { continue };
+                let idf = (1.0 + total / postings.len() as f64).ln();
+                for (doc, tf) in postings {
+                    *scores.entry(*doc).or_default() += factor * idf * tf;
+                }
+            }
+        }
+        let mut hits: Vec<_> = scores.into_iter()
+            .map(|(doc, score)| SearchHit {
+                name: self.docs[doc].0.clone(),
+                info: self.docs[doc].1.clone(),
+                score,
+            }).collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+/// Builds an FTS5 `MATCH` expression, with every token as a prefix query.
+#[cfg(not(target_arch = "wasm32"))]
+fn match_expression(text: &str) -> String {
+    tokenize(text).iter()
+        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+/// Refreshes the search entry of a tag after it is written. `name` is
+/// the quoted form kept in [`crate::Tag::name`]. GlueSQL has nothing
+/// to refresh, as its index is built per search.
+pub async fn reindex_tag(db: &Database, name: &str) -> Result<DatabaseResult, DatabaseError> {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            unindex_tag(db, name).await?;
+            let things = db.read("tags",
+                &["tag_name".to_string(), "info".to_string()],
+                &format!("tag_name = {}", name),
+                ""
+            ).await?;
+            match things.get::<String>(0).first() {
+                Some(tag_name) => {
+                    let info = things.get::<Option<String>>(1).pop().flatten().unwrap_or_default();
+                    db.create("tags_fts",
+                        &["tag_name".to_string(), "name_tokens".to_string(), "info_tokens".to_string()],
+                        &[db::quote(tag_name), db::quote(&tokenize(tag_name).join(" ")), db::quote(&tokenize(&info).join(" "))]
+                    ).await
+                },
+                None => Ok(DatabaseResult::Success("no such tag".into())),
+            }
+        }
+        else {
+            let _ = (db, name);
+            Ok(DatabaseResult::Success("in-memory index".into()))
+        }
+    }
+}
+
+/// Drops the search entry of a tag.
+pub async fn unindex_tag(db: &Database, name: &str) -> Result<DatabaseResult, DatabaseError> {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            db.delete("tags_fts", "tag_name", name).await
+        }
+        else {
+            let _ = (db, name);
+            Ok(DatabaseResult::Success("in-memory index".into()))
+        }
+    }
+}
+
+/// Brings `tags_fts` in step with `tags` by comparing the tokens of
+/// every tag, in one transaction, and returns how many entries were
+/// refreshed or dropped. The writes of the `Tag` functions keep the
+/// index in step; this catches the rest, such as the tags written
+/// before the migration that created it, by raw writes or by another
+/// program, and runs when the database is opened.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sync_index(db: &Database) -> Result<usize, DatabaseError> {
+    let tags = db.read("tags", &["tag_name".to_string(), "info".to_string()], "true", "").await?;
+    let entries = db.read("tags_fts",
+        &["tag_name".to_string(), "name_tokens".to_string(), "info_tokens".to_string()], "true", "").await?;
+    let mut indexed: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for (name, name_tokens, info_tokens) in itertools::izip!(entries.get::<String>(0), entries.get::<String>(1), entries.get::<String>(2)) {
+        indexed.entry(name).or_default().push((name_tokens, info_tokens));
+    }
+    let stale: Vec<String> = tags.get::<String>(0).into_iter().zip(tags.get::<Option<String>>(1))
+        .filter(|(name, info)| {
+            let expected = (tokenize(name).join(" "), tokenize(info.as_deref().unwrap_or_default()).join(" "));
+            indexed.remove(name) != Some(vec![expected])
+        })
+        .map(|(name, _)| name)
+        .collect();
+    let changed = stale.len() + indexed.len();
+    if changed == 0 { return Ok(0) }
+    let tx = db.begin().await?;
+    for name in indexed.into_keys() {
+        unindex_tag(&tx, &db::quote(&name)).await?;
+    }
+    for name in stale {
+        reindex_tag(&tx, &db::quote(&name)).await?;
+    }
+    tx.commit().await?;
+    Ok(changed)
+}
+
+/// Searches tag names and descriptions, best matches first.
+pub async fn search(db: &Database, text: &str, limit: usize) -> Result<Vec<SearchHit>, DatabaseError> {
+    cfg_if::cfg_if! {
+        if #[cfg(not(target_arch = "wasm32"))] {
+            let expression = match_expression(text);
+            if expression.is_empty() { return Ok(vec![]) }
+            let things = db.read("tags_fts JOIN tags USING (tag_name)",
+                &["tags.tag_name".to_string(), "tags.info".to_string(),
+                    format!("bm25(tags_fts, 0.0, {}, {})", NAME_BOOST, INFO_BOOST)],
+                &format!("tags_fts MATCH {}", db::quote(&expression)),
+                &format!("ORDER BY 3 LIMIT {}", limit)
+            ).await?;
+            // bm25 is negative, and lower is better
+            Ok(itertools::izip!(
+                things.get::<String>(0),
+                things.get::<Option<String>>(1),
+                things.get::<f64>(2)
+            ).map(|(name, info, rank)| SearchHit { name, info, score: -rank }).collect())
+        }
+        else {
+            let things = db.read("tags",
+                &["tag_name".to_string(), "info".to_string()],
+                "true",
+                ""
+            ).await?;
+            let mut index = Index::default();
+            for (name, info) in things.get::<String>(0).into_iter().zip(things.get::<String>(1)) {
+                index.insert(&name, Some(info.as_str()).filter(|i| !i.is_empty()));
+            }
+            Ok(index.search(text, limit))
+        }
+    }
+}
+
+/// The sync version of [`search`].
+pub fn search_sync(db: &Database, text: &str, limit: usize) -> Vec<SearchHit> {
+    block_on(async {
+        search(db, text, limit).await
+            .unwrap_or_else(|e| { crate::logger::warn(e.to_string()); vec![] })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_mixed() {
+        assert_eq!(tokenize("Rust 编程, GitHub!"), vec!["rust", "编", "编程", "程", "github"]);
+        assert!(tokenize(" - ").is_empty());
+    }
+
+    #[test]
+    fn test_index_ranking() {
+        let mut index = Index::default();
+        index.insert("machine learning", None);
+        index.insert("机器学习", Some("https://en.wikipedia.org/wiki/Machine_learning"));
+        index.insert("git", Some("https://git-scm.com/"));
+        let hits = index.search("machine", 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].name, "machine learning");
+        assert_eq!(index.search("学习", 10)[0].name, "机器学习");
+        assert_eq!(index.search("gi", 10)[0].name, "git");
+    }
+}
@@ -0,0 +1,317 @@
+//! A local HTTP server for the `Tag` API, run by `frictune-server`.
+//!
+//! Every answer is JSON; errors come as `{"error": "..."}` with a 4xx or
+//! 5xx status. Names in paths and queries are percent-encoded and
+//! normalized like [`crate::Tag::new`]. A body over [`MAX_BODY`] bytes
+//! is refused with 413 before it is parsed.
+//!
+//! | request                            | answer                                  |
+//! |------------------------------------|-----------------------------------------|
+//! | `GET /tags`                        | all tag names                           |
+//! | `GET /tags?q=TEXT&limit=N`         | search hits `{name, info, score}`       |
+//! | `GET /tags/NAME`                   | `{name, desc, neighbors}`, heaviest first |
+//! | `POST /tags` `{name, desc?, links?}` | adds a tag, `links` as `[[name, weight]]` |
+//! | `PUT /tags/NAME` `{desc}`          | sets the description                    |
+//! | `DELETE /tags/NAME`                | removes the tag and its links           |
+//! | `PUT /links` `{source, target, weight}` | links two existing tags            |
+//! | `DELETE /links?source=A&target=B`  | removes the link                        |
+//! | `GET /relation?source=A&target=B`  | `{source, target, weight}`, `null` if unlinked |
+//! | `GET /graph`                       | `{nodes, links}` as drawn by the web graph |
+//!
+//! [`handle`] answers one request without any networking; [`Server`]
+//! puts it behind a socket on `127.0.0.1`.
+
+use std::{io::Read, sync::Arc, thread};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    db::crud::{Database, DatabaseError},
+    store::blocking::TagStore,
+};
+
+pub const DEFAULT_PORT: u16 = 8787;
+pub const DEFAULT_LIMIT: usize = 10;
+/// The largest request body read, in bytes.
+pub const MAX_BODY: u64 = 1 << 20;
+
+/// The status and JSON body of an answer.
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    fn ok(body: Value) -> Self {
+        Reply { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Reply { status, body: json!({ "error": message.to_string() }) }
+    }
+}
+
+impl From<DatabaseError> for Reply {
+    fn from(e: DatabaseError) -> Self {
+        Reply::error(500, e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Neighbor {
+    pub name: String,
+    pub weight: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TagView {
+    pub name: String,
+    pub desc: Option<String>,
+    pub neighbors: Vec<Neighbor>,
+}
+
+#[derive(Deserialize)]
+struct NewTag {
+    name: String,
+    desc: Option<String>,
+    #[serde(default)]
+    links: Vec<(String, f64)>,
+}
+
+#[derive(Deserialize)]
+struct TagUpdate {
+    desc: String,
+}
+
+#[derive(Deserialize)]
+struct NewLink {
+    source: String,
+    target: String,
+    weight: f64,
+}
+
+/// Decodes `%XX` escapes, and `+` as a space in queries.
+fn percent_decode(text: &str, plus_as_space: bool) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+                continue
+            },
+            b'+' if plus_as_space => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The decoded `key=value` pairs of a query.
+type Query = Vec<(String, String)>;
+
+/// The path segments and the query pairs of a request target.
+fn split_url(url: &str) -> Option<(Vec<String>, Query)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments = path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode(s, false))
+        .collect::<Option<Vec<_>>>()?;
+    let pairs = query.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            Some((percent_decode(k, true)?, percent_decode(v, true)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((segments, pairs))
+}
+
+fn param<'q>(query: &'q [(String, String)], key: &str) -> Option<&'q str> {
+    query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+fn body<'b, T: serde::Deserialize<'b>>(body: &'b str) -> Result<T, Reply> {
+    serde_json::from_str(body).map_err(|e| Reply::error(400, e))
+}
+
+fn valid_weight(weight: f64) -> Result<f64, Reply> {
+    if (0.0..=1.0).contains(&weight) { Ok(weight) }
+    else { Err(Reply::error(400, format!("weight {} is not within [0, 1]", weight))) }
+}
+
+fn view(store: &TagStore, name: &str) -> Result<Reply, Reply> {
+    let tag = store.tag(name);
+    if !tag.exists() {
+        return Err(Reply::error(404, format!("no tag {}", tag.name())));
+    }
+    let neighbors = tag.neighbors()?.into_iter()
+        .map(|(name, weight)| Neighbor { name, weight })
+        .collect();
+    let view = TagView { name: tag.name().into(), desc: tag.desc()?, neighbors };
+    Ok(Reply::ok(serde_json::to_value(view).unwrap()))
+}
+
+fn require(store: &TagStore, name: &str) -> Result<(), Reply> {
+    let tag = store.tag(name);
+    if tag.exists() { Ok(()) }
+    else { Err(Reply::error(404, format!("no tag {}", tag.name()))) }
+}
+
+fn route(store: &TagStore, method: &str, url: &str, text: &str) -> Result<Reply, Reply> {
+    let (path, query) = split_url(url).ok_or_else(|| Reply::error(400, "malformed url"))?;
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    match (method, path.as_slice()) {
+        ("GET", ["tags"]) => match param(&query, "q") {
+            Some(text) => {
+                let limit = match param(&query, "limit") {
+                    Some(limit) => limit.parse().map_err(|_| Reply::error(400, "limit is not a number"))?,
+                    None => DEFAULT_LIMIT,
+                };
+                let hits: Vec<_> = store.search(text, limit)?.into_iter()
+                    .map(|hit| json!({ "name": hit.name, "info": hit.info, "score": hit.score }))
+                    .collect();
+                Ok(Reply::ok(json!(hits)))
+            },
+            None => Ok(Reply::ok(json!(store.tags()?))),
+        },
+        ("POST", ["tags"]) => {
+            let new: NewTag = body(text)?;
+            if store.tag(&new.name).exists() {
+                return Err(Reply::error(409, format!("tag {} exists", store.tag(&new.name).name())));
+            }
+            for (_, weight) in new.links.iter() { valid_weight(*weight)?; }
+            let links: Vec<(&str, f64)> = new.links.iter().map(|(n, w)| (n.as_str(), *w)).collect();
+            let tag = store.tag(&new.name);
+            tag.add_linked(&links)?;
+            if let Some(desc) = new.desc.filter(|d| !d.is_empty()) {
+                tag.describe(&desc)?;
+            }
+            let name = tag.name().to_string();
+            view(store, &name).map(|reply| Reply { status: 201, ..reply })
+        },
+        ("GET", ["tags", name]) => view(store, name),
+        ("PUT", ["tags", name]) => {
+            let update: TagUpdate = body(text)?;
+            require(store, name)?;
+            store.tag(name).describe(&update.desc)?;
+            view(store, name)
+        },
+        ("DELETE", ["tags", name]) => {
+            require(store, name)?;
+            let tag = store.tag(name);
+            tag.remove()?;
+            Ok(Reply::ok(json!({ "removed": tag.name() })))
+        },
+        ("PUT", ["links"]) => {
+            let link: NewLink = body(text)?;
+            let weight = valid_weight(link.weight)?;
+            require(store, &link.source)?;
+            require(store, &link.target)?;
+            let target = store.tag(&link.target).name().to_string();
+            let tag = store.tag(&link.source);
+            tag.link(&target, weight)?;
+            Ok(Reply::ok(json!({ "source": tag.name(), "target": target, "weight": weight })))
+        },
+        ("DELETE", ["links"]) | ("GET", ["relation"]) => {
+            let (Some(source), Some(target)) = (param(&query, "source"), param(&query, "target")) else {
+                return Err(Reply::error(400, "source and target are required"));
+            };
+            let weight = store.tag(source).weight_to(target)?;
+            let (source, target) = (store.tag(source).name().to_string(), store.tag(target).name().to_string());
+            if method == "GET" {
+                return Ok(Reply::ok(json!({ "source": source, "target": target, "weight": weight })));
+            }
+            if weight.is_none() {
+                return Err(Reply::error(404, format!("no link from {} to {}", source, target)));
+            }
+            store.tag(&source).unlink(&target)?;
+            Ok(Reply::ok(json!({ "source": source, "target": target })))
+        },
+        ("GET", ["graph"]) => {
+            let graph = store.graph()?;
+            Ok(Reply::ok(json!({ "nodes": graph.draw_nodes(), "links": graph.draw_links() })))
+        },
+        (_, ["tags"] | ["tags", _] | ["links"] | ["relation"] | ["graph"]) =>
+            Err(Reply::error(405, format!("{} is not allowed here", method))),
+        _ => Err(Reply::error(404, format!("no route for {}", url))),
+    }
+}
+
+/// Answers one request.
+pub fn handle(store: &TagStore, method: &str, url: &str, body: &str) -> Reply {
+    route(store, method, url, body).unwrap_or_else(|reply| reply)
+}
+
+pub struct Server {
+    http: Arc<tiny_http::Server>,
+    store: TagStore,
+}
+
+impl Server {
+    /// Listens on `127.0.0.1:port`; port 0 picks a free one.
+    pub fn bind(port: u16, db: Database) -> anyhow::Result<Self> {
+        let http = tiny_http::Server::http(("127.0.0.1", port)).map_err(|e| anyhow::anyhow!(e))?;
+        Ok(Server { http: Arc::new(http), store: TagStore::from_database(db) })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.http.server_addr().to_ip().map(|addr| addr.port()).unwrap_or_default()
+    }
+
+    /// Answers requests on `workers` threads, each with its own handle
+    /// on the database. Returns when the listener is closed.
+    pub fn run(self, workers: usize) {
+        let threads: Vec<_> = (0..workers.max(1)).map(|_| {
+            let (http, store) = (self.http.clone(), self.store.clone());
+            thread::spawn(move || {
+                for request in http.incoming_requests() {
+                    respond(&store, request);
+                }
+            })
+        }).collect();
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn respond(store: &TagStore, mut request: tiny_http::Request) {
+    let mut bytes = vec![];
+    let reply = match request.as_reader().take(MAX_BODY + 1).read_to_end(&mut bytes) {
+        Ok(read) if read as u64 > MAX_BODY => Reply::error(413, format!("the body is over {} bytes", MAX_BODY)),
+        Ok(_) => match String::from_utf8(bytes) {
+            Ok(text) => handle(store, request.method().as_str(), request.url(), &text),
+            Err(e) => Reply::error(400, e),
+        },
+        Err(e) => Reply::error(400, e),
+    };
+    let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = tiny_http::Response::from_string(reply.body.to_string())
+        .with_status_code(reply.status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        crate::logger::warn(e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_url() {
+        let (path, query) = split_url("/tags/hacker%20news?q=a+b&limit=3").unwrap();
+        assert_eq!(path, vec!["tags", "hacker news"]);
+        assert_eq!(param(&query, "q"), Some("a b"));
+        assert_eq!(param(&query, "limit"), Some("3"));
+        assert_eq!(split_url("/tags/%E6%97%A5").unwrap().0, vec!["tags", "日"]);
+        assert!(split_url("/tags/%zz").is_none());
+    }
+}
@@ -0,0 +1,392 @@
+//! The tag API, on handles.
+//!
+//! [`TagStore`] owns the connection, so that calls no longer thread a
+//! `Database` through, and [`TagStore::tag`] hands out a [`TagRef`] for
+//! one tag:
+//!
+//! ```no_run
+//! # futures::executor::block_on(async {
+//! let store = frictune::store::TagStore::open("./tags.db").unwrap();
+//! store.tag("rust").add().await.unwrap();
+//! store.tag("rust").link("cargo", 0.8).await.unwrap();
+//! let neighbors = store.tag("rust").neighbors().await.unwrap();
+//! # });
+//! ```
+//!
+//! [`blocking::TagStore`] offers the same calls without `.await`. The
+//! `Tag` functions are built on the handles, for code that holds a
+//! `Database` of its own.
+
+use std::{borrow::Cow, sync::Arc};
+
+use crate::{
+    db::{self, crud::{Database, DatabaseError, DatabaseResult}},
+    graph::TagGraph,
+    logger,
+    search::{self, SearchHit},
+    Tag,
+};
+
+#[derive(Clone)]
+pub struct TagStore {
+    db: Database,
+}
+
+/// One tag of a [`TagStore`]. The tag need not exist yet.
+pub struct TagRef<'s> {
+    db: &'s Database,
+    tag: Tag,
+}
+
+impl TagStore {
+    /// Opens the database and applies the pending migrations.
+    pub fn open(db_url: &str) -> anyhow::Result<Self> {
+        Ok(TagStore { db: Database::sync_new(db_url)? })
+    }
+
+    pub fn from_database(db: Database) -> Self {
+        TagStore { db }
+    }
+
+    pub fn into_database(self) -> Database {
+        self.db
+    }
+
+    /// The connection, for the functions that take it directly.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// A handle on a tag; the name is normalized like [`Tag::new`].
+    pub fn tag(&self, name: &str) -> TagRef<'_> {
+        TagRef::new(&self.db, Tag::new(name))
+    }
+
+    /// The names of all tags.
+    pub async fn tags(&self) -> Result<Vec<String>, DatabaseError> {
+        names(&self.db).await
+    }
+
+    pub async fn graph(&self) -> Result<Arc<TagGraph>, DatabaseError> {
+        self.db.graph().await
+    }
+
+    pub async fn search(&self, text: &str, limit: usize) -> Result<Vec<SearchHit>, DatabaseError> {
+        search::search(&self.db, text, limit).await
+    }
+}
+
+impl From<Database> for TagStore {
+    fn from(db: Database) -> Self {
+        TagStore::from_database(db)
+    }
+}
+
+/// The names of all tags.
+pub(crate) async fn names(db: &Database) -> Result<Vec<String>, DatabaseError> {
+    Ok(db.read("tags", &["tag_name".to_string()], "true", "").await?.get::<String>(0))
+}
+
+/// Adds a tag with its description if it is missing.
+async fn insert(db: &Database, tag: &Tag) -> Result<(), DatabaseError> {
+    let entries = [String::from("tag_name"), String::from("info")];
+    let data = [Some(tag.name.clone()), tag.desc.clone()].into_iter().flatten().collect::<Vec<_>>();
+    let add = |graph: &mut TagGraph| graph.add_tag(&tag.raw_name(), tag.desc.as_deref().map(db::unquote));
+    match db.create_with("tags", &entries[..data.len()], &data, add).await {
+        Ok(_) => {
+            logger::watch(search::reindex_tag(db, &tag.name).await);
+            Ok(())
+        },
+        Err(DatabaseError::UniqueViolation) => {
+            logger::warn(DatabaseError::UniqueViolation.to_string());
+            Ok(())
+        },
+        Err(e) => Err(e),
+    }
+}
+
+impl<'s> TagRef<'s> {
+    pub(crate) fn new(db: &'s Database, tag: Tag) -> Self {
+        TagRef { db, tag }
+    }
+
+    /// The normalized name.
+    pub fn name(&self) -> Cow<'_, str> {
+        self.tag.raw_name()
+    }
+
+    pub async fn exists(&self) -> bool {
+        match self.db.read(
+            "tags",
+            &[String::from("tag_name")],
+            &format!("tag_name = {}", self.tag.name),
+            ""
+        ).await {
+            Ok(things) => things.len() == 1,
+            Err(e) => { logger::warn(e.to_string()); false }
+        }
+    }
+
+    /// Adds the tag if it is missing.
+    pub async fn add(&self) -> Result<(), DatabaseError> {
+        insert(self.db, &self.tag).await
+    }
+
+    /// Adds the tag if it is missing, together with the tags it links
+    /// to, and links them.
+    pub async fn add_linked(&self, links: &[(&str, f64)]) -> Result<(), DatabaseError> {
+        let links: Vec<_> = links.iter().map(|(name, weight)| (Tag::new(name), *weight)).collect();
+        self.add_tags(&links).await
+    }
+
+    pub(crate) async fn add_tags(&self, links: &[(Tag, f64)]) -> Result<(), DatabaseError> {
+        insert(self.db, &self.tag).await?;
+        for (target, weight) in links {
+            insert(self.db, target).await?;
+            self.link_tag(target, *weight).await?;
+        }
+        Ok(())
+    }
+
+    /// Sets the description, adding the tag if it is missing.
+    pub async fn describe(&self, desc: &str) -> Result<(), DatabaseError> {
+        self.set_info(&db::quote(desc)).await.map(|_| ())
+    }
+
+    /// [`TagRef::describe`] with a quoted description.
+    pub(crate) async fn set_info(&self, info: &str) -> Result<DatabaseResult, DatabaseError> {
+        let entry = ["tag_name".to_string(), "info".to_string()];
+        let data = [self.tag.name.clone(), info.into()];
+        let res = self.db.update_with("tags", &entry, &data, &entry[1..], &data[1..], "true",
+            |graph| graph.add_tag(&self.tag.raw_name(), Some(db::unquote(info)))).await;
+        if res.is_ok() {
+            logger::watch(search::reindex_tag(self.db, &self.tag.name).await);
+        }
+        res
+    }
+
+    /// Links this tag to `target`, which must exist.
+    pub async fn link(&self, target: &str, weight: f64) -> Result<(), DatabaseError> {
+        self.link_tag(&Tag::new(target), weight).await.map(|_| ())
+    }
+
+    pub(crate) async fn link_tag(&self, target: &Tag, weight: f64) -> Result<DatabaseResult, DatabaseError> {
+        let entries = [String::from("tag1"), String::from("tag2"), String::from("weight"), String::from("is_origin")];
+        let data = [self.tag.name.clone(), target.name.clone(), weight.to_string(), String::from("true")];
+        let res = self.db.update_with(
+            "relationship",
+            &entries,
+            &data,
+            &entries[2..],
+            &data[2..],
+            "true",
+            |graph| graph.set_link(&self.tag.raw_name(), &target.raw_name(), weight, true)
+        ).await?;
+        self.propagate().await;
+        Ok(res)
+    }
+
+    /// Removes the link to `target`, if any.
+    pub async fn unlink(&self, target: &str) -> Result<(), DatabaseError> {
+        self.unlink_tag(&Tag::new(target)).await.map(|_| ())
+    }
+
+    /// Removes the link to `target`; the links derived from this tag
+    /// are recomputed without it.
+    pub(crate) async fn unlink_tag(&self, target: &Tag) -> Result<DatabaseResult, DatabaseError> {
+        let res = self.db.execute(&format!(
+            "DELETE FROM relationship WHERE tag1 = {} AND (tag2 = {} OR is_origin = false);",
+            self.tag.name, target.name
+        )).await?;
+        self.propagate().await;
+        Ok(res)
+    }
+
+    /// Removes the tag and its links.
+    pub async fn remove(&self) -> Result<(), DatabaseError> {
+        self.delete().await.map(|_| ())
+    }
+
+    pub(crate) async fn delete(&self) -> Result<DatabaseResult, DatabaseError> {
+        self.db.delete("relationship", "tag1", &self.tag.name).await?;
+        self.db.delete("relationship", "tag2", &self.tag.name).await?;
+        logger::watch(search::unindex_tag(self.db, &self.tag.name).await);
+        self.db.delete("tags", "tag_name", &self.tag.name).await
+    }
+
+    /// Derives the links of this tag through the tags it links to, with
+    /// the product of the weights, keeping the heavier of two links.
+    pub(crate) async fn propagate(&self) {
+        // TODO: a reverse-way propagation
+        let affected_tags: Vec<(String, f64)> = match self.db.read(
+            "relationship",
+            &["tag2".to_string(), "weight".to_string()],
+            &format!("tag1 = {}", self.tag.name),
+            ""
+        ).await {
+            Ok(things) =>
+                things.get::<String>(0).into_iter().zip(
+                    things.get::<f64>(1)
+                ).collect(),
+            Err(e) => { logger::warn(e.to_string()); return }
+        };
+
+        let entries = [String::from("tag1"), String::from("tag2"), String::from("weight"), String::from("is_origin")];
+        for (tag, weight) in affected_tags {
+            let links = match self.db.read(
+                "relationship",
+                &[String::from("*")],
+                &format!("tag1 = {}", db::quote(&tag)),
+                ""
+            ).await {
+                Ok(things) => things.get::<String>(0).into_iter().zip(things.get::<f64>(2)),
+                Err(e) => { logger::warn(e.to_string()); continue }
+            };
+            for (n, w) in links {
+                let data = [self.tag.name.clone(), db::quote(&n), (w * weight).to_string(), String::from("false")];
+                logger::watch(self.db.update_with(
+                    "relationship",
+                    &entries,
+                    &data,
+                    &entries[2..],
+                    &data[2..],
+                    "excluded.weight > weight AND is_origin = false",
+                    |graph| graph.raise_derived_link(&self.tag.raw_name(), &n, w * weight)
+                ).await);
+            }
+        }
+    }
+
+    pub async fn desc(&self) -> Result<Option<String>, DatabaseError> {
+        let graph = self.db.graph().await?;
+        Ok(graph.id(&self.tag.raw_name()).and_then(|id| graph.desc(id)).map(String::from))
+    }
+
+    /// The tags this tag links to with the weights, heaviest first.
+    pub async fn neighbors(&self) -> Result<Vec<(String, f64)>, DatabaseError> {
+        let graph = self.db.graph().await?;
+        let Some(id) = graph.id(&self.tag.raw_name()) else { return Ok(vec![]) };
+        let mut neighbors: Vec<_> = graph.neighbors(id).iter()
+            .map(|link| (graph.name(link.to).to_string(), link.weight))
+            .collect();
+        neighbors.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(neighbors)
+    }
+
+    /// The weight of the link to `target`, if any.
+    pub async fn weight_to(&self, target: &str) -> Result<Option<f64>, DatabaseError> {
+        self.weight_to_tag(&Tag::new(target)).await
+    }
+
+    pub(crate) async fn weight_to_tag(&self, target: &Tag) -> Result<Option<f64>, DatabaseError> {
+        let graph = self.db.graph().await?;
+        Ok(graph.id(&self.tag.raw_name())
+            .zip(graph.id(&target.raw_name()))
+            .and_then(|(from, to)| graph.link(from, to))
+            .map(|link| link.weight))
+    }
+}
+
+/// The same API without `async`, for the CLI and `tune_html`.
+pub mod blocking {
+    use std::{borrow::Cow, sync::Arc};
+
+    use futures::executor::block_on;
+
+    use crate::{db::crud::{Database, DatabaseError}, graph::TagGraph, search::SearchHit};
+
+    #[derive(Clone)]
+    pub struct TagStore {
+        inner: super::TagStore,
+    }
+
+    pub struct TagRef<'s> {
+        inner: super::TagRef<'s>,
+    }
+
+    impl TagStore {
+        pub fn open(db_url: &str) -> anyhow::Result<Self> {
+            Ok(TagStore { inner: super::TagStore::open(db_url)? })
+        }
+
+        pub fn from_database(db: Database) -> Self {
+            TagStore { inner: super::TagStore::from_database(db) }
+        }
+
+        pub fn into_database(self) -> Database {
+            self.inner.into_database()
+        }
+
+        pub fn database(&self) -> &Database {
+            self.inner.database()
+        }
+
+        pub fn tag(&self, name: &str) -> TagRef<'_> {
+            TagRef { inner: self.inner.tag(name) }
+        }
+
+        pub fn tags(&self) -> Result<Vec<String>, DatabaseError> {
+            block_on(self.inner.tags())
+        }
+
+        pub fn graph(&self) -> Result<Arc<TagGraph>, DatabaseError> {
+            block_on(self.inner.graph())
+        }
+
+        pub fn search(&self, text: &str, limit: usize) -> Result<Vec<SearchHit>, DatabaseError> {
+            block_on(self.inner.search(text, limit))
+        }
+    }
+
+    impl From<Database> for TagStore {
+        fn from(db: Database) -> Self {
+            TagStore::from_database(db)
+        }
+    }
+
+    impl<'s> TagRef<'s> {
+        pub fn name(&self) -> Cow<'_, str> {
+            self.inner.name()
+        }
+
+        pub fn exists(&self) -> bool {
+            block_on(self.inner.exists())
+        }
+
+        pub fn add(&self) -> Result<(), DatabaseError> {
+            block_on(self.inner.add())
+        }
+
+        pub fn add_linked(&self, links: &[(&str, f64)]) -> Result<(), DatabaseError> {
+            block_on(self.inner.add_linked(links))
+        }
+
+        pub fn describe(&self, desc: &str) -> Result<(), DatabaseError> {
+            block_on(self.inner.describe(desc))
+        }
+
+        pub fn link(&self, target: &str, weight: f64) -> Result<(), DatabaseError> {
+            block_on(self.inner.link(target, weight))
+        }
+
+        pub fn unlink(&self, target: &str) -> Result<(), DatabaseError> {
+            block_on(self.inner.unlink(target))
+        }
+
+        pub fn remove(&self) -> Result<(), DatabaseError> {
+            block_on(self.inner.remove())
+        }
+
+        pub fn desc(&self) -> Result<Option<String>, DatabaseError> {
+            block_on(self.inner.desc())
+        }
+
+        pub fn neighbors(&self) -> Result<Vec<(String, f64)>, DatabaseError> {
+            block_on(self.inner.neighbors())
+        }
+
+        pub fn weight_to(&self, target: &str) -> Result<Option<f64>, DatabaseError> {
+            block_on(self.inner.weight_to(target))
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Missing links suggested by structural similarity.
+//!
+//! Two tags that are not linked in either direction but share many
+//! neighbors probably belong together. Link directions are ignored and
+//! every neighbor counts with its link weight:
+//!
+//! - [`Metric::Jaccard`]: the weighted Jaccard index of the two
+//!   neighborhoods, `sum(min) / sum(max)`;
+//! - [`Metric::AdamicAdar`]: the shared neighbors, each counting
+//!   `wa * wb / ln(degree)`, so that hubs shared by everything say
+//!   little;
+//! - [`Metric::Cosine`]: the cosine of the neighbor weight vectors.
+//!
+//! # Example
+//!
+//! ```
+//! use frictune::{graph::TagGraph, suggest::{suggest_links, Metric}};
+//!
+//! let graph = TagGraph::from_parts(vec![], vec![
+//!     ("rust".into(), "cargo".into(), 0.9, true),
+//!     ("rustc".into(), "cargo".into(), 0.8, true),
+//! ]);
+//! let found = suggest_links(&graph, Metric::Cosine, 5);
+//! assert_eq!((found[0].tag1.as_str(), found[0].tag2.as_str()), ("rust", "rustc"));
+//! assert_eq!(found[0].evidence, vec!["cargo"]);
+//! ```
+
+use std::collections::HashMap;
+
+use futures::executor::block_on;
+
+use crate::{db::crud::{Database, DatabaseError}, graph::TagGraph};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Jaccard,
+    AdamicAdar,
+    Cosine,
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jaccard" => Ok(Self::Jaccard),
+            "adamic-adar" => Ok(Self::AdamicAdar),
+            "cosine" => Ok(Self::Cosine),
+            other => Err(format!("unknown metric {}", other)),
+        }
+    }
+}
+
+pub struct LinkSuggestion {
+    pub tag1: String,
+    pub tag2: String,
+    pub score: f64,
+    /// The shared neighbors, most strongly shared first.
+    pub evidence: Vec<String>,
+}
+
+fn score(metric: Metric, a: &HashMap<usize, f64>, b: &HashMap<usize, f64>, shared: &[usize], degree: &[usize]) -> f64 {
+    match metric {
+        Metric::Jaccard => {
+            let min: f64 = shared.iter().map(|z| a[z].min(b[z])).sum();
+            let max = a.values().sum::<f64>() + b.values().sum::<f64>() - min;
+            if max > 0.0 { min / max } else { 0.0 }
+        },
+        Metric::AdamicAdar => shared.iter()
+            .map(|z| a[z] * b[z] / (degree[*z] as f64).ln())
+            .sum(),
+        Metric::Cosine => {
+            let dot: f64 = shared.iter().map(|z| a[z] * b[z]).sum();
+            let norm = |v: &HashMap<usize, f64>| v.values().map(|w| w * w).sum::<f64>().sqrt();
+            dot / (norm(a) * norm(b))
+        },
+    }
+}
+
+/// The `limit` best scored pairs of unlinked tags with at least one
+/// shared neighbor, `tag1` before `tag2` by name.
+pub fn suggest_links(graph: &TagGraph, metric: Metric, limit: usize) -> Vec<LinkSuggestion> {
+    let adjacency: Vec<HashMap<usize, f64>> = graph.undirected(false).into_iter()
+        .map(|neighbors| neighbors.into_iter().collect())
+        .collect();
+    let degree: Vec<usize> = adjacency.iter().map(HashMap::len).collect();
+
+    // every pair of tags two steps apart, with their shared neighbors
+    let mut candidates: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (z, neighbors) in adjacency.iter().enumerate() {
+        let mut around: Vec<usize> = neighbors.keys().copied().collect();
+        around.sort_unstable();
+        for (i, a) in around.iter().enumerate() {
+            for b in around[i + 1..].iter() {
+                if !adjacency[*a].contains_key(b) {
+                    candidates.entry((*a, *b)).or_default().push(z);
+                }
+            }
+        }
+    }
+
+    let mut suggestions: Vec<LinkSuggestion> = candidates.into_iter().map(|((a, b), mut shared)| {
+        let (na, nb) = (&adjacency[a], &adjacency[b]);
+        shared.sort_by(|x, y| na[y].min(nb[y]).total_cmp(&na[x].min(nb[x]))
+            .then_with(|| graph.name(*x).cmp(graph.name(*y))));
+        let (tag1, tag2) = if graph.name(a) <= graph.name(b) { (a, b) } else { (b, a) };
+        LinkSuggestion {
+            tag1: graph.name(tag1).to_string(),
+            tag2: graph.name(tag2).to_string(),
+            score: score(metric, na, nb, &shared, &degree),
+            evidence: shared.iter().map(|z| graph.name(*z).to_string()).collect(),
+        }
+    }).collect();
+    suggestions.sort_by(|x, y| y.score.total_cmp(&x.score)
+        .then_with(|| (&x.tag1, &x.tag2).cmp(&(&y.tag1, &y.tag2))));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+/// The weights to link `suggestions` with: the scores of the metrics
+/// have different ranges, Adamic-Adar's being unbounded, so each score
+/// is divided by the best one. The best suggestion gets 1.0 and the
+/// others their share of it, all within [0, 1].
+pub fn link_weights(suggestions: &[LinkSuggestion]) -> Vec<f64> {
+    let best = suggestions.iter().map(|s| s.score).fold(0.0, f64::max);
+    suggestions.iter()
+        .map(|s| if best > 0.0 { (s.score / best).clamp(0.0, 1.0) } else { 0.0 })
+        .collect()
+}
+
+/// Loads the graph and suggests links.
+pub async fn suggest(db: &Database, metric: Metric, limit: usize) -> Result<Vec<LinkSuggestion>, DatabaseError> {
+    Ok(suggest_links(&*db.graph().await?, metric, limit))
+}
+
+/// The sync version of [`suggest`].
+pub fn suggest_sync(db: &Database, metric: Metric, limit: usize) -> Vec<LinkSuggestion> {
+    block_on(async {
+        suggest(db, metric, limit).await
+            .unwrap_or_else(|e| { crate::logger::warn(e.to_string()); vec![] })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let mut links = vec![];
+        for (a, b, w) in [("a", "x", 1.0), ("a", "y", 0.5), ("b", "x", 1.0), ("b", "y", 0.5), ("c", "x", 0.2), ("a", "c", 0.3)] {
+            links.push((a.to_string(), b.to_string(), w, true));
+        }
+        let graph = TagGraph::from_parts(vec![], links);
+
+        for metric in [Metric::Jaccard, Metric::AdamicAdar, Metric::Cosine] {
+            let found = suggest_links(&graph, metric, 10);
+            // a and c are linked, so never suggested
+            assert!(found.iter().all(|s| (s.tag1.as_str(), s.tag2.as_str()) != ("a", "c")));
+            let ab = found.iter().find(|s| (s.tag1.as_str(), s.tag2.as_str()) == ("a", "b")).unwrap();
+            assert_eq!(ab.evidence, vec!["x", "y"]);
+        }
+
+        let jaccard = suggest_links(&graph, Metric::Jaccard, 1);
+        assert_eq!((jaccard[0].tag1.as_str(), jaccard[0].tag2.as_str()), ("a", "b"));
+        assert!((jaccard[0].score - 1.5 / 1.8).abs() < 1e-9);
+
+        let adamic_adar = suggest_links(&graph, Metric::AdamicAdar, 10);
+        assert!(adamic_adar[0].score > 1.0);
+        let weights = link_weights(&adamic_adar);
+        assert_eq!(weights[0], 1.0);
+        assert!(weights.iter().all(|w| (0.0..=1.0).contains(w)));
+        assert!(weights.windows(2).all(|w| w[0] >= w[1]));
+    }
+}
@@ -1,6 +1,13 @@
 use frictune::Tag;
+use frictune::resolve::{resolve_sync, Resolution};
 use clap::Parser;
 use clap::Subcommand;
+use clap::ValueEnum;
+use futures::executor::block_on;
+
+/// How many results the commands that cap their output print by
+/// default, see their `--top`.
+const TOP: usize = 10;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -18,15 +25,19 @@ enum Commands {
         #[arg(long, short)]
         tags: Vec<String>,
         #[arg(long, short)]
-        weights: Vec<f32>,
+        weights: Vec<f64>,
     },
     Del {
         name: String,
     },
+    /// link two tags, offering to create a missing one
     Link {
         src: String,
         tgt: String,
-        weight: f32,
+        weight: f64,
+        /// create missing tags without asking
+        #[arg(long, short)]
+        create: bool,
     },
     Eval {
         src: String,
@@ -36,18 +47,237 @@ enum Commands {
         name: String,
         desc: String,
     },
+    /// show the strongest chain of links from one tag to another
+    Path {
+        src: String,
+        tgt: String,
+    },
+    /// search tag names and descriptions
+    Search {
+        text: String,
+        /// how many results to print
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+    },
+    /// find tags with a boolean query, e.g. 'rust AND (cli OR tui) NOT web'
+    Query {
+        expr: String,
+        /// how many results to print
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+    },
+    /// suggest tags related to all the given tags
+    Recommend {
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// how many results to print
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+    },
+    /// rank the tags by how central they are in the graph
+    Rank {
+        /// how many results to print
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+        #[arg(long, value_enum, default_value_t = RankBy::Pagerank)]
+        by: RankBy,
+        /// print the ranks as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// group the tags into topic clusters
+    Clusters {
+        /// how many of the strongest tags to show per cluster
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+        /// also list the tags that are in no cluster with others
+        #[arg(long)]
+        all: bool,
+    },
+    /// check the links and tags for inconsistencies
+    Fsck {
+        /// delete broken links and clamp weights
+        #[arg(long)]
+        repair: bool,
+    },
+    /// infer link weights from tags sharing blocks in posts
+    Infer {
+        /// pmi, jaccard or conditional
+        #[arg(long, short, default_value = "pmi")]
+        method: frictune::infer::Method,
+        /// print the inferred weights beside the typed ones
+        #[arg(long)]
+        compare: bool,
+        /// move the typed weights towards the inferred ones by ALPHA
+        #[arg(long, value_name = "ALPHA")]
+        blend: Option<f64>,
+    },
+    /// propose links between tags sharing many neighbors
+    SuggestLinks {
+        /// jaccard, adamic-adar or cosine
+        #[arg(long, short, default_value = "jaccard")]
+        metric: frictune::suggest::Metric,
+        /// how many results to print
+        #[arg(long, short, default_value_t = TOP)]
+        top: usize,
+        /// ask to create each suggested link, weighted by its score
+        /// divided by the best score
+        #[arg(long)]
+        apply: bool,
+        /// with --apply, create all suggested links without asking
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// rename all tags by the normalization policy of the settings,
+    /// merging the tags that end up with the same name
+    Normalize {
+        /// only print the merges
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// link the tags of the Markdown notes under a folder, reading only
+    /// the notes changed since the last run
+    Index {
+        dir: std::path::PathBuf,
+    },
+    /// keep the database in sync with the posts (.txt files in the
+    /// tune_html markup) and the Markdown notes under a folder, until
+    /// interrupted
+    Watch {
+        dir: std::path::PathBuf,
+        /// render every changed post into this folder, as tune_html
+        /// does
+        #[arg(long, value_name = "DIR")]
+        render: Option<std::path::PathBuf>,
+        /// the template to render with
+        #[arg(long, default_value = "./template.hbs")]
+        template: std::path::PathBuf,
+    },
+    /// answer line-delimited JSON-RPC 2.0 requests on stdin, for
+    /// editor plugins, see `frictune::rpc`
+    Rpc,
+    /// run the language server of the `{{ tag ... }}` markup on stdio
+    Lsp,
+    /// dump the whole tag graph, to stdout unless --output is given;
+    /// csv writes tags.csv and links.csv into the --output directory,
+    /// the current one by default
+    Export {
+        #[arg(long, short, default_value = "json")]
+        format: frictune::export::Format,
+        /// also dump the derived links
+        #[arg(long)]
+        derived: bool,
+        /// only dump the tags near this one
+        #[arg(long)]
+        around: Option<String>,
+        /// how many links away from --around a tag may be
+        #[arg(long, default_value_t = 1, requires = "around")]
+        depth: usize,
+        #[arg(long, short)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// bring tags and links from a file into the database
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+    /// inspect or apply schema migrations
+    Migrate {
+        #[command(subcommand)]
+        action: Option<MigrateAction>,
+    },
     Repl,
 }
 
-pub fn parse_args(db_conn: &mut frictune::db::crud::Database) {
+#[derive(Clone, Copy, ValueEnum)]
+enum RankBy {
+    Pagerank,
+    InDegree,
+    OutDegree,
+    Betweenness,
+}
+
+#[derive(Subcommand)]
+enum ImportSource {
+    /// a dump of `frictune export --format json`
+    Json {
+        file: std::path::PathBuf,
+        /// drop all tags and links of the database first, instead of
+        /// merging the dump into them
+        #[arg(long)]
+        replace: bool,
+    },
+    /// the tags.csv and links.csv of `frictune export --format csv`,
+    /// or a spreadsheet with the same columns; merged into the database
+    Csv {
+        #[arg(long)]
+        tags: Option<std::path::PathBuf>,
+        #[arg(long)]
+        links: Option<std::path::PathBuf>,
+        /// read a field from a column with another header, as in
+        /// `--map name=Tag`
+        #[arg(long = "map", value_name = "FIELD=HEADER")]
+        map: Vec<String>,
+        /// only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// a bookmark file exported by a browser; bookmarks already in the
+    /// database are skipped
+    Bookmarks {
+        file: std::path::PathBuf,
+    },
+    /// an Obsidian vault, each note a tag linked to its wikilinks and
+    /// tags; merged into the database
+    Obsidian {
+        dir: std::path::PathBuf,
+        /// only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// show the current and the latest schema version
+    Status,
+    /// apply all pending migrations
+    Up {
+        /// only print the statements that would run
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+pub fn parse_args(settings: &crate::conf::Conf) {
+    let db_uri = settings.db_uri.as_str();
     let cli = Cli::parse();
+    // `migrate` must see the schema as it is, so only the other
+    // commands open the database with migrations applied
+    if let Some(Commands::Migrate { action }) = &cli.command {
+        let db_conn = match frictune::db::crud::Database::sync_open(db_uri) {
+            Ok(conn) => conn,
+            Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+        };
+        migrate(&db_conn, action.as_ref().unwrap_or(&MigrateAction::Status));
+        return;
+    }
+    // stdout belongs to the protocol
+    if let Some(Commands::Rpc | Commands::Lsp | Commands::Export { output: None, .. }) = &cli.command {
+        frictune::logger::to_stderr();
+    }
+    let db_conn = match frictune::db::crud::Database::sync_new(db_uri) {
+        Ok(conn) => conn,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    };
+    let db_conn = &db_conn;
     match &cli.command {
         Some(Commands::Add { name, tags, weights }) => {
             if tags.len() == weights.len() {
                 Tag::new(name).add_sync(db_conn,
                     &tags.iter().zip(weights)
                         .map(|(tag, weight)| (tag.to_owned(), weight.to_owned()))
-                        .collect::<Vec<(String, f32)>>()
+                        .collect::<Vec<(String, f64)>>()
                 );
             }
 
@@ -58,9 +288,11 @@ pub fn parse_args(db_conn: &mut frictune::db::crud::Database) {
             
         },
         Some(Commands::Del { name }) => {
+            if !check_tag(db_conn, name, None) { return }
             Tag::new(name).rem_sync(db_conn);
         },
         Some(Commands::Eval { src, tgt }) => {
+            if !(check_tag(db_conn, src, None) && check_tag(db_conn, tgt, None)) { return }
             match Tag::query_sync(db_conn, src, tgt) {
                 Some(weight) => { frictune::logger::print(
                     &format!("The weight between {} and {} is {}",
@@ -76,10 +308,12 @@ pub fn parse_args(db_conn: &mut frictune::db::crud::Database) {
                 &format!("The tag {src} is linked with description {desc}.")
             )
         },
-        Some(Commands::Link { src, tgt, weight }) => {
+        Some(Commands::Link { src, tgt, weight, create }) => {
+            if !(check_tag(db_conn, src, Some(*create)) && check_tag(db_conn, tgt, Some(*create))) { return }
             Tag::new(src).link_sync(db_conn, tgt, *weight);
         },
         Some(Commands::Mod { name, desc }) => {
+            if !check_tag(db_conn, name, None) { return }
             let concerned = Tag::new(name);
             let old_desc = concerned.qd_sync(db_conn).unwrap_or_default();
             Tag::new(name).mod_sync(db_conn, desc);
@@ -88,7 +322,351 @@ pub fn parse_args(db_conn: &mut frictune::db::crud::Database) {
                 &format!("Tag {name} is updated with description {new_desc} from {old_desc}.")
             );
         },
+        Some(Commands::Path { src, tgt }) => {
+            if !(check_tag(db_conn, src, None) && check_tag(db_conn, tgt, None)) { return }
+            let graph = match block_on(db_conn.graph()) {
+                Ok(graph) => graph,
+                Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+            };
+            let (from, to) = (Tag::new(src), Tag::new(tgt));
+            match graph.id(&from.raw_name()).zip(graph.id(&to.raw_name()))
+                .and_then(|(from, to)| graph.strongest_path(from, to)) {
+                Some((path, weight)) => frictune::logger::print(&format!("{} | {:.3}",
+                    path.iter().map(|id| graph.name(*id)).collect::<Vec<_>>().join(" -> "), weight)),
+                None => frictune::logger::print(&format!("No path from {src} to {tgt}.")),
+            }
+        },
+        Some(Commands::Search { text, top }) => {
+            for hit in frictune::search::search_sync(db_conn, text, *top) {
+                frictune::logger::print(
+                    &format!("{} | {} | {:.3}", hit.name, hit.info.unwrap_or_default(), hit.score)
+                );
+            }
+        },
+        Some(Commands::Query { expr, top }) => {
+            // unknown tags only match nothing, but point out likely typos
+            if let Ok(parsed) = frictune::query::Expr::parse(expr) {
+                for name in parsed.tags() {
+                    check_tag(db_conn, name, None);
+                }
+            }
+            match frictune::query::query_sync(db_conn, expr) {
+                Ok(hits) => for hit in hits.into_iter().take(*top) {
+                    frictune::logger::print(&format!("{} | {:.3}", hit.name, hit.score));
+                },
+                Err(e) => { frictune::logger::warn(e.to_string()); },
+            }
+        },
+        Some(Commands::Recommend { tags, top }) => {
+            for name in tags {
+                check_tag(db_conn, name, None);
+            }
+            let tags: Vec<_> = tags.iter().map(|t| frictune::normalize::normalize(t)).collect();
+            for rec in frictune::recommend::recommend_sync(db_conn, &tags, *top) {
+                frictune::logger::print(&format!("{} | {:.3}", rec.name, rec.score));
+            }
+        },
+        Some(Commands::Rank { top, by, json }) => {
+            let mut ranks = frictune::rank::rank_sync(db_conn);
+            let key = |r: &frictune::rank::TagRank| match by {
+                RankBy::Pagerank => r.pagerank,
+                RankBy::InDegree => r.in_degree,
+                RankBy::OutDegree => r.out_degree,
+                RankBy::Betweenness => r.betweenness,
+            };
+            ranks.sort_by(|a, b| key(b).total_cmp(&key(a)));
+            ranks.truncate(*top);
+            if *json {
+                frictune::logger::print(&serde_json::to_string_pretty(&ranks).unwrap());
+            } else {
+                for r in ranks {
+                    frictune::logger::print(&format!("{} | pagerank {:.4} | in {:.3} | out {:.3} | betweenness {:.1}",
+                        r.name, r.pagerank, r.in_degree, r.out_degree, r.betweenness));
+                }
+            }
+        },
+        Some(Commands::Clusters { top, all }) => {
+            for cluster in frictune::cluster::cluster_sync(db_conn, *top) {
+                if cluster.members.len() < 2 && !*all { continue }
+                frictune::logger::print(&format!("cluster {} ({} tags): {}",
+                    cluster.id, cluster.members.len(), cluster.top.join(", ")));
+            }
+        },
+        Some(Commands::Fsck { repair }) => {
+            match frictune::fsck::fsck_sync(db_conn, *repair) {
+                Ok(report) => {
+                    for issue in report.issues.iter() {
+                        frictune::logger::print(&issue.to_string());
+                    }
+                    frictune::logger::print(&format!("{} issues found, {} repaired",
+                        report.issues.len(), report.repaired));
+                },
+                Err(e) => { frictune::logger::warn(e.to_string()); },
+            }
+        },
+        Some(Commands::Infer { method, compare, blend }) => {
+            match block_on(frictune::infer::infer(db_conn, *method)) {
+                Ok(inferred) => frictune::logger::print(&format!("{} links inferred from co-occurrence", inferred.len())),
+                Err(e) => { frictune::logger::warn(e.to_string()); return; },
+            }
+            if *compare {
+                match block_on(frictune::infer::compare(db_conn, *method)) {
+                    Ok(comparisons) => for c in comparisons {
+                        let manual = c.manual.map(|w| format!("{:.3}", w)).unwrap_or_else(|| "-".into());
+                        frictune::logger::print(&format!("{} -> {} | typed {} | inferred {:.3}",
+                            c.tag1, c.tag2, manual, c.inferred));
+                    },
+                    Err(e) => { frictune::logger::warn(e.to_string()); },
+                }
+            }
+            if let Some(alpha) = blend {
+                match block_on(frictune::infer::blend(db_conn, *method, *alpha)) {
+                    Ok(changed) => frictune::logger::print(&format!("{} links blended", changed)),
+                    Err(e) => { frictune::logger::warn(e.to_string()); },
+                }
+            }
+        },
+        Some(Commands::SuggestLinks { metric, top, apply, yes }) => {
+            let suggestions = frictune::suggest::suggest_sync(db_conn, *metric, *top);
+            let weights = frictune::suggest::link_weights(&suggestions);
+            for (s, weight) in suggestions.iter().zip(weights) {
+                frictune::logger::print(&format!("{} -- {} | {:.3} | shared: {}",
+                    s.tag1, s.tag2, s.score, s.evidence.join(", ")));
+                if !*apply { continue }
+                if *yes || confirm(&format!("Link {} to {}? [y/N] ", s.tag1, s.tag2)) {
+                    frictune::logger::watch(block_on(
+                        Tag::new(&s.tag1).link_tags(db_conn, &Tag::new(&s.tag2), weight)
+                    ));
+                }
+            }
+        },
+        Some(Commands::Normalize { dry_run }) => {
+            let policy = frictune::normalize::policy();
+            match frictune::normalize::normalize_db_sync(db_conn, &policy, *dry_run) {
+                Ok(merges) => {
+                    for m in merges.iter() {
+                        frictune::logger::print(&format!("{} <- {}", m.target, m.sources.join(", ")));
+                    }
+                    frictune::logger::print(&format!("{} tags {}", merges.len(),
+                        if *dry_run { "would be normalized" } else { "normalized" }));
+                },
+                Err(e) => { frictune::logger::warn(e.to_string()); },
+            }
+        },
+        Some(Commands::Index { dir }) => {
+            match frictune::notes::index_dir_sync(db_conn, dir, &settings.index) {
+                Ok(report) => frictune::logger::print(&format!(
+                    "{} notes indexed, {} unchanged, {} removed; {} links added, {} removed",
+                    report.indexed, report.unchanged, report.removed, report.links_added, report.links_removed)),
+                Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+            }
+        },
+        Some(Commands::Watch { dir, render, template }) => {
+            let render = render.as_ref()
+                .map(|out_dir| frictune::watch::Render { template: template.clone(), out_dir: out_dir.clone() });
+            let watched = frictune::watch::Watch::new(db_conn.clone(), dir, settings.index.clone(), render)
+                .and_then(|watch| { watch.sync_all()?; watch.run() });
+            if let Err(e) = watched {
+                frictune::logger::rupt(e.to_string().as_str());
+            }
+        },
+        Some(Commands::Rpc) => {
+            let (stdin, stdout) = (std::io::stdin(), std::io::stdout());
+            if let Err(e) = frictune::rpc::serve(db_conn.clone(), stdin.lock(), stdout.lock()) {
+                frictune::logger::rupt(e.to_string().as_str());
+            }
+        },
+        Some(Commands::Lsp) => {
+            if let Err(e) = frictune::lsp::serve_stdio(db_conn.clone()) {
+                frictune::logger::rupt(e.to_string().as_str());
+            }
+        },
+        Some(Commands::Export { format, derived, around, depth, output }) => {
+            let around = around.as_deref().map(|name| (name, *depth));
+            if let Err(e) = export(db_conn, *format, *derived, around, output.as_deref()) {
+                frictune::logger::rupt(e.to_string().as_str());
+            }
+        },
+        Some(Commands::Import { source }) => {
+            if let Err(e) = import(db_conn, source, settings) {
+                frictune::logger::rupt(e.to_string().as_str());
+            }
+        },
+        Some(Commands::Migrate { .. }) => unreachable!(),
         Some(Commands::Repl) => { frictune::logger::rupt("not implemented"); },
         None => { frictune::logger::rupt("not implemented"); },
     }
-}
\ No newline at end of file
+}
+
+/// Checks that a tag exists, and prints the closest names if not.
+/// `create` is `None` when a missing tag is an error, otherwise the tag
+/// is added, without asking if `create` holds `true`.
+fn check_tag(db_conn: &frictune::db::crud::Database, name: &str, create: Option<bool>) -> bool {
+    let name = &frictune::normalize::normalize(name);
+    let suggestions = match resolve_sync(db_conn, name) {
+        Resolution::Found(_) => return true,
+        Resolution::Missing(suggestions) => suggestions,
+    };
+    frictune::logger::warn(format!("No tag named {name}."));
+    if !suggestions.is_empty() {
+        frictune::logger::warn(format!("Did you mean {}?",
+            suggestions.iter().map(|s| s.name.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+    match create {
+        Some(true) => {},
+        Some(false) if confirm(&format!("Create tag {name}? [y/N] ")) => {},
+        _ => return false,
+    }
+    Tag::new(name).add_sync::<String>(db_conn, &[]);
+    true
+}
+
+/// Asks a yes/no question on the terminal. Without a terminal the
+/// answer is no.
+fn confirm(question: &str) -> bool {
+    use std::io::{IsTerminal, Write};
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() { return false }
+    print!("{question}");
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    stdin.read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// `around` is a tag and a depth, to dump only the tags near it.
+fn export(db_conn: &frictune::db::crud::Database, format: frictune::export::Format, derived: bool,
+        around: Option<(&str, usize)>, output: Option<&std::path::Path>) -> anyhow::Result<()> {
+    use frictune::export::{self, Format, Snapshot};
+    let graph = block_on(db_conn.graph())?;
+    let snapshot = match around {
+        Some((name, depth)) => Snapshot::around(&graph, name, depth, derived)
+            .ok_or_else(|| anyhow::anyhow!("no tag named {}", name))?,
+        None => Snapshot::from_graph(&graph, derived),
+    };
+    if format == Format::Csv {
+        let dir = output.unwrap_or(std::path::Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        let create = |name| -> std::io::Result<_> { Ok(std::io::BufWriter::new(std::fs::File::create(dir.join(name))?)) };
+        return export::csv::write(&snapshot, create(export::csv::TAGS_FILE)?, create(export::csv::LINKS_FILE)?);
+    }
+    let out: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match format {
+        Format::Json => export::json::write(&snapshot, out),
+        Format::Graphml => export::graphml::write(&snapshot, out),
+        Format::Gexf => export::gexf::write(&snapshot, out),
+        Format::Dot => export::dot::write(&snapshot, out),
+        Format::Bookmarks => export::bookmarks::write(&snapshot, out),
+        Format::Csv => unreachable!(),
+    }
+}
+
+fn import(db_conn: &frictune::db::crud::Database, source: &ImportSource, settings: &crate::conf::Conf) -> anyhow::Result<()> {
+    use frictune::export::{self, ImportMode};
+    let (snapshot, mode) = match source {
+        ImportSource::Json { file, replace } => (
+            export::json::read(std::io::BufReader::new(std::fs::File::open(file)?))?,
+            if *replace { ImportMode::Replace } else { ImportMode::Merge },
+        ),
+        ImportSource::Csv { tags, links, map, dry_run } => {
+            let mut columns = export::csv::Columns::default();
+            for mapping in map {
+                columns.map(mapping).map_err(anyhow::Error::msg)?;
+            }
+            let open = |path: &std::path::PathBuf| -> std::io::Result<_> { Ok(std::io::BufReader::new(std::fs::File::open(path)?)) };
+            let mut snapshot = export::Snapshot::default();
+            let mut problems = vec![];
+            if let Some(path) = tags {
+                let (entries, found) = export::csv::read_tags(open(path)?, &columns)?;
+                snapshot.tags = entries;
+                problems.extend(found);
+            }
+            if let Some(path) = links {
+                let (entries, found) = export::csv::read_links(open(path)?, &columns)?;
+                snapshot.links = entries;
+                problems.extend(found);
+            }
+            if !problems.is_empty() {
+                for problem in problems.iter() {
+                    frictune::logger::warn(problem.to_string());
+                }
+                anyhow::bail!("{} rows cannot be imported, nothing was changed", problems.len());
+            }
+            let changes = export::diff(&*block_on(db_conn.graph())?, &snapshot);
+            for change in changes.iter() {
+                frictune::logger::print(&change.to_string());
+            }
+            if *dry_run {
+                frictune::logger::print(&format!("{} changes", changes.len()));
+                return Ok(());
+            }
+            (snapshot, ImportMode::Merge)
+        },
+        ImportSource::Bookmarks { file } => {
+            let mut snapshot = export::bookmarks::parse(&std::fs::read_to_string(file)?, &settings.bookmarks);
+            let skipped = export::bookmarks::skip_known(&*block_on(db_conn.graph())?, &mut snapshot);
+            if !skipped.is_empty() {
+                frictune::logger::print(&format!("{} bookmarks are already in the database: {}",
+                    skipped.len(), skipped.join(", ")));
+            }
+            (snapshot, ImportMode::Merge)
+        },
+        ImportSource::Obsidian { dir, dry_run } => {
+            let snapshot = export::obsidian::parse(&export::obsidian::read(dir)?, &settings.obsidian);
+            if *dry_run {
+                let changes = export::diff(&*block_on(db_conn.graph())?, &snapshot);
+                for change in changes.iter() {
+                    frictune::logger::print(&change.to_string());
+                }
+                frictune::logger::print(&format!("{} changes", changes.len()));
+                return Ok(());
+            }
+            (snapshot, ImportMode::Merge)
+        },
+    };
+    let report = export::import_sync(db_conn, &snapshot, mode)?;
+    frictune::logger::print(&format!("{} tags added, {} descriptions changed, {} links set",
+        report.tags_added, report.tags_described, report.links_set));
+    Ok(())
+}
+
+fn migrate(db_conn: &frictune::db::crud::Database, action: &MigrateAction) {
+    use frictune::db::crud::Database;
+    let status = match block_on(db_conn.migration_status()) {
+        Ok(status) => status,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    };
+    match action {
+        MigrateAction::Status => {
+            frictune::logger::print(
+                &format!("schema version {} of {}", status.current, status.latest)
+            );
+            for migration in status.pending {
+                frictune::logger::print(
+                    &format!("pending: {} {}", migration.version, migration.name)
+                );
+            }
+        },
+        MigrateAction::Up { dry_run: true } => {
+            for migration in status.pending {
+                frictune::logger::print(
+                    &format!("-- {} {}\n{}", migration.version, migration.name,
+                        migration.statements(Database::DIALECT))
+                );
+            }
+        },
+        MigrateAction::Up { dry_run: false } => {
+            match block_on(db_conn.migrate(false)) {
+                Ok(applied) => for migration in applied {
+                    frictune::logger::print(
+                        &format!("applied: {} {}", migration.version, migration.name)
+                    );
+                },
+                Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+            }
+        },
+    }
+}
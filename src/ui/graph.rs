@@ -1,55 +1,43 @@
 use dioxus::prelude::rsx;
+#[cfg(target_arch = "wasm32")]
+use frictune::graph::{DrawLink as Link, DrawNode as Node};
 
-#[derive(serde::Serialize)]
-struct Node {
-    id: String,
-    desc: String,
-}
-
-#[derive(serde::Serialize)]
-struct Link {
-    source: String,
-    target: String,
-    strength: f32,
+/// The cached graph of the database, see [`frictune::graph`].
+#[cfg(target_arch = "wasm32")]
+fn cached_graph(db: &frictune::db::crud::Database) -> std::sync::Arc<frictune::graph::TagGraph> {
+    match futures::executor::block_on(db.graph()) {
+        Ok(graph) => graph,
+        Err(e) => frictune::logger::rupt(e.to_string().as_str()),
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn export_nodes_json(db: &mut frictune::db::crud::Database) -> String {
-    let tag_names = frictune::Tag::get_tags(db);
-    serde_json::to_string(&tag_names.iter()
-        .map(|t| {
-            let tag = frictune::Tag::new(t);
-            Node { id: t.into(), desc: tag.qd_sync(db).unwrap_or_default() } 
-        }).collect::<Vec<_>>()
-    ).unwrap()
+pub fn export_nodes_json(db: &frictune::db::crud::Database) -> String {
+    serde_json::to_string(&cached_graph(db).draw_nodes()).unwrap()
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn export_succ_json(tag_name: &str, db: &mut frictune::db::crud::Database) -> (String, String) {
+pub fn export_succ_json(tag_name: &str, db: &frictune::db::crud::Database) -> (String, String) {
     let this_tag = frictune::Tag::new(tag_name);
     let tag_names = this_tag.qtrd(db);
+    let graph = cached_graph(db);
+    let clusters = frictune::cluster::membership(&graph);
+    let cluster = |name: &str| clusters.get(name).copied().unwrap_or_default();
     let (mut nodes, links): (Vec<_>, Vec<_>) = tag_names.iter()
         .map(|(name, desc, weight)| {
             (
-                Node { id: name.into(), desc: desc.clone().unwrap_or_default() },
+                Node { id: name.into(), desc: desc.clone().unwrap_or_default(), cluster: cluster(name) },
                 Link { source: tag_name.into(), target: name.into(), strength: weight.unwrap_or_default() },
             )
         }).unzip();
-    nodes.push(Node { id: tag_name.into(), desc: this_tag.qd_sync(db).unwrap_or_default() });
+    let desc = graph.id(tag_name).and_then(|id| graph.desc(id)).unwrap_or_default();
+    nodes.push(Node { id: tag_name.into(), desc: desc.into(), cluster: cluster(tag_name) });
     (serde_json::to_string(&nodes).unwrap(), serde_json::to_string(&links).unwrap())
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn export_links_json(db: &mut frictune::db::crud::Database) -> String {
-    let tag_names = frictune::Tag::get_tags(db);
-    serde_json::to_string(&tag_names.iter()
-        .flat_map(|t| {
-            let tag = frictune::Tag::new(t);
-            tag.qtrd(db).iter().map(|(name, _, weight)| {
-                Link { source: t.into(), target: name.into(), strength: weight.unwrap_or_default() }
-            }).collect::<Vec<_>>()
-        }).collect::<Vec<_>>()
-    ).unwrap()
+pub fn export_links_json(db: &frictune::db::crud::Database) -> String {
+    serde_json::to_string(&cached_graph(db).draw_links()).unwrap()
 }
 
 use dioxus::prelude::*;
@@ -79,7 +67,7 @@ pub fn inspect_graph(cx: Scope<GraphProps>) -> Element {
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn d3play(db: &mut frictune::db::crud::Database) {
+pub fn d3play(db: &frictune::db::crud::Database) {
     let window = web_sys::window().expect("no global `window` exists");
     let document = window.document().expect("should have a document on window");
     let body = document.body().expect("document should have a body");
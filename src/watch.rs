@@ -0,0 +1,264 @@
+//! Keeping the database in sync with a folder.
+//!
+//! [`Watch`] follows the posts (`.txt` files in the `tune_html` markup)
+//! and the Markdown notes under a folder through the notifications of
+//! the file system. When a post changes, its blocks are read again and
+//! the database takes over the differences: new tags, new descriptions
+//! and links whose weights changed. A link gone from the post is
+//! removed as well, unless another post still has it; tags are never
+//! removed. The links of every post are kept in the `post_links` table,
+//! so that the changes made while the watch was not running are taken
+//! over by [`Watch::sync_all`]. The blocks are recorded for
+//! [`crate::infer`] as `tune_html` does, and the page can be rendered
+//! again with [`Render`]. A post with a malformed block is left as it
+//! was until the block is fixed.
+//!
+//! Notes are indexed as by `frictune index`, see [`crate::notes`].
+//! Every change is logged with the path of its file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::executor::block_on;
+use notify::{EventKind, RecursiveMode, Watcher as _};
+
+use crate::{
+    db::{self, crud::{Database, DatabaseError}},
+    infer,
+    markup::{self, Block},
+    notes::{self, IndexReport},
+    render, Tag,
+};
+
+/// How long to wait for more events after one, as editors save in
+/// several steps.
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Where and how changed posts are rendered.
+#[derive(Clone, Debug)]
+pub struct Render {
+    pub template: PathBuf,
+    /// A post `a.txt` is rendered into `a.html` here.
+    pub out_dir: PathBuf,
+}
+
+/// The tags and links a post puts into the database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PostTags {
+    /// Every tag with its description, if the post gives one.
+    pub tags: BTreeMap<String, Option<String>>,
+    pub links: BTreeMap<(String, String), f64>,
+}
+
+impl PostTags {
+    pub fn from_blocks(blocks: &[Block]) -> Self {
+        let mut post = PostTags::default();
+        for block in blocks {
+            let tags = std::iter::once(&block.main).chain(block.trailers.iter().map(|(tag, _)| tag));
+            for tag in tags {
+                let desc = post.tags.entry(tag.raw_name().into_owned()).or_default();
+                if tag.desc.is_some() { *desc = tag.desc.as_deref().map(db::unquote) }
+            }
+            for (trailer, weight) in block.trailers.iter() {
+                post.links.insert((block.main.raw_name().into_owned(), trailer.raw_name().into_owned()), *weight);
+            }
+        }
+        post
+    }
+}
+
+/// Whether a path is a post in the `tune_html` markup.
+pub fn is_post(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("txt"))
+}
+
+pub struct Watch {
+    db: Database,
+    root: PathBuf,
+    notes: notes::Settings,
+    render: Option<Render>,
+}
+
+impl Watch {
+    pub fn new(db: Database, dir: &Path, notes: notes::Settings, render: Option<Render>) -> anyhow::Result<Self> {
+        if let Some(render) = &render {
+            std::fs::create_dir_all(&render.out_dir)?;
+        }
+        Ok(Watch { db, root: dir.canonicalize()?, notes, render })
+    }
+
+    /// Brings the database up to date with every post and note under
+    /// the folder, including the posts removed since the last run, as a
+    /// start.
+    pub fn sync_all(&self) -> anyhow::Result<()> {
+        let report = block_on(notes::index_dir(&self.db, &self.root, &self.notes))?;
+        if let Some(summary) = summary(&report) {
+            crate::logger::print(&format!("notes: {}", summary));
+        }
+        let walk = walkdir::WalkDir::new(&self.root).into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+        for entry in walk {
+            let entry = entry?;
+            if entry.file_type().is_file() && is_post(entry.path()) {
+                self.update(entry.path());
+            }
+        }
+        let known = block_on(self.db.read("post_links", &["post".to_string()], "true", ""))?.get::<String>(0);
+        let gone: BTreeSet<PathBuf> = known.into_iter().map(PathBuf::from)
+            .filter(|path| path.starts_with(&self.root) && !path.exists())
+            .collect();
+        for path in gone {
+            self.update(&path);
+        }
+        Ok(())
+    }
+
+    /// Takes over a file that was created, modified or removed, and
+    /// logs what changed.
+    pub fn update(&self, path: &Path) {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')) { return }
+        let changes = if notes::is_note(path) {
+            block_on(notes::sync_note(&self.db, path, &self.notes))
+                .map(|report| summary(&report).into_iter().collect())
+        } else if is_post(path) {
+            block_on(self.update_post(path))
+        } else {
+            return
+        };
+        match changes {
+            Ok(changes) => for change in changes {
+                crate::logger::print(&format!("{}: {}", relative.display(), change));
+            },
+            Err(e) => crate::logger::warn(format!("{}: {}", relative.display(), e)),
+        }
+    }
+
+    async fn update_post(&self, path: &Path) -> anyhow::Result<Vec<String>> {
+        let key = path.to_string_lossy().to_string();
+        let page = match std::fs::read_to_string(path) {
+            Ok(text) => Some(render::page(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some((range, message)) = page.as_ref().and_then(|(_, content)| markup::syntax_error(content)) {
+            anyhow::bail!("not updated, a block at byte {} of the paragraphs is malformed: {}", range.start, message);
+        }
+        let blocks = match &page {
+            Some((_, content)) => markup::parse_blocks(content).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+            None => vec![],
+        };
+        let new = PostTags::from_blocks(&blocks);
+        let old = stored_links(&self.db, &key).await?;
+
+        let mut changes = vec![];
+        let tx = self.db.begin().await?;
+        let db = &tx;
+        let graph = db.graph().await?;
+        for (name, desc) in new.tags.iter() {
+            match graph.id(name) {
+                None => {
+                    Tag::new_with_desc(name, desc.as_deref().map(db::quote)).add_tag::<String>(db, &[]).await?;
+                    changes.push(format!("add tag {}", name));
+                },
+                Some(id) => if let Some(desc) = desc.as_ref().filter(|desc| graph.desc(id) != Some(desc.as_str())) {
+                    Tag::new(name).modify_tag(db, &db::quote(desc)).await?;
+                    changes.push(format!("describe {}: {}", name, desc));
+                },
+            }
+        }
+        for ((source, target), weight) in new.links.iter() {
+            let current = graph.id(source).zip(graph.id(target))
+                .and_then(|(from, to)| graph.link(from, to))
+                .filter(|link| link.is_origin)
+                .map(|link| link.weight);
+            if current != Some(*weight) {
+                Tag::new(source).link_tags(db, &Tag::new(target), *weight).await?;
+                changes.push(format!("link {} -> {} ({})", source, target, weight));
+            }
+        }
+        for pair in old.keys().filter(|pair| !new.links.contains_key(*pair)) {
+            let others = db.read("post_links", &["post".to_string()],
+                &format!("tag1 = {} AND tag2 = {} AND post <> {}", db::quote(&pair.0), db::quote(&pair.1), db::quote(&key)), "").await?;
+            if !others.is_empty() { continue }
+            Tag::new(&pair.0).unlink_tags(db, &Tag::new(&pair.1)).await?;
+            changes.push(format!("unlink {} -> {}", pair.0, pair.1));
+        }
+        db.delete("post_links", "post", &db::quote(&key)).await?;
+        for ((source, target), weight) in new.links.iter() {
+            db.create("post_links",
+                &["post".to_string(), "tag1".to_string(), "tag2".to_string(), "weight".to_string()],
+                &[db::quote(&key), db::quote(source), db::quote(target), weight.to_string()]
+            ).await?;
+        }
+        infer::record_post(db, &key, &blocks).await?;
+        tx.commit().await?;
+
+        if let (Some(render), Some((title, content))) = (&self.render, &page) {
+            let out = render.out_dir.join(path.file_stem().unwrap_or_default()).with_extension("html");
+            let html = render::fill(&self.db, &render.template.to_string_lossy(), title, content, &blocks).await?;
+            std::fs::write(&out, html)?;
+            changes.push(format!("render {}", out.display()));
+        }
+        Ok(changes)
+    }
+
+    /// Follows the folder until the notifications stop.
+    pub fn run(&self) -> anyhow::Result<()> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&self.root, RecursiveMode::Recursive)?;
+        crate::logger::print(&format!("watching {}", self.root.display()));
+        while let Ok(event) = receiver.recv() {
+            let mut paths = BTreeSet::new();
+            let mut collect = |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => paths.extend(event.paths),
+                Ok(_) => {},
+                Err(e) => crate::logger::warn(e.to_string()),
+            };
+            collect(event);
+            while let Ok(event) = receiver.recv_timeout(SETTLE) {
+                collect(event);
+            }
+            for path in paths {
+                self.update(&path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The links a post put into the database when it was last taken over.
+async fn stored_links(db: &Database, post: &str) -> Result<BTreeMap<(String, String), f64>, DatabaseError> {
+    let rows = db.read("post_links", &["tag1".to_string(), "tag2".to_string(), "weight".to_string()],
+        &format!("post = {}", db::quote(post)), "").await?;
+    Ok(itertools::izip!(rows.get::<String>(0), rows.get::<String>(1), rows.get::<f64>(2))
+        .map(|(source, target, weight)| ((source, target), weight))
+        .collect())
+}
+
+/// What indexing a note changed, if anything.
+fn summary(report: &IndexReport) -> Option<String> {
+    if report.indexed == 0 && report.removed == 0 { return None }
+    Some(format!("{} indexed, {} removed; {} links added, {} removed",
+        report.indexed, report.removed, report.links_added, report.links_removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_post_tags() {
+        let (_, content) = render::page("title\n\n{{ cargo \"https://doc.rust-lang.org/cargo\" (rust 0.5) }} {{ rust \"lang\" (cargo) }}");
+        let post = PostTags::from_blocks(&markup::parse_blocks(&content).unwrap());
+        assert_eq!(post.tags.get("cargo"), Some(&Some("https://doc.rust-lang.org/cargo".to_string())));
+        assert_eq!(post.tags.get("rust"), Some(&Some("lang".to_string())));
+        assert_eq!(post.links.get(&("cargo".to_string(), "rust".to_string())), Some(&0.5));
+        assert_eq!(post.links.get(&("rust".to_string(), "cargo".to_string())), Some(&1.0));
+        assert!(is_post(Path::new("a/b.TXT")));
+    }
+}
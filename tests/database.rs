@@ -0,0 +1,94 @@
+use frictune::{db::{self, crud::Database, migrate}, export::Snapshot, graph::TagGraph, store::blocking::TagStore, Tag};
+use futures::executor::block_on;
+
+#[test]
+fn test_raw_writes_reach_the_graph() {
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    store.tag("cargo").add_linked(&[("rust", 0.8)]).unwrap();
+    let db = store.database().clone();
+    assert_eq!(block_on(db.graph()).unwrap().len(), 2);
+
+    let entries = ["tag1".to_string(), "tag2".to_string(), "weight".to_string(), "is_origin".to_string()];
+    let data = [db::quote("cargo"), db::quote("rust"), "0.3".to_string(), "true".to_string()];
+    block_on(db.update("relationship", &entries, &data, &entries[2..], &data[2..], "true")).unwrap();
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(0.3));
+
+    block_on(db.create("tags", &["tag_name".to_string()], &[db::quote("crates")])).unwrap();
+    assert_eq!(block_on(db.graph()).unwrap().len(), 3);
+
+    block_on(db.delete("relationship", "tag1", &db::quote("cargo"))).unwrap();
+    assert_eq!(store.tag("cargo").neighbors().unwrap(), vec![]);
+}
+
+#[test]
+fn test_handles_across_threads() {
+    let folder = tempfile::tempdir().unwrap();
+    let db = Database::sync_new(folder.path().join("tags.db").to_str().unwrap()).unwrap();
+    Tag::new("hub").add_sync::<String>(&db, &[]);
+    let mut threads: Vec<_> = (0..4).map(|i| {
+        let db = db.clone();
+        std::thread::spawn(move || for j in 0..10 {
+            let tag = Tag::new(&format!("t{}-{}", i, j));
+            tag.add_sync(&db, &[(Tag::new("hub"), 0.5)]);
+            // a handle reads its own writes through the cached graph
+            let graph = block_on(db.graph()).unwrap();
+            let (from, to) = (graph.id(&tag.raw_name()).unwrap(), graph.id("hub").unwrap());
+            assert_eq!(graph.link(from, to).map(|link| link.weight), Some(0.5));
+        })
+    }).collect();
+    // and loads racing the writes never cache a stale graph
+    let reader = db.clone();
+    threads.push(std::thread::spawn(move || for _ in 0..20 {
+        reader.invalidate_graph();
+        block_on(reader.graph()).unwrap();
+    }));
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let cached = block_on(db.graph()).unwrap();
+    assert_eq!(cached.len(), 41);
+    let loaded = TagGraph::load_sync(&db).unwrap();
+    assert_eq!(Snapshot::from_graph(&cached, true), Snapshot::from_graph(&loaded, true));
+}
+
+#[test]
+fn test_search_index_follows_raw_writes_on_open() {
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("tags.db");
+    let store = TagStore::from_database(Database::sync_new(path.to_str().unwrap()).unwrap());
+    store.tag("rust").describe("the language").unwrap();
+    store.tag("go").add().unwrap();
+    assert_eq!(store.search("language", 5).unwrap()[0].name, "rust");
+
+    // the same number of tags, with other names and descriptions
+    let db = store.database();
+    let entries = ["tag_name".to_string(), "info".to_string()];
+    let data = [db::quote("rust"), db::quote("the compiler")];
+    block_on(db.update("tags", &entries, &data, &entries[1..], &data[1..], "true")).unwrap();
+    block_on(db.delete("tags", "tag_name", &db::quote("go"))).unwrap();
+    block_on(db.create("tags", &entries[..1], &[db::quote("zig")])).unwrap();
+
+    let store = TagStore::from_database(Database::sync_new(path.to_str().unwrap()).unwrap());
+    assert!(store.search("language", 5).unwrap().is_empty());
+    assert_eq!(store.search("compiler", 5).unwrap()[0].name, "rust");
+    assert_eq!(store.search("zig", 5).unwrap()[0].name, "zig");
+    assert!(store.search("go", 5).unwrap().is_empty());
+}
+
+#[test]
+fn test_schema_version_only_reads() {
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("tags.db");
+    let db = Database::sync_open(path.to_str().unwrap()).unwrap();
+    assert_eq!(block_on(db.schema_version()).unwrap(), 0);
+    let tables = block_on(db.read("sqlite_master", &["name".to_string()], "type = 'table'", "")).unwrap();
+    assert!(tables.get::<String>(0).is_empty());
+
+    let db = Database::sync_new(path.to_str().unwrap()).unwrap();
+    let graph = block_on(db.graph()).unwrap();
+    let status = block_on(db.migration_status()).unwrap();
+    assert_eq!((status.current, status.pending.len()), (migrate::latest(), 0));
+    // the cached graph is still the one loaded before
+    assert!(std::sync::Arc::ptr_eq(&graph, &block_on(db.graph()).unwrap()));
+}
@@ -0,0 +1,249 @@
+use frictune::{
+    db::crud::Database,
+    export::{self, ImportMode, ImportReport, Snapshot},
+    store::blocking::TagStore,
+};
+
+fn sample() -> TagStore {
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    store.tag("rust").describe("https://rust-lang.org").unwrap();
+    store.tag("cargo").add_linked(&[("rust", 0.8)]).unwrap();
+    store.tag("crates").add_linked(&[("cargo", 0.5)]).unwrap();
+    store
+}
+
+#[test]
+fn test_json_round_trip() {
+    let store = sample();
+    let snapshot = Snapshot::read_sync(store.database(), false).unwrap();
+    assert_eq!(snapshot.tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["cargo", "crates", "rust"]);
+    assert!(snapshot.links.iter().all(|l| l.is_origin));
+
+    let mut dump = vec![];
+    export::json::write(&snapshot, &mut dump).unwrap();
+    let copy = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    let report = export::import_sync(copy.database(), &export::json::read(dump.as_slice()).unwrap(), ImportMode::Merge).unwrap();
+    assert_eq!(report, ImportReport { tags_added: 3, tags_described: 0, links_set: 2 });
+    assert_eq!(Snapshot::read_sync(copy.database(), true).unwrap(), Snapshot::read_sync(store.database(), true).unwrap());
+
+    // a newer document is refused
+    assert!(export::json::read(r#"{"frictune": 99, "tags": []}"#.as_bytes()).is_err());
+}
+
+#[test]
+fn test_merge_and_replace() {
+    let store = sample();
+    let incoming = Snapshot {
+        tags: vec![export::TagEntry { name: "rust".into(), desc: Some("the language".into()) }],
+        links: vec![export::LinkEntry { source: "rust".into(), target: "go".into(), weight: 0.2, is_origin: true }],
+    };
+    let report = export::import_sync(store.database(), &incoming, ImportMode::Merge).unwrap();
+    assert_eq!(report, ImportReport { tags_added: 1, tags_described: 1, links_set: 1 });
+    assert_eq!(store.tags().unwrap().len(), 4);
+    assert_eq!(store.tag("rust").desc().unwrap().as_deref(), Some("the language"));
+
+    export::import_sync(store.database(), &incoming, ImportMode::Replace).unwrap();
+    let mut names = store.tags().unwrap();
+    names.sort();
+    assert_eq!(names, vec!["go", "rust"]);
+    assert_eq!(store.tag("rust").weight_to("go").unwrap(), Some(0.2));
+}
+
+#[test]
+fn test_replace_clears_indexed_rows() {
+    use futures::executor::block_on;
+    use frictune::notes;
+
+    let store = sample();
+    let settings = notes::Settings::default();
+    block_on(notes::index_note(store.database(), "cargo.md", "#cargo #crates", &settings)).unwrap();
+    block_on(store.database().execute("INSERT INTO post_blocks VALUES ('post', 0, 'rust');
+        INSERT INTO post_links VALUES ('post', 'cargo', 'rust', 0.5);
+        INSERT INTO inferred_link VALUES ('rust', 'cargo', 'jaccard', 0.5);")).unwrap();
+
+    let incoming = Snapshot { tags: vec![export::TagEntry { name: "rust".into(), desc: None }], links: vec![] };
+    export::import_sync(store.database(), &incoming, ImportMode::Replace).unwrap();
+    for table in ["post_blocks", "post_links", "inferred_link", "note_links", "notes"] {
+        let rows = block_on(store.database().read(table, &["*".to_string()], "true", "")).unwrap();
+        assert!(rows.get::<String>(0).is_empty(), "{} is not empty", table);
+    }
+    // so the note links its tags again
+    let report = block_on(notes::index_note(store.database(), "cargo.md", "#cargo #crates", &settings)).unwrap();
+    assert_eq!(store.tag("cargo").weight_to("crates").unwrap(), Some(settings.weight));
+    assert_eq!(report.indexed, 1);
+}
+
+#[test]
+fn test_csv_columns_and_problems() {
+    let tags = "Tag,Notes\nrust,the language\n  ,empty\nit's,quoted\n";
+    let links = "from,to,weight\ncargo,rust,0.8\ncargo,rust,1.5\ncargo,rust,heavy\n";
+    let mut columns = export::csv::Columns::default();
+    for mapping in ["name=Tag", "desc=Notes", "source=from", "target=to"] {
+        columns.map(mapping).unwrap();
+    }
+    assert!(columns.map("colour=Colour").is_err());
+
+    let (snapshot, problems) = export::csv::read(tags.as_bytes(), links.as_bytes(), &columns).unwrap();
+    assert_eq!(snapshot.tags, vec![
+        export::TagEntry { name: "rust".into(), desc: Some("the language".into()) },
+        export::TagEntry { name: "it's".into(), desc: Some("quoted".into()) },
+    ]);
+    assert_eq!(snapshot.links, vec![export::LinkEntry { source: "cargo".into(), target: "rust".into(), weight: 0.8, is_origin: true }]);
+    assert_eq!(problems.iter().map(|p| (p.file, p.line)).collect::<Vec<_>>(),
+        vec![("tags.csv", 3), ("links.csv", 3), ("links.csv", 4)]);
+
+    // a missing required column is an error, not a problem per row
+    assert!(export::csv::read_links("source,target\ncargo,rust\n".as_bytes(), &export::csv::Columns::default()).is_err());
+}
+
+#[test]
+fn test_csv_diff() {
+    let store = sample();
+    let mut tags = vec![];
+    let mut links = vec![];
+    export::csv::write(&Snapshot::read_sync(store.database(), false).unwrap(), &mut tags, &mut links).unwrap();
+    let (snapshot, _) = export::csv::read(tags.as_slice(), links.as_slice(), &export::csv::Columns::default()).unwrap();
+    let graph = futures::executor::block_on(store.database().graph()).unwrap();
+    assert!(export::diff(&graph, &snapshot).is_empty());
+
+    let incoming = Snapshot {
+        tags: vec![export::TagEntry { name: "go".into(), desc: None }],
+        links: vec![
+            export::LinkEntry { source: "cargo".into(), target: "rust".into(), weight: 0.4, is_origin: true },
+            export::LinkEntry { source: "go".into(), target: "rust".into(), weight: 0.1, is_origin: true },
+        ],
+    };
+    assert_eq!(export::diff(&graph, &incoming), vec![
+        export::Change::AddTag { name: "go".into(), desc: None },
+        export::Change::Reweight { source: "cargo".into(), target: "rust".into(), old: 0.8, new: 0.4 },
+        export::Change::AddLink { source: "go".into(), target: "rust".into(), weight: 0.1 },
+    ]);
+}
+
+#[test]
+fn test_graphml_and_gexf() {
+    let store = sample();
+    store.tag("rust").describe("<systems> & \"safe\"").unwrap();
+    store.tag("go").add_linked(&[("crates", 0.1)]).unwrap();
+    let graph = futures::executor::block_on(store.database().graph()).unwrap();
+
+    let nearby = Snapshot::around(&graph, "rust", 1, false).unwrap();
+    assert_eq!(nearby.tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["cargo", "rust"]);
+    assert!(Snapshot::around(&graph, "python", 1, false).is_none());
+    assert_eq!(Snapshot::around(&graph, "rust", 3, false).unwrap().tags.len(), 4);
+
+    let mut graphml = vec![];
+    export::graphml::write(&nearby, &mut graphml).unwrap();
+    let graphml = String::from_utf8(graphml).unwrap();
+    assert!(graphml.contains(r#"<data key="desc">&lt;systems&gt; &amp; &quot;safe&quot;</data>"#));
+    assert!(graphml.contains(r#"<edge source="cargo" target="rust"><data key="weight">0.8</data><data key="is_origin">true</data></edge>"#));
+    assert!(!graphml.contains("crates"));
+
+    let mut gexf = vec![];
+    export::gexf::write(&nearby, &mut gexf).unwrap();
+    let gexf = String::from_utf8(gexf).unwrap();
+    assert!(gexf.contains(r#"<node id="cargo" label="cargo"/>"#));
+    assert!(gexf.contains(r#"source="cargo" target="rust" weight="0.8""#));
+}
+
+#[test]
+fn test_dot() {
+    let store = sample();
+    store.tag("cargo").describe("the \"package\" manager").unwrap();
+    let graph = futures::executor::block_on(store.database().graph()).unwrap();
+    let snapshot = Snapshot::from_graph(&graph, true);
+    let mut dot = vec![];
+    export::dot::write(&snapshot, &mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.starts_with("digraph frictune {\n"));
+    assert!(dot.contains(r#"  "rust" [tooltip="https://rust-lang.org", URL="https://rust-lang.org"];"#));
+    assert!(dot.contains(r#"  "cargo" [tooltip="the \"package\" manager"];"#));
+    assert!(dot.contains(r#"  "cargo" -> "rust" [label="0.80", penwidth=3.4];"#));
+    for link in snapshot.links.iter() {
+        let edge = format!(r#"  "{}" -> "{}" "#, link.source, link.target);
+        let line = dot.lines().find(|line| line.starts_with(&edge)).unwrap();
+        assert_eq!(line.contains("style=dashed"), !link.is_origin);
+    }
+}
+
+#[test]
+fn test_bookmarks() {
+    let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3 PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><H3>Rust</H3>
+        <DL><p>
+            <DT><H3>Async</H3>
+            <DL><p>
+                <DT><A HREF="https://tokio.rs/" TAGS="runtime, networking">Tokio</A>
+            </DL><p>
+            <DT><A HREF="https://doc.rust-lang.org/book/">The Rust Programming Language &amp; Rust&#39;s book</A>
+        </DL><p>
+        <DT><A HREF="https://tokio.rs/" TAGS="async">Tokio again</A>
+        <DT><A HREF="https://example.com/">Tokio</A>
+    </DL><p>
+</DL><p>
+"#;
+    let weights = export::bookmarks::Weights { folder: 0.3, tag: 0.9 };
+    let mut snapshot = export::bookmarks::parse(html, &weights);
+    let names: Vec<_> = snapshot.tags.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["Rust", "Async", "Tokio", "runtime", "networking", "The Rust Programming Language & Rust's book", "async"]);
+    let link = |source: &str, target: &str| snapshot.links.iter()
+        .find(|l| l.source == source && l.target == target).map(|l| l.weight);
+    assert_eq!(link("Async", "Rust"), Some(0.3));
+    assert_eq!(link("Tokio", "Async"), Some(0.3));
+    assert_eq!(link("Tokio", "runtime"), Some(0.9));
+    // the second bookmark of the URL adds its keywords to the first
+    assert_eq!(link("Tokio", "async"), Some(0.9));
+    assert!(snapshot.tags.iter().all(|t| t.desc.as_deref() != Some("https://example.com/")));
+
+    let mut out = vec![];
+    export::bookmarks::write(&snapshot, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(r#"<DT><A HREF="https://tokio.rs/" TAGS="Async,runtime,networking,async">Tokio</A>"#));
+    assert_eq!(export::bookmarks::parse(&out, &weights).tags.len(), 7);
+
+    let graph = frictune::graph::TagGraph::from_parts(
+        vec![("tokio".into(), Some("https://tokio.rs/".into()))], vec![]);
+    assert_eq!(export::bookmarks::skip_known(&graph, &mut snapshot), vec!["Tokio"]);
+    assert!(snapshot.links.iter().all(|l| l.source != "Tokio"));
+}
+
+#[test]
+fn test_obsidian() {
+    use export::obsidian::{parse, Describe, Note, Settings};
+
+    let note = |path: &str, text: &str| Note { path: path.into(), text: text.into() };
+    let notes = [
+        note("Tokio.md", "---\naliases: Tokio runtime\ntags: [async]\n---\n# The Tokio runtime ##\nBuilt on [[Mio|mio]], see [[lang/rust#Async]] and #networking.\n![[diagram.png]] [[#Usage]]"),
+        note("async/Tokio.md", "Not the same [[Tokio runtime]]."),
+        note("lang/Rust.md", "```\n[[Hidden]] #hidden\n```\nSee `[[Code]]` and [[Ferris's Crab]]"),
+    ];
+    let settings = Settings { link: 0.4, tag: 0.7, describe: Describe::Heading };
+    let snapshot = parse(&notes, &settings);
+    let tags: Vec<_> = snapshot.tags.iter().map(|t| (t.name.as_str(), t.desc.as_deref())).collect();
+    assert_eq!(tags, vec![
+        ("Tokio", Some("The Tokio runtime")),
+        ("async/Tokio", Some("async/Tokio.md")),
+        ("Rust", Some("lang/Rust.md")),
+        ("Mio", None),
+        ("async", None),
+        ("networking", None),
+        ("Ferris's Crab", None),
+    ]);
+    let links: Vec<_> = snapshot.links.iter()
+        .map(|l| format!("{} -> {} ({})", l.source, l.target, l.weight))
+        .collect();
+    assert_eq!(links, vec![
+        "Tokio -> Mio (0.4)",
+        "Tokio -> Rust (0.4)",
+        "Tokio -> async (0.7)",
+        "Tokio -> networking (0.7)",
+        "async/Tokio -> Tokio (0.4)",
+        "Rust -> Ferris's Crab (0.4)",
+    ]);
+
+    let settings = Settings { describe: Describe::Path, ..Default::default() };
+    assert_eq!(parse(&notes, &settings).tags[0].desc.as_deref(), Some("Tokio.md"));
+}
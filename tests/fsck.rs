@@ -0,0 +1,46 @@
+use std::{path::Path, process::Command};
+
+use frictune::{db::crud::Database, store::blocking::TagStore};
+
+/// Runs the `frictune` binary in `dir`, where the default settings put
+/// the database at `tags.db`, and returns what it printed.
+fn frictune(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_frictune")).current_dir(dir).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_fsck_repair() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::sync_new(dir.path().join("tags.db").to_str().unwrap()).unwrap();
+    let store = TagStore::from_database(db.clone());
+    store.tag("rust").add().unwrap();
+    store.tag("cargo").add_linked(&[("rust", 0.8)]).unwrap();
+    // rows only an older version, or another program, could write
+    futures::executor::block_on(db.execute("PRAGMA foreign_keys = OFF;
+        INSERT INTO relationship (tag1, tag2, weight, is_origin) VALUES ('cargo', 'ghost', 0.5, true);
+        INSERT INTO relationship (tag1, tag2, weight, is_origin) VALUES ('rust', 'rust', 0.3, true);
+        INSERT INTO relationship (tag1, tag2, weight, is_origin) VALUES ('rust', 'cargo', NULL, false);
+        UPDATE relationship SET weight = 1.5 WHERE tag1 = 'cargo' AND tag2 = 'rust';
+        PRAGMA foreign_keys = ON;")).unwrap();
+    // the link without a weight is left out of the graph
+    assert_eq!(store.tag("rust").weight_to("cargo").unwrap(), None);
+
+    let out = frictune(dir.path(), &["fsck"]);
+    assert!(out.contains("ghost"));
+    assert!(out.contains("link rust -> cargo has no weight"));
+    assert!(out.contains("5 issues found, 0 repaired"));
+
+    // deleting the link without a weight repairs its other issue too
+    let out = frictune(dir.path(), &["fsck", "--repair"]);
+    assert!(out.contains("5 issues found, 4 repaired"));
+    let out = frictune(dir.path(), &["fsck"]);
+    assert!(out.contains("0 issues found"));
+
+    // the binary wrote behind the back of the cached graph
+    db.invalidate_graph();
+    let graph = store.graph().unwrap();
+    assert_eq!(graph.links().filter(|(_, link)| link.is_origin).count(), 1);
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(1.0));
+}
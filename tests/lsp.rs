@@ -0,0 +1,81 @@
+use std::thread;
+
+use frictune::{db::crud::Database, store::blocking::TagStore};
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use serde_json::{json, Value};
+
+struct Client {
+    connection: Connection,
+    next_id: i32,
+}
+
+impl Client {
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        self.connection.sender.send(Message::Request(Request::new(id.clone(), method.into(), params))).unwrap();
+        loop {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(response) if response.id == id => return response.result.unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.connection.sender.send(Message::Notification(Notification::new(method.into(), params))).unwrap();
+    }
+
+    fn diagnostics(&self) -> Value {
+        loop {
+            if let Message::Notification(n) = self.connection.receiver.recv().unwrap() {
+                if n.method == "textDocument/publishDiagnostics" { return n.params["diagnostics"].clone() }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_session() {
+    let db = Database::sync_new("sqlite::memory:").unwrap();
+    let store = TagStore::from_database(db.clone());
+    store.tag("rust").describe("https://rust-lang.org").unwrap();
+    store.tag("cargo").add_linked(&[("rust", 0.8)]).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let post = dir.path().join("post.txt");
+    let post_text = "{{ cargo \"https://doc.rust-lang.org/cargo\" (rust 0.5) }}";
+    std::fs::write(&post, post_text).unwrap();
+    frictune::infer::record_post_sync(store.database(), post.to_str().unwrap(),
+        &frictune::markup::parse_blocks(post_text).unwrap());
+
+    let (server, connection) = Connection::memory();
+    let handle = thread::spawn(move || frictune::lsp::run(db, &server).unwrap());
+    let mut client = Client { connection, next_id: 0 };
+    client.request("initialize", json!({ "capabilities": {} }));
+    client.notify("initialized", json!({}));
+
+    let uri = "file:///tmp/draft.txt";
+    let text = "{{ cargo \"x\" (rust 2.0) }} and {{ ca";
+    client.notify("textDocument/didOpen", json!({
+        "textDocument": { "uri": uri, "languageId": "text", "version": 1, "text": text }
+    }));
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.as_array().unwrap().len(), 2);
+    assert_eq!(diagnostics[1]["message"], "weight 2 is not within [0, 1]");
+
+    let at = |line: u32, character: u32| json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } });
+    let hover = client.request("textDocument/hover", at(0, 4));
+    assert!(hover["contents"]["value"].as_str().unwrap().contains("related: rust (0.80)"));
+
+    let completion = client.request("textDocument/completion", at(0, text.len() as u32));
+    assert_eq!(completion[0]["label"], "cargo");
+
+    let definition = client.request("textDocument/definition", at(0, 4));
+    assert_eq!(definition.as_array().unwrap().len(), 1);
+    assert!(definition[0]["uri"].as_str().unwrap().ends_with(post.file_name().unwrap().to_str().unwrap()));
+
+    client.request("shutdown", Value::Null);
+    client.notify("exit", Value::Null);
+    handle.join().unwrap();
+}
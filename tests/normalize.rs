@@ -0,0 +1,88 @@
+use frictune::{
+    db::crud::Database,
+    export::Snapshot,
+    notes,
+    normalize::{normalize_db_sync, Merge, Policy},
+    store::blocking::TagStore,
+};
+
+fn sample() -> TagStore {
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    store.tag("Rust").describe("https://rust-lang.org").unwrap();
+    store.tag("rust ").add_linked(&[("cargo", 0.4)]).unwrap();
+    store.tag("Rust").add_linked(&[("cargo", 0.8)]).unwrap();
+    store.tag("Go ").add_linked(&[("cargo", 0.1)]).unwrap();
+    store
+}
+
+fn names(store: &TagStore) -> Vec<String> {
+    let mut names = store.tags().unwrap();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_normalize_db() {
+    let store = sample();
+    let policy = Policy { case_fold: true, trim: true, ..Policy::NONE };
+    let merges = normalize_db_sync(store.database(), &policy, false).unwrap();
+    assert_eq!(merges, vec![
+        Merge { target: "go".into(), sources: vec!["Go ".into()] },
+        Merge { target: "rust".into(), sources: vec!["Rust".into(), "rust ".into()] },
+    ]);
+    assert_eq!(names(&store), vec!["cargo", "go", "rust"]);
+    assert_eq!(store.tag("rust").desc().unwrap().as_deref(), Some("https://rust-lang.org"));
+    // the heavier of the two links stays
+    assert_eq!(store.tag("rust").weight_to("cargo").unwrap(), Some(0.8));
+    assert_eq!(store.tag("go").weight_to("cargo").unwrap(), Some(0.1));
+}
+
+#[test]
+fn test_normalize_db_rolls_back() {
+    let store = sample();
+    // a link to a missing tag cannot be moved over
+    futures::executor::block_on(store.database().execute("PRAGMA foreign_keys = OFF;
+        INSERT INTO relationship VALUES ('rust ', 'ghost', 0.5, true);
+        PRAGMA foreign_keys = ON;")).unwrap();
+    let policy = Policy { case_fold: true, trim: true, ..Policy::NONE };
+    assert!(normalize_db_sync(store.database(), &policy, false).is_err());
+    // `Go ` was merged before the failure, and is back
+    assert_eq!(names(&store), vec!["Go ", "Rust", "cargo", "rust "]);
+    assert_eq!(store.tag("Rust").weight_to("cargo").unwrap(), Some(0.8));
+    assert_eq!(store.tag("Go ").weight_to("cargo").unwrap(), Some(0.1));
+}
+
+#[test]
+fn test_normalize_db_prefers_origin_links() {
+    let store = sample();
+    // a derived link heavier than the origin link it collides with
+    futures::executor::block_on(store.database().execute(
+        "INSERT INTO tags (tag_name) VALUES ('RUST');
+        INSERT INTO relationship VALUES ('RUST', 'cargo', 0.9, false);")).unwrap();
+    let policy = Policy { case_fold: true, trim: true, ..Policy::NONE };
+    normalize_db_sync(store.database(), &policy, false).unwrap();
+    let snapshot = Snapshot::read_sync(store.database(), true).unwrap();
+    let link = snapshot.links.iter().find(|l| l.source == "rust" && l.target == "cargo").unwrap();
+    assert_eq!((link.weight, link.is_origin), (0.8, true));
+}
+
+#[test]
+fn test_normalize_db_renames_note_links() {
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    let folder = tempfile::tempdir().unwrap();
+    let note = folder.path().join("a.md");
+    let settings = notes::Settings::default();
+    std::fs::write(&note, "#Rust #cli").unwrap();
+    notes::index_dir_sync(store.database(), folder.path(), &settings).unwrap();
+
+    let policy = Policy { case_fold: true, ..Policy::NONE };
+    normalize_db_sync(store.database(), &policy, false).unwrap();
+    assert_eq!(store.tag("rust").weight_to("cli").unwrap(), Some(settings.weight));
+
+    // the note still backs the links under their new names
+    std::fs::write(&note, "#cli").unwrap();
+    let report = notes::index_dir_sync(store.database(), folder.path(), &settings).unwrap();
+    assert_eq!(report.links_removed, 2);
+    assert_eq!(store.tag("rust").neighbors().unwrap(), vec![]);
+    assert_eq!(store.tag("cli").weight_to("rust").unwrap(), None);
+}
@@ -0,0 +1,96 @@
+use std::fs;
+
+use frictune::{
+    db::crud::Database,
+    notes::{self, IndexReport, Settings},
+    store::blocking::TagStore,
+};
+
+/// A folder of notes with a hidden folder in it, removed on drop.
+fn folder() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join(".hidden")).unwrap();
+    dir
+}
+
+/// The origin links as `source -> target` strings.
+fn links(store: &TagStore) -> Vec<String> {
+    let graph = store.graph().unwrap();
+    let mut links: Vec<_> = graph.links()
+        .filter(|(_, link)| link.is_origin)
+        .map(|(from, link)| format!("{} -> {}", graph.name(from), graph.name(link.to)))
+        .collect();
+    links.sort();
+    links
+}
+
+#[test]
+fn test_index_incrementally() {
+    let folder = folder();
+    let dir = folder.path();
+    fs::write(dir.join("a.md"), "---\ntags: [rust, cli]\n---\nParsing arguments with #clap.").unwrap();
+    fs::write(dir.join("b.md"), "#rust and #cli").unwrap();
+    fs::write(dir.join(".hidden/c.md"), "#secret #rust").unwrap();
+    fs::write(dir.join("d.txt"), "#text #rust").unwrap();
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    let settings = Settings { weight: 0.4 };
+
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { indexed: 2, links_added: 6, ..Default::default() });
+    let mut names = store.tags().unwrap();
+    names.sort();
+    assert_eq!(names, vec!["clap", "cli", "rust"]);
+    assert_eq!(store.tag("rust").weight_to("clap").unwrap(), Some(0.4));
+
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { unchanged: 2, ..Default::default() });
+
+    // clap is gone from a.md, and no other note backs its links
+    fs::write(dir.join("a.md"), "#rust #cli").unwrap();
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { indexed: 1, unchanged: 1, links_removed: 4, ..Default::default() });
+    assert_eq!(links(&store), vec!["cli -> rust", "rust -> cli"]);
+
+    // a.md still backs the links of b.md
+    fs::remove_file(dir.join("b.md")).unwrap();
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { unchanged: 1, removed: 1, ..Default::default() });
+
+    // a weight set by hand stays
+    store.tag("rust").link("cli", 0.9).unwrap();
+    fs::remove_file(dir.join("a.md")).unwrap();
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { removed: 1, links_removed: 1, ..Default::default() });
+    assert_eq!(links(&store), vec!["rust -> cli"]);
+}
+
+#[test]
+fn test_links_set_by_hand_survive() {
+    let folder = folder();
+    let dir = folder.path();
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    let settings = Settings { weight: 0.4 };
+    // at the weight the notes would have given it
+    store.tag("rust").add_linked(&[("cli", 0.4)]).unwrap();
+
+    fs::write(dir.join("a.md"), "#rust #cli").unwrap();
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { indexed: 1, links_added: 1, ..Default::default() });
+    fs::remove_file(dir.join("a.md")).unwrap();
+    let report = notes::index_dir_sync(store.database(), dir, &settings).unwrap();
+    assert_eq!(report, IndexReport { removed: 1, links_removed: 1, ..Default::default() });
+    assert_eq!(links(&store), vec!["rust -> cli"]);
+}
+
+#[test]
+fn test_index_rolls_back() {
+    let folder = folder();
+    let dir = folder.path();
+    fs::write(dir.join("a.md"), "#rust #cli").unwrap();
+    fs::write(dir.join("b.md"), [0xff, 0xfe]).unwrap();
+    let store = TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    assert!(notes::index_dir_sync(store.database(), dir, &Settings::default()).is_err());
+    assert_eq!(store.tags().unwrap(), Vec::<String>::new());
+    let notes = futures::executor::block_on(store.database().read("note_links", &["path".to_string()], "true", "")).unwrap();
+    assert!(notes.get::<String>(0).is_empty());
+}
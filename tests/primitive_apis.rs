@@ -1,28 +1,30 @@
-use frictune::{logger::watch, db, Tag};
+use frictune::{db, Tag};
 use futures::executor::block_on;
 
+#[test]
 fn test_primitive_apis() {
-        block_on(async {
-        let mut conn = db::crud::Database::new("./tags.db").await.unwrap();
-        watch(conn.delete("relationship", "1", "1").await);
-        let a = frictune::Tag{ name: String::from("7"), desc: None };
-        let b = frictune::Tag{ name: String::from("8"), desc: None };
-        watch(a.add_tag::<String>(&mut conn, &[]).await);
-        watch(b.add_tag(&mut conn, &[(String::from("7"), 0.4)]).await);
-        let c = frictune::Tag{ name: String::from("9"), desc: None };
-        watch(c.add_tag::<String>(&mut conn, &[]).await);
-        watch(a.link_tags(&mut conn, &c.name, 0.8).await);
-        b.auto_update_links(&mut conn).await;
-        let d = frictune::Tag{ name: String::from("10"), desc: None };
-        watch(d.add_tag(&mut conn, &[(String::from("7"), 0.4)]).await);
-        watch(b.link_tags(&mut conn, &d.name, 0.2).await);
-        watch(d.link_tags(&mut conn, &b.name, 0.9).await);
-        Tag::update_all_links(&mut conn).await;
-        //println!("{}", Tag::query_relation(&mut conn, "7", "8").await.unwrap());
+    let folder = tempfile::tempdir().unwrap();
+    block_on(async {
+        let conn = db::crud::Database::new(folder.path().join("tags.db").to_str().unwrap()).await.unwrap();
+        let (a, b, c, d) = (Tag::new("7"), Tag::new("8"), Tag::new("9"), Tag::new("10"));
+        a.add_tag::<String>(&conn, &[]).await.unwrap();
+        b.add_tag(&conn, &[(String::from("7"), 0.4)]).await.unwrap();
+        c.add_tag::<String>(&conn, &[]).await.unwrap();
+        a.link_tags(&conn, &c, 0.8).await.unwrap();
+        // derived links never replace origin links
+        b.auto_update_links(&conn).await;
+        assert_eq!(Tag::query_relation(&conn, &b, &a).await, Some(0.4));
 
-        // match c.remove_tag(&mut conn).await {
-        //     Ok(_) => {},
-        //     Err(e) => {println!("{}", e)},
-        // }
+        d.add_tag(&conn, &[(String::from("7"), 0.4)]).await.unwrap();
+        b.link_tags(&conn, &d, 0.2).await.unwrap();
+        d.link_tags(&conn, &b, 0.9).await.unwrap();
+        Tag::update_all_links(&conn).await;
+        assert_eq!(Tag::query_relation(&conn, &d, &b).await, Some(0.9));
+        assert_eq!(Tag::query_relation(&conn, &b, &d).await, Some(0.2));
+        assert_eq!(Tag::query_relation(&conn, &a, &c).await, Some(0.8));
+
+        c.remove_tag(&conn).await.unwrap();
+        assert!(!c.exists(&conn).await);
+        assert_eq!(Tag::query_relation(&conn, &a, &c).await, None);
     });
-}
\ No newline at end of file
+}
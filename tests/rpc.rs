@@ -0,0 +1,67 @@
+use frictune::{db::crud::Database, rpc};
+use serde_json::{json, Value};
+
+fn session() -> rpc::Session {
+    rpc::Session::new(Database::sync_new("sqlite::memory:").unwrap())
+}
+
+fn call(session: &rpc::Session, id: i64, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    let response: Value = serde_json::from_str(&session.handle_line(&request.to_string()).unwrap()).unwrap();
+    assert_eq!(response["id"], id);
+    response
+}
+
+#[test]
+fn test_methods() {
+    let session = session();
+    call(&session, 1, "add", json!({ "name": "rust", "desc": "https://rust-lang.org" }));
+    let added = call(&session, 2, "add", json!({ "name": "cargo", "links": [["rust", 0.8]] }));
+    assert_eq!(added["result"]["neighbors"], json!([{ "name": "rust", "weight": 0.8 }]));
+
+    assert_eq!(call(&session, 3, "relation", json!({ "source": "cargo", "target": "rust" }))["result"], 0.8);
+    assert_eq!(call(&session, 4, "relation", json!({ "source": "rust", "target": "cargo" }))["result"], Value::Null);
+    call(&session, 5, "link", json!({ "source": "rust", "target": "cargo", "weight": 0.3 }));
+    assert_eq!(call(&session, 6, "neighbors", json!({ "name": "rust" }))["result"][0]["name"], "cargo");
+    assert_eq!(call(&session, 7, "describe", json!({ "name": "cargo", "desc": "doc.rust-lang.org/cargo" }))["result"]["desc"], "doc.rust-lang.org/cargo");
+    assert_eq!(call(&session, 8, "search", json!({ "query": "cargo" }))["result"][0]["name"], "cargo");
+}
+
+#[test]
+fn test_names_with_quotes() {
+    let session = session();
+    let added = call(&session, 1, "add", json!({ "name": "it's", "desc": "o'reilly.com", "links": [["rust's book", 0.6]] }));
+    assert_eq!(added["result"]["name"], "it's");
+    assert_eq!(added["result"]["desc"], "o'reilly.com");
+    assert_eq!(call(&session, 2, "relation", json!({ "source": "it's", "target": "rust's book" }))["result"], 0.6);
+    call(&session, 3, "link", json!({ "source": "rust's book", "target": "it's", "weight": 0.2 }));
+    assert_eq!(call(&session, 4, "neighbors", json!({ "name": "rust's book" }))["result"][0]["name"], "it's");
+    assert_eq!(call(&session, 5, "search", json!({ "query": "reilly" }))["result"][0]["name"], "it's");
+}
+
+#[test]
+fn test_errors() {
+    let session = session();
+    let missing = call(&session, 1, "neighbors", json!({ "name": "nothing" }));
+    assert_eq!(missing["error"]["code"], rpc::TAG_NOT_FOUND);
+    assert_eq!(missing["error"]["data"], "nothing");
+    assert_eq!(call(&session, 2, "link", json!({ "source": "a", "target": "b", "weight": 3.0 }))["error"]["code"], rpc::INVALID_WEIGHT);
+    assert_eq!(call(&session, 3, "relation", json!({ "source": "a" }))["error"]["code"], rpc::INVALID_PARAMS);
+    assert_eq!(call(&session, 4, "fly", json!({}))["error"]["code"], rpc::METHOD_NOT_FOUND);
+
+    let garbled: Value = serde_json::from_str(&session.handle_line("{").unwrap()).unwrap();
+    assert_eq!(garbled["error"]["code"], rpc::PARSE_ERROR);
+    // notifications are not answered
+    assert!(session.handle_line(r#"{"jsonrpc": "2.0", "method": "add", "params": {"name": "quiet"}}"#).is_none());
+    assert!(call(&session, 5, "neighbors", json!({ "name": "quiet" }))["error"].is_null());
+}
+
+#[test]
+fn test_null_id_is_answered() {
+    let session = session();
+    let response = session.handle_line(r#"{"jsonrpc": "2.0", "id": null, "method": "add", "params": {"name": "rust"}}"#).unwrap();
+    let response: Value = serde_json::from_str(&response).unwrap();
+    assert!(response.as_object().unwrap().contains_key("id"));
+    assert_eq!(response["id"], Value::Null);
+    assert_eq!(response["result"]["name"], "rust");
+}
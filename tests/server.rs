@@ -0,0 +1,117 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use frictune::{db::crud::Database, server::{Server, MAX_BODY}};
+use serde_json::{json, Value};
+
+/// A server on a fresh database in a temporary folder, removed with
+/// the server.
+struct TestServer {
+    port: u16,
+    _dir: tempfile::TempDir,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::sync_new(dir.path().join("tags.db").to_str().unwrap()).unwrap();
+        let server = Server::bind(0, db).unwrap();
+        let port = server.port();
+        std::thread::spawn(move || server.run(2));
+        TestServer { port, _dir: dir }
+    }
+
+    fn call(&self, method: &str, url: &str, body: Option<Value>) -> (u16, Value) {
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(stream,
+            "{method} {url} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+#[test]
+fn test_tags_and_links() {
+    let server = TestServer::start();
+    assert_eq!(server.call("POST", "/tags", Some(json!({ "name": "rust", "desc": "https://rust-lang.org" }))).0, 201);
+    let (status, body) = server.call("POST", "/tags", Some(json!({ "name": "cargo", "links": [["rust", 0.8]] })));
+    assert_eq!(status, 201);
+    assert_eq!(body["neighbors"], json!([{ "name": "rust", "weight": 0.8 }]));
+    assert_eq!(server.call("POST", "/tags", Some(json!({ "name": "rust" }))).0, 409);
+
+    let (status, body) = server.call("GET", "/tags", None);
+    assert_eq!(status, 200);
+    let mut names: Vec<String> = serde_json::from_value(body).unwrap();
+    names.sort();
+    assert_eq!(names, vec!["cargo", "rust"]);
+
+    let (_, body) = server.call("GET", "/tags/rust", None);
+    assert_eq!(body["desc"], "https://rust-lang.org");
+    assert_eq!(server.call("PUT", "/tags/rust", Some(json!({ "desc": "the language" }))).1["desc"], "the language");
+    assert_eq!(server.call("GET", "/tags/nothing", None).0, 404);
+
+    assert_eq!(server.call("PUT", "/links", Some(json!({ "source": "rust", "target": "cargo", "weight": 0.5 }))).0, 200);
+    assert_eq!(server.call("PUT", "/links", Some(json!({ "source": "rust", "target": "cargo", "weight": 2.0 }))).0, 400);
+    assert_eq!(server.call("GET", "/relation?source=rust&target=cargo", None).1["weight"], 0.5);
+    assert_eq!(server.call("DELETE", "/links?source=rust&target=cargo", None).0, 200);
+    assert_eq!(server.call("GET", "/relation?source=rust&target=cargo", None).1["weight"], Value::Null);
+    assert_eq!(server.call("DELETE", "/links?source=rust&target=cargo", None).0, 404);
+
+    assert_eq!(server.call("DELETE", "/tags/cargo", None).0, 200);
+    assert_eq!(server.call("GET", "/tags/cargo", None).0, 404);
+    assert_eq!(server.call("GET", "/tags/rust", None).1["neighbors"], json!([]));
+}
+
+#[test]
+fn test_names_with_quotes() {
+    let server = TestServer::start();
+    let (status, body) = server.call("POST", "/tags", Some(json!({ "name": "it's", "desc": "o'reilly.com" })));
+    assert_eq!(status, 201);
+    assert_eq!((&body["name"], &body["desc"]), (&json!("it's"), &json!("o'reilly.com")));
+    // not a way around the quoting either
+    let sneaky = "x' OR tag_name = 'it''s";
+    assert_eq!(server.call("POST", "/tags", Some(json!({ "name": sneaky, "links": [["it's", 0.4]] }))).0, 201);
+    assert_eq!(server.call("GET", "/tags/it%27s", None).1["desc"], "o'reilly.com");
+    assert_eq!(server.call("GET", "/relation?source=x%27%20OR%20tag_name%20%3D%20%27it%27%27s&target=it's", None).1["weight"], 0.4);
+
+    assert_eq!(server.call("DELETE", "/tags/it's", None).0, 200);
+    assert_eq!(server.call("GET", "/tags", None).1, json!([sneaky]));
+}
+
+#[test]
+fn test_search_and_graph() {
+    let server = TestServer::start();
+    server.call("POST", "/tags", Some(json!({ "name": "hacker news", "desc": "news.ycombinator.com" })));
+    server.call("POST", "/tags", Some(json!({ "name": "lobsters", "links": [["hacker news", 0.7]] })));
+
+    let (status, body) = server.call("GET", "/tags?q=hacker&limit=5", None);
+    assert_eq!(status, 200);
+    assert_eq!(body[0]["name"], "hacker news");
+    assert_eq!(server.call("GET", "/tags/hacker%20news", None).0, 200);
+
+    let (status, body) = server.call("GET", "/graph", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["nodes"].as_array().unwrap().len(), 2);
+    assert!(body["links"].as_array().unwrap().contains(&json!({ "source": "lobsters", "target": "hacker news", "strength": 0.7 })));
+
+    assert_eq!(server.call("PATCH", "/graph", None).0, 405);
+    assert_eq!(server.call("GET", "/nowhere", None).0, 404);
+}
+
+#[test]
+fn test_body_limit() {
+    let server = TestServer::start();
+    let desc = "x".repeat(MAX_BODY as usize);
+    let (status, body) = server.call("POST", "/tags", Some(json!({ "name": "big", "desc": desc })));
+    assert_eq!(status, 413);
+    assert!(body["error"].is_string());
+    assert_eq!(server.call("GET", "/tags/big", None).0, 404);
+}
@@ -0,0 +1,48 @@
+use frictune::{db::crud::Database, store::{self, blocking}};
+use futures::executor::block_on;
+
+#[test]
+fn test_async_handles() {
+    let store = store::TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    block_on(async {
+        let rust = store.tag("rust");
+        assert!(!rust.exists().await);
+        rust.describe("https://rust-lang.org").await.unwrap();
+        assert!(rust.exists().await);
+        assert_eq!(rust.desc().await.unwrap().as_deref(), Some("https://rust-lang.org"));
+
+        store.tag("cargo").add().await.unwrap();
+        store.tag("crates").add_linked(&[("cargo", 0.5)]).await.unwrap();
+        rust.link("cargo", 0.8).await.unwrap();
+        rust.link("crates", 0.9).await.unwrap();
+        assert_eq!(rust.neighbors().await.unwrap(), vec![("crates".to_string(), 0.9), ("cargo".to_string(), 0.8)]);
+        assert_eq!(rust.weight_to("cargo").await.unwrap(), Some(0.8));
+
+        rust.unlink("cargo").await.unwrap();
+        assert_eq!(rust.weight_to("cargo").await.unwrap(), None);
+        store.tag("crates").remove().await.unwrap();
+        assert_eq!(rust.neighbors().await.unwrap(), vec![]);
+        let mut names = store.tags().await.unwrap();
+        names.sort();
+        assert_eq!(names, vec!["cargo", "rust"]);
+    });
+}
+
+#[test]
+fn test_blocking_handles() {
+    let store = blocking::TagStore::from_database(Database::sync_new("sqlite::memory:").unwrap());
+    let rust = store.tag("rust");
+    assert!(!rust.exists());
+    rust.add_linked(&[("cargo", 0.8), ("cli", 0.3)]).unwrap();
+    assert!(rust.exists() && store.tag("cli").exists());
+    rust.describe("the language").unwrap();
+    assert_eq!(rust.desc().unwrap().as_deref(), Some("the language"));
+    assert_eq!(rust.neighbors().unwrap(), vec![("cargo".to_string(), 0.8), ("cli".to_string(), 0.3)]);
+
+    // linking again sets the weight, and other handles see it
+    rust.link("cli", 0.9).unwrap();
+    assert_eq!(store.tag("rust").weight_to("cli").unwrap(), Some(0.9));
+    rust.unlink("cli").unwrap();
+    assert_eq!(rust.weight_to("cli").unwrap(), None);
+    assert_eq!(store.search("language", 5).unwrap()[0].name, "rust");
+}
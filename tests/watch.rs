@@ -0,0 +1,83 @@
+use std::fs;
+
+use frictune::{
+    db::crud::Database,
+    notes::Settings,
+    store::blocking::TagStore,
+    watch::Watch,
+};
+
+#[test]
+fn test_update_posts() {
+    let folder = tempfile::tempdir().unwrap();
+    let dir = folder.path();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    fs::write(&a, "A\n\n{{ cargo \"build tool\" (rust 0.5) }}").unwrap();
+    fs::write(&b, "B\n\n{{ cargo (rust 0.5) }}").unwrap();
+    let db = Database::sync_new("sqlite::memory:").unwrap();
+    let store = TagStore::from_database(db.clone());
+    let watch = Watch::new(db, dir, Settings::default(), None).unwrap();
+
+    watch.sync_all().unwrap();
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(0.5));
+    assert_eq!(store.tag("cargo").desc().unwrap(), Some("build tool".to_string()));
+
+    fs::write(&a, "A\n\n{{ cargo \"the build tool\" (rust 0.9) }}").unwrap();
+    watch.update(&a);
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(0.9));
+    assert_eq!(store.tag("cargo").desc().unwrap(), Some("the build tool".to_string()));
+
+    // b.txt still has the link
+    fs::write(&a, "A\n\n{{ cargo }}").unwrap();
+    watch.update(&a);
+    assert!(store.tag("cargo").weight_to("rust").unwrap().is_some());
+    fs::remove_file(&b).unwrap();
+    watch.update(&b);
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), None);
+
+    // a malformed block leaves the database as it was
+    fs::write(&a, "A\n\n{{ cargo (rust 0.2) }} {{ rust (").unwrap();
+    watch.update(&a);
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), None);
+}
+
+#[test]
+fn test_post_without_description() {
+    let folder = tempfile::tempdir().unwrap();
+    let dir = folder.path();
+    let post = dir.join("post.txt");
+    fs::write(&post, "Title\n\n{{ cargo (rust 0.5) (crates) }}").unwrap();
+    let db = Database::sync_new("sqlite::memory:").unwrap();
+    let store = TagStore::from_database(db.clone());
+    let watch = Watch::new(db, dir, Settings::default(), None).unwrap();
+
+    watch.update(&post);
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(0.5));
+    assert_eq!(store.tag("cargo").weight_to("crates").unwrap(), Some(1.0));
+    assert_eq!(store.tag("cargo").desc().unwrap(), None);
+}
+
+#[test]
+fn test_restart_takes_over_edits() {
+    let folder = tempfile::tempdir().unwrap();
+    let dir = folder.path().join("posts");
+    fs::create_dir(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    fs::write(&a, "A\n\n{{ cargo (rust 0.5) }}").unwrap();
+    fs::write(&b, "B\n\n{{ serde (json 0.5) }}").unwrap();
+    let path = folder.path().join("tags.db");
+    let db = Database::sync_new(path.to_str().unwrap()).unwrap();
+    Watch::new(db.clone(), &dir, Settings::default(), None).unwrap().sync_all().unwrap();
+
+    // both posts change while no watch is running
+    fs::write(&a, "A\n\n{{ cargo }}").unwrap();
+    fs::remove_file(&b).unwrap();
+    let db = Database::sync_new(path.to_str().unwrap()).unwrap();
+    let store = TagStore::from_database(db.clone());
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), Some(0.5));
+    Watch::new(db, &dir, Settings::default(), None).unwrap().sync_all().unwrap();
+    assert_eq!(store.tag("cargo").weight_to("rust").unwrap(), None);
+    assert_eq!(store.tag("serde").weight_to("json").unwrap(), None);
+}
@@ -50,8 +50,10 @@ function draw() {
         .attr("class", "caption")
         .attr("style", "display: none;")
         .text(function(d, i) { return d.id + "\n" + d.desc; });
+    const color = d3.scaleOrdinal(d3.schemeCategory10);
     node.append("circle")
-        .attr("r", 4);
+        .attr("r", 4)
+        .attr("fill", d => color(d.cluster));
 
 
     d3.selectAll(".node").on("mouseover", function() {